@@ -0,0 +1,227 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+/// How many colors a color map's lookup table has
+pub const LOOKUP_TABLE_SIZE: usize = 256;
+
+/// A color along a color map, at `position` from 0 for the lowest values to 1
+/// for the highest
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+	pub position: f32,
+	/// sRGB
+	pub color: [u8; 3],
+}
+
+const fn stop(position: f32, r: u8, g: u8, b: u8) -> ColorStop {
+	ColorStop { position, color: [r, g, b] }
+}
+
+// Sampled from matplotlib's definitions, which are perceptually uniform to
+// well within what linear interpolation between these loses
+const VIRIDIS: [ColorStop; 9] = [
+	stop(0.0, 68, 1, 84),
+	stop(0.125, 72, 40, 120),
+	stop(0.25, 62, 73, 137),
+	stop(0.375, 49, 104, 142),
+	stop(0.5, 38, 130, 142),
+	stop(0.625, 31, 158, 137),
+	stop(0.75, 53, 183, 121),
+	stop(0.875, 110, 206, 88),
+	stop(1.0, 253, 231, 37),
+];
+
+const CIVIDIS: [ColorStop; 9] = [
+	stop(0.0, 0, 34, 78),
+	stop(0.125, 18, 53, 112),
+	stop(0.25, 59, 73, 108),
+	stop(0.375, 87, 93, 109),
+	stop(0.5, 112, 113, 115),
+	stop(0.625, 138, 135, 121),
+	stop(0.75, 166, 157, 117),
+	stop(0.875, 196, 181, 108),
+	stop(1.0, 254, 232, 56),
+];
+
+// ColorBrewer's 11 class RdBu, reversed so cold is blue
+const RD_BU: [ColorStop; 11] = [
+	stop(0.0, 5, 48, 97),
+	stop(0.1, 33, 102, 172),
+	stop(0.2, 67, 147, 195),
+	stop(0.3, 146, 197, 222),
+	stop(0.4, 209, 229, 240),
+	stop(0.5, 247, 247, 247),
+	stop(0.6, 253, 219, 199),
+	stop(0.7, 244, 165, 130),
+	stop(0.8, 214, 96, 77),
+	stop(0.9, 178, 24, 43),
+	stop(1.0, 103, 0, 31),
+];
+
+/// The colors data values are drawn with, from the lowest to the highest
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMap {
+	#[default]
+	Viridis,
+	/// Like viridis, but also even for those with color vision deficiencies
+	Cividis,
+	/// Diverging from blue through white to red, for anomalies around a
+	/// midpoint
+	RdBu,
+	/// Stops in increasing order of position
+	Custom(Vec<ColorStop>),
+}
+
+impl ColorMap {
+	pub fn stops(&self) -> &[ColorStop] {
+		match self {
+			ColorMap::Viridis => &VIRIDIS,
+			ColorMap::Cividis => &CIVIDIS,
+			ColorMap::RdBu => &RD_BU,
+			ColorMap::Custom(stops) => stops,
+		}
+	}
+
+	pub fn validate(&self) -> Result<(), String> {
+		let stops = self.stops();
+		if stops.len() < 2 {
+			return Err(format!("A color map needs at least 2 stops, not {}", stops.len()));
+		}
+		// NaN positions aren't in order either
+		let out_of_order = |pair: &&[ColorStop]| {
+			pair[1].position.partial_cmp(&pair[0].position) != Some(Ordering::Greater)
+		};
+		if let Some(pair) = stops.windows(2).find(out_of_order) {
+			return Err(format!(
+				"Color stops must increase in position, but {} is followed by {}",
+				pair[0].position, pair[1].position
+			));
+		}
+		Ok(())
+	}
+
+	/// The color at `position`, interpolated between the stops either side.
+	/// Positions past the first or last stop get its color.
+	pub fn sample(&self, position: f32) -> [u8; 3] {
+		let stops = self.stops();
+		let after = stops.iter().position(|stop| stop.position > position);
+		let (before, after) = match after {
+			Some(0) => return stops[0].color,
+			Some(after) => (stops[after - 1], stops[after]),
+			None => return stops[stops.len() - 1].color,
+		};
+
+		let t = (position - before.position) / (after.position - before.position);
+		std::array::from_fn(|channel| {
+			let (from, to) = (before.color[channel] as f32, after.color[channel] as f32);
+			(from + (to - from) * t).round() as u8
+		})
+	}
+
+	/// `LOOKUP_TABLE_SIZE` RGBA texels evenly spaced from 0 to 1, to upload as
+	/// a texture
+	pub fn lookup_table(&self) -> Vec<u8> {
+		(0..LOOKUP_TABLE_SIZE)
+			.flat_map(|texel| {
+				let [r, g, b] = self.sample(texel as f32 / (LOOKUP_TABLE_SIZE - 1) as f32);
+				[r, g, b, 255]
+			})
+			.collect()
+	}
+}
+
+/// How a dataset's values are colored, saved alongside its images as
+/// `color_scale.json`. Datasets without one use viridis.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorScale {
+	pub color_map: ColorMap,
+	/// The value at the middle of the color map, for diverging maps. The range
+	/// is widened to be even either side of it, so equal differences from the
+	/// midpoint get equally strong colors.
+	pub midpoint: Option<f64>,
+}
+
+impl ColorScale {
+	pub const FILE_NAME: &'static str = "color_scale.json";
+
+	/// Where `value` falls along the color map, for a channel of `min..=max`.
+	/// `getDataColor` in the planet shader does the same.
+	pub fn position(&self, value: f64, min: f64, max: f64) -> f32 {
+		let position = match self.midpoint {
+			Some(midpoint) => {
+				let half_range = (midpoint - min).max(max - midpoint);
+				if half_range <= 0.0 {
+					return 0.5;
+				}
+				0.5 + 0.5 * (value - midpoint) / half_range
+			}
+			None if max > min => (value - min) / (max - min),
+			None => 0.0,
+		};
+		position.clamp(0.0, 1.0) as f32
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn samples_between_stops() {
+		let map = ColorMap::Custom(vec![
+			stop(0.0, 0, 0, 0),
+			stop(0.5, 200, 100, 0),
+			stop(1.0, 0, 0, 255),
+		]);
+		assert_eq!(map.sample(0.25), [100, 50, 0]);
+		assert_eq!(map.sample(0.5), [200, 100, 0]);
+		assert_eq!(map.sample(-1.0), [0, 0, 0]);
+		assert_eq!(map.sample(2.0), [0, 0, 255]);
+
+		let table = ColorMap::Viridis.lookup_table();
+		assert_eq!(table.len(), LOOKUP_TABLE_SIZE * 4);
+		assert_eq!(table[..4], [68, 1, 84, 255]);
+		assert_eq!(table[table.len() - 4..], [253, 231, 37, 255]);
+	}
+
+	#[test]
+	fn rejects_unordered_stops() {
+		for map in [ColorMap::Viridis, ColorMap::Cividis, ColorMap::RdBu] {
+			assert_eq!(map.validate(), Ok(()));
+		}
+		assert!(ColorMap::Custom(vec![stop(0.0, 0, 0, 0)]).validate().is_err());
+		let unordered = ColorMap::Custom(vec![stop(0.5, 0, 0, 0), stop(0.5, 1, 1, 1)]);
+		assert!(unordered.validate().is_err());
+	}
+
+	#[test]
+	fn centers_diverging_scales_on_the_midpoint() {
+		let linear = ColorScale::default();
+		assert_eq!(linear.position(15.0, 10.0, 20.0), 0.5);
+		assert_eq!(linear.position(30.0, 10.0, 20.0), 1.0);
+
+		// -2 to 6 is widened to -6 to 6
+		let anomaly = ColorScale { color_map: ColorMap::RdBu, midpoint: Some(0.0) };
+		assert_eq!(anomaly.position(0.0, -2.0, 6.0), 0.5);
+		assert_eq!(anomaly.position(-2.0, -2.0, 6.0), 1.0 / 3.0);
+		assert_eq!(anomaly.position(6.0, -2.0, 6.0), 1.0);
+	}
+
+	#[test]
+	fn reads_color_scales() {
+		let scale: ColorScale =
+			serde_json::from_str(r#"{ "color_map": "rdbu", "midpoint": 0 }"#).unwrap();
+		assert_eq!(scale, ColorScale { color_map: ColorMap::RdBu, midpoint: Some(0.0) });
+
+		let custom = r#"{ "color_map": { "custom": [
+			{ "position": 0, "color": [0, 0, 0] },
+			{ "position": 1, "color": [255, 255, 255] }
+		] } }"#;
+		let scale: ColorScale = serde_json::from_str(custom).unwrap();
+		assert_eq!(scale.color_map.sample(0.5), [128, 128, 128]);
+		assert_eq!(scale.midpoint, None);
+	}
+}
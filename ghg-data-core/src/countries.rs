@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Names for the country identities in the alpha channel of the country map,
+/// saved alongside it as `countries.json`. Identity 0 is the ocean.
+///
+/// Identities are spread evenly over the 8-bit channel, so `max_identity` is
+/// needed to turn a stored byte back into an identity.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CountryIndex {
+	pub max_identity: u32,
+	pub names: BTreeMap<u32, String>,
+}
+
+impl CountryIndex {
+	pub const FILE_NAME: &'static str = "countries.json";
+
+	/// The byte `identity` is stored as
+	pub fn encode(&self, identity: u32) -> u8 { identity_to_byte(identity, self.max_identity) }
+
+	/// The identity stored as `byte`, if any is. Identities are at most 255, so
+	/// each has a byte to itself.
+	pub fn decode(&self, byte: u8) -> Option<u32> {
+		if self.max_identity == 0 {
+			return None;
+		}
+		// The smallest identity that reaches `byte` once truncated
+		let identity = (byte as u32 * self.max_identity).div_ceil(255);
+		(identity <= self.max_identity && self.encode(identity) == byte).then_some(identity)
+	}
+
+	/// The name of the country stored as `byte`, or `None` for the ocean
+	pub fn name_of_byte(&self, byte: u8) -> Option<&str> {
+		self.decode(byte).and_then(|identity| self.names.get(&identity)).map(String::as_str)
+	}
+}
+
+/// Spreads identities `0..=max_identity` evenly over `0..=255`
+pub fn identity_to_byte(identity: u32, max_identity: u32) -> u8 {
+	assert!(max_identity < 256, "Identities must fit in a byte: {max_identity}");
+	if max_identity == 0 {
+		return 0;
+	}
+	(255 * identity / max_identity) as u8
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn index(max_identity: u32) -> CountryIndex {
+		CountryIndex { max_identity, names: BTreeMap::new() }
+	}
+
+	#[test]
+	fn decodes_every_identity() {
+		for max_identity in [1, 7, 177, 255] {
+			let index = index(max_identity);
+			for identity in 0..=max_identity {
+				assert_eq!(index.decode(index.encode(identity)), Some(identity));
+			}
+		}
+	}
+
+	#[test]
+	fn ignores_unused_bytes() {
+		let index = index(7);
+		assert_eq!(index.encode(1), 36);
+		assert_eq!(index.decode(35), None);
+		assert_eq!(index.decode(37), None);
+	}
+
+	#[test]
+	fn looks_up_names() {
+		let mut index = index(2);
+		index.names.insert(1, "Iceland".to_owned());
+
+		assert_eq!(index.name_of_byte(0), None);
+		assert_eq!(index.name_of_byte(127), Some("Iceland"));
+		assert_eq!(index.name_of_byte(255), None);
+	}
+}
@@ -3,4 +3,7 @@
 
 extern crate nalgebra_glm as nglm;
 
+pub mod color_map;
+pub mod countries;
 pub mod metadata;
+pub mod tiles;
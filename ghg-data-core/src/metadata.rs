@@ -6,9 +6,49 @@ pub struct ChannelMetadata {
 	pub max: f64,
 }
 
+/// Data textures store each channel as a byte: its position within
+/// `min..=max`, scaled to `0..=255` and truncated. This is the one place that
+/// mapping is defined; `channelValues` in the shaders decodes it the same way.
+impl ChannelMetadata {
+	/// Values outside `min..=max` are clamped. A channel with an empty range
+	/// decodes to `min` whatever it stores.
+	pub fn encode(&self, value: f64) -> u8 {
+		let portion = (value - self.min) / (self.max - self.min);
+		// `as` saturates, and turns NaN into 0
+		(255.0 * portion) as u8
+	}
+
+	/// The smallest value that encodes to `byte`
+	pub fn decode(&self, byte: u8) -> f64 { self.min + byte as f64 * self.step() }
+
+	/// The difference in value between consecutive bytes
+	pub fn step(&self) -> f64 { (self.max - self.min) / 255.0 }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Metadata(pub Vec<ChannelMetadata>);
 
+impl Metadata {
+	/// The value of each channel of a texel, from its bytes
+	pub fn decode_texel(&self, texel: &[u8]) -> Result<Vec<f64>, String> {
+		self.check_channel_count(texel.len())?;
+		Ok(self.0.iter().zip(texel).map(|(channel, &byte)| channel.decode(byte)).collect())
+	}
+
+	/// The bytes of a texel, from the value of each channel
+	pub fn encode_texel(&self, values: &[f64]) -> Result<Vec<u8>, String> {
+		self.check_channel_count(values.len())?;
+		Ok(self.0.iter().zip(values).map(|(channel, &value)| channel.encode(value)).collect())
+	}
+
+	fn check_channel_count(&self, count: usize) -> Result<(), String> {
+		if count != self.0.len() {
+			return Err(format!("Expected {} channels, got {count}", self.0.len()));
+		}
+		Ok(())
+	}
+}
+
 impl FromIterator<ChannelMetadata> for Metadata {
 	fn from_iter<T: IntoIterator<Item = ChannelMetadata>>(iter: T) -> Self {
 		Self(<Vec<ChannelMetadata> as FromIterator<ChannelMetadata>>::from_iter(iter))
@@ -52,3 +92,48 @@ impl TryInto<(nglm::Vec4, nglm::Vec4)> for Metadata {
 		))
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn metadata() -> Metadata {
+		Metadata(vec![
+			ChannelMetadata { min: 200.0, max: 327.5 },
+			ChannelMetadata { min: -1.0, max: 1.0 },
+		])
+	}
+
+	#[test]
+	fn round_trips_within_a_step() {
+		let metadata = metadata();
+		for value in [200.0, 231.7, 300.01, 327.5] {
+			let texel = metadata.encode_texel(&[value, 0.0]).unwrap();
+			let decoded = metadata.decode_texel(&texel).unwrap();
+			assert!(decoded[0] <= value && value - decoded[0] < metadata.0[0].step());
+		}
+
+		assert_eq!(metadata.decode_texel(&[0, 255]).unwrap(), [200.0, 1.0]);
+	}
+
+	#[test]
+	fn clamps_out_of_range_values() {
+		let channel = ChannelMetadata { min: 0.0, max: 10.0 };
+		assert_eq!(channel.encode(-5.0), 0);
+		assert_eq!(channel.encode(20.0), 255);
+		assert_eq!(channel.encode(f64::NAN), 0);
+	}
+
+	#[test]
+	fn constant_channels_decode_to_their_value() {
+		let channel = ChannelMetadata { min: 273.0, max: 273.0 };
+		assert_eq!(channel.encode(273.0), 0);
+		assert_eq!(channel.decode(0), 273.0);
+	}
+
+	#[test]
+	fn rejects_mismatched_channel_counts() {
+		assert!(metadata().decode_texel(&[0, 0, 0]).is_err());
+		assert!(metadata().encode_texel(&[0.0]).is_err());
+	}
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Describes a pyramid of equirectangular tiles, saved alongside it as
+/// `tiles.json`. Tiles are at `{level}/{x}/{y}.png`, where level 0 is the
+/// highest detail as with mipmaps, and `(0, 0)` is the north-west corner. Tiles
+/// in the last column and row are smaller if the level doesn't divide evenly.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileSet {
+	pub tile_size: u32,
+	pub channels: u8,
+	pub levels: Vec<TileLevel>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileLevel {
+	pub level: usize,
+	pub width: u32,
+	pub height: u32,
+	pub columns: u32,
+	pub rows: u32,
+}
+
+impl TileSet {
+	pub const FILE_NAME: &'static str = "tiles.json";
+
+	pub fn tile_path(level: usize, x: u32, y: u32) -> String { format!("{level}/{x}/{y}.png") }
+
+	pub fn level(&self, level: usize) -> Option<&TileLevel> {
+		self.levels.iter().find(|tile_level| tile_level.level == level)
+	}
+}
+
+impl TileLevel {
+	pub fn new(level: usize, width: u32, height: u32, tile_size: u32) -> Self {
+		Self {
+			level,
+			width,
+			height,
+			columns: width.div_ceil(tile_size),
+			rows: height.div_ceil(tile_size),
+		}
+	}
+
+	/// The `(x, y, width, height)` of a tile in this level's pixels
+	pub fn tile_bounds(&self, x: u32, y: u32, tile_size: u32) -> (u32, u32, u32, u32) {
+		let (left, top) = (x * tile_size, y * tile_size);
+		(left, top, tile_size.min(self.width - left), tile_size.min(self.height - top))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn partial_tiles_at_the_edges() {
+		let level = TileLevel::new(0, 1200, 600, 512);
+		assert_eq!((level.columns, level.rows), (3, 2));
+		assert_eq!(level.tile_bounds(0, 0, 512), (0, 0, 512, 512));
+		assert_eq!(level.tile_bounds(2, 1, 512), (1024, 512, 176, 88));
+	}
+
+	#[test]
+	fn descriptor_round_trip() {
+		let tile_set = TileSet {
+			tile_size: 512,
+			channels: 2,
+			levels: vec![TileLevel::new(0, 1024, 512, 512), TileLevel::new(1, 512, 256, 512)],
+		};
+		let json = serde_json::to_string(&tile_set).unwrap();
+		assert_eq!(serde_json::from_str::<TileSet>(&json).unwrap(), tile_set);
+		assert_eq!(tile_set.level(1), Some(&tile_set.levels[1]));
+		assert_eq!(TileSet::tile_path(1, 0, 0), "1/0/0.png");
+	}
+}
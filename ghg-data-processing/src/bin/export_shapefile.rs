@@ -1,21 +1,33 @@
 use std::env;
 use std::path::Path;
 
-use ghg_data_processing::export::geometry_map::{
-	GeometryUniverse, IntoGeometryMap, ToGeometryUniverse,
+use ghg_data_core::countries::CountryIndex;
+use ghg_data_core::tiles::{TileLevel, TileSet};
+use ghg_data_processing::export::geometry_map::{GeometryUniverse, ToGeometryUniverse};
+use ghg_data_processing::export::geometry_tiles::IntoTiledGeometryMap;
+use ghg_data_processing::export::pyramid::{downsample, save_pyramid_from_level, Downsample};
+use ghg_data_processing::export::tiling::{
+	save_tile_block, save_tile_set, save_tiled_pyramid_from_level, TILE_SIZE,
 };
-use ghg_data_processing::export::image::ToImage;
 use ghg_data_processing::file_type::{DataFile, ShapefileMetadata, Shp};
 use ghg_data_processing::read_data::find_data_files;
+use image::imageops::replace;
+use image::{ImageBuffer, LumaA};
+
+/// Borders are thin, so they're kept wherever any pixel had one. Identities
+/// can't be blended, so the most common one wins.
+const DOWNSAMPLING: [Downsample; 2] = [Downsample::Max, Downsample::Mode];
+
+/// Level 0 is rasterised in blocks of this many tiles across and down, so
+/// memory use doesn't grow with the map's size
+const BLOCK_TILES: usize = 8;
 
 fn main() -> std::io::Result<()> {
 	let args: Vec<String> = env::args().collect();
 	assert_eq!(args.len(), 2);
 
-	let mipmap_level = 0;
-	let output_root = format!("ghg/www/images/countries/{mipmap_level}");
-	let output_root = Path::new(output_root.as_str());
-	assert!(output_root.exists());
+	let output_root = Path::new("ghg/www/images/countries");
+	assert!(output_root.exists(), "Run from the project root");
 
 	let data_source = Path::new(&args[1]);
 
@@ -30,24 +42,63 @@ fn main() -> std::io::Result<()> {
 		println!("  - {file:?}");
 	}
 
-	let metadata = match mipmap_level {
-		0 => ShapefileMetadata { width: 21600, height: 10800 },
-		1 => ShapefileMetadata { width: 10800, height: 5400 },
-		2 => ShapefileMetadata { width: 5400, height: 2700 },
-		3 => ShapefileMetadata { width: 2700, height: 1350 },
-		_ => panic!("Unsupported mipmap level {mipmap_level}"),
-	};
+	let metadata = ShapefileMetadata { width: 21600, height: 10800 };
 
 	for file in &data_files {
 		let geometry_universe: GeometryUniverse = Shp::<f64>::open(file, metadata)
 			.expect(format!("Failed to read file {:?}", file.file_name().unwrap()).as_str())
 			.to_geometry_universe();
 
-		let geometry_map = geometry_universe.into_geometry_map(metadata.width, metadata.height);
-		let image = geometry_map.to_image();
+		let country_index = serde_json::to_string(&geometry_universe.country_index())
+			.expect("Failed to serialize country index");
+		std::fs::write(output_root.join(CountryIndex::FILE_NAME), country_index)?;
+
+		let geometry_map =
+			geometry_universe.into_tiled_geometry_map(metadata.width, metadata.height);
+
+		// Level 0 is too big to hold at once, so it's rasterised in blocks, and
+		// level 1 is built up from them as they're written
+		let mut level_1 = ImageBuffer::<LumaA<u8>, Vec<u8>>::new(
+			(metadata.width / 2) as u32,
+			(metadata.height / 2) as u32,
+		);
+
+		let block_size = BLOCK_TILES * TILE_SIZE as usize;
+		for block_y in (0..metadata.height).step_by(block_size) {
+			for block_x in (0..metadata.width).step_by(block_size) {
+				let (column, row) = (block_x / block_size, block_y / block_size);
+				println!("  Rasterising block {column}.{row}");
+				let width = block_size.min(metadata.width - block_x);
+				let height = block_size.min(metadata.height - block_y);
+				let block = geometry_map.tile_image(block_x, block_y, width, height);
+
+				let first_tile = ((column * BLOCK_TILES) as u32, (row * BLOCK_TILES) as u32);
+				save_tile_block(&block, output_root, 0, first_tile, TILE_SIZE)
+					.expect("Failed to save image data");
+
+				replace(
+					&mut level_1,
+					&downsample(&block, &DOWNSAMPLING),
+					(block_x / 2) as i64,
+					(block_y / 2) as i64,
+				);
+			}
+		}
+
+		save_pyramid_from_level(1, &level_1, output_root, "full", &DOWNSAMPLING)
+			.expect("Failed to save image data");
+
+		let level_0 = TileLevel::new(0, metadata.width as u32, metadata.height as u32, TILE_SIZE);
+		let lower_levels =
+			save_tiled_pyramid_from_level(1, &level_1, output_root, &DOWNSAMPLING, TILE_SIZE)
+				.expect("Failed to save image data");
 
-		let output_name = output_root.join("original.png");
-		image.save(output_name).expect("Failed to save image data");
+		let tile_set = TileSet {
+			tile_size: TILE_SIZE,
+			channels: 2,
+			levels: [vec![level_0], lower_levels].concat(),
+		};
+		save_tile_set(&tile_set, output_root)?;
 	}
 
 	Ok(())
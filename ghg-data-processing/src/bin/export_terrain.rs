@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use ghg_data_processing::export::pyramid::{save_pyramid, Downsample};
+use ghg_data_processing::export::tiling::save_tiled_pyramid;
+use image::io::Reader;
+use image::{DynamicImage, EncodableLayout, GenericImageView, GrayImage};
+
+/// Merges the topography and bathymetry sources into one heightmap, where
+/// values below 128 are below sea level, then saves it as a mip pyramid and
+/// as tiles.
+/// The sources should be saved into `ghg/www/images/earth_height/0` as
+/// `topography-original.png` and `bathymetry-original.png`.
+fn main() {
+	let output_root = Path::new("ghg/www/images/earth_height");
+	assert!(output_root.exists(), "Run from the project root");
+
+	let terrain = create_original_terrain(&output_root.join("0"));
+	save_pyramid(&terrain, output_root, "full", &[Downsample::Average])
+		.expect("Failed to save terrain pyramid!");
+	save_tiled_pyramid(&terrain, output_root, &[Downsample::Average])
+		.expect("Failed to save terrain tiles!");
+}
+
+fn read_image(path: &Path) -> DynamicImage {
+	let mut reader = Reader::open(path)
+		.expect("Failed to open image.")
+		.with_guessed_format()
+		.expect("Failed to guess format");
+	reader.no_limits();
+
+	reader.decode().expect("Failed to decode image.")
+}
+
+fn create_original_terrain(lod_root: &Path) -> GrayImage {
+	let topo_path = lod_root.join("topography-original.png");
+	let bathy_path = lod_root.join("bathymetry-original.png");
+
+	const U8_HALF: u8 = 128u8;
+
+	let output_dimensions: (u32, u32);
+	let mut output_buffer: Vec<u8>;
+
+	{
+		// BATHY
+		let bathy_image = read_image(&bathy_path);
+		println!("Bathymetry color type: {:?}", bathy_image.color());
+		let bathy_gray = bathy_image.to_luma8();
+
+		let (bathy_width, bathy_height) = bathy_image.dimensions();
+
+		output_dimensions = (bathy_width, bathy_height);
+		output_buffer = Vec::with_capacity(bathy_width as usize * bathy_height as usize);
+
+		bathy_gray.as_bytes().iter().for_each(|&pixel| {
+			let rescaled = pixel / 2;
+			output_buffer.push(rescaled);
+		});
+	}
+
+	{
+		// TOPO
+		let topo_image = read_image(&topo_path);
+		let topo_gray = topo_image.as_luma8().expect("Failed to read topography as Luma8");
+
+		let (topo_width, topo_height) = topo_image.dimensions();
+
+		if output_dimensions.0 != topo_width || output_dimensions.1 != topo_height {
+			let output_width = output_dimensions.0;
+			let output_height = output_dimensions.1;
+			panic!("Mismatched input sizes: Topography is {output_width}x{output_height}, Bathymetry is {topo_width}x{topo_height}.");
+		}
+
+		topo_gray.as_bytes().iter().enumerate().for_each(|(index, &pixel)| {
+			if pixel != 0 {
+				let rescaled = pixel / 2 + U8_HALF;
+				output_buffer[index] = rescaled;
+			}
+		});
+	}
+
+	GrayImage::from_raw(output_dimensions.0, output_dimensions.1, output_buffer)
+		.expect("Failed to create merged image!")
+}
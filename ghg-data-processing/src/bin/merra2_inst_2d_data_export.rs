@@ -3,14 +3,20 @@
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::{env, thread};
 
+use ghg_data_core::color_map::ColorScale;
 use ghg_data_processing::export::data_2d_statistics::{Data2dStatistics, DataType, ToMetadata};
 use ghg_data_processing::export::image::ToImage;
 use ghg_data_processing::file_type::cdf::{CdfMetadata, Nc, Nc4};
 use ghg_data_processing::file_type::{DataFile, ToStatistics};
+use ghg_data_processing::incremental::{BuildReport, Job, JobOutcome};
+use ghg_data_processing::progress::Progress;
 use ghg_data_processing::read_data::find_data_files;
-use ghg_data_processing::save_result::save_channels;
+use ghg_data_processing::save_result::{save_channels, save_color_scale};
 use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::Serialize;
 
 /// If you have a problem finding HDF5 or netCDF, make sure to run this with
 /// these flags:     --all-features --features hdf5-sys/static,netcdf/static
@@ -31,62 +37,151 @@ use rayon::prelude::*;
 // Instantaneous Two-Dimensional Collections: instM_2d_asm_Nx (M2IMNXASM):
 // Single-Level Diagnostics
 
+/// Bump when a change here or in the exporters changes the outputs, so they're
+/// all rebuilt
+const EXPORT_VERSION: u32 = 1;
+
+const MONTH_STRIDE_LENGTH: i32 = 4;
+
+/// Everything other than the input files that affects a stride's output
+#[derive(Serialize)]
+struct StrideParameters<'a> {
+	version: u32,
+	variables: &'a [String],
+	width_dimension: usize,
+	height_dimension: usize,
+}
+
+/// One output image: the months of a year it covers, and their files
+struct Stride {
+	output_stem: String,
+	files: Vec<PathBuf>,
+}
+
+/// `--jobs <count>` sets how many strides are exported at once, which defaults
+/// to one per core
+fn parse_worker_count() -> usize {
+	let args: Vec<String> = env::args().skip(1).collect();
+	match args.as_slice() {
+		[] => thread::available_parallelism().map_or(1, |count| count.get()),
+		[flag, count] if flag == "--jobs" => {
+			count.parse().unwrap_or_else(|_| panic!("Invalid worker count: {count}"))
+		}
+		_ => panic!("Usage: merra2_inst_2d_data_export [--jobs <count>]"),
+	}
+}
+
 fn main() -> std::io::Result<()> {
+	let workers = parse_worker_count();
+
 	let output_root = Path::new("ghg/www/images/earth_temp");
 	assert!(output_root.exists());
 
 	let data_source = Path::new("raw_data/merra2_1980_2021");
 	assert!(data_source.exists());
 
+	// Temperatures are colored with the viewer's default, viridis, over each
+	// month's range
+	save_color_scale(output_root, &ColorScale::default())?;
+
 	let data_paths = find_data_files(data_source, &[Nc4::<f64>::extension()]);
 
 	let metadata = CdfMetadata { width_dimension: 2, height_dimension: 1 };
 	let variables = ["T2M".to_owned()];
-
+	let parameters = StrideParameters {
+		version: EXPORT_VERSION,
+		variables: &variables,
+		width_dimension: metadata.width_dimension,
+		height_dimension: metadata.height_dimension,
+	};
+
+	let mut strides = Vec::new();
 	for year in 1980..=2021 {
-		println!(">>> Starting year {year} <<<");
 		let year_files = paths_from_year(&data_paths, year);
-		println!("  Files: {year_files:?}");
-
-		(0..3).into_iter().for_each(|month_stride| {
-			produce_stride_image(output_root, year, &year_files, metadata, &variables, month_stride)
-		});
+		for month_stride in 0..3 {
+			let first_month = month_stride * MONTH_STRIDE_LENGTH;
+			let files = (first_month..first_month + MONTH_STRIDE_LENGTH)
+				.map(|month| {
+					let mut files = paths_from_month(&year_files, month);
+					assert_eq!(files.len(), 1, "Expected one file for {year}, month {}", month + 1);
+					files.remove(0)
+				})
+				.collect();
+			let output_stem = format!(
+				"{:0>4}.{:0>2}.{:0>2}",
+				year,
+				first_month + 1,
+				first_month + MONTH_STRIDE_LENGTH
+			);
+			strides.push(Stride { output_stem, files });
+		}
 	}
 
+	println!("Exporting {} strides with {workers} workers", strides.len());
+	let progress = Progress::new(strides.len());
+	let pool = ThreadPoolBuilder::new()
+		.num_threads(workers)
+		.build()
+		.expect("Failed to start export workers");
+	let outcomes = pool.install(|| {
+		strides
+			.par_iter()
+			.map(|stride| -> std::io::Result<_> {
+				let outcome =
+					export_stride(output_root, stride, metadata, &variables, &parameters)?;
+				progress.finish(&stride.output_stem, outcome);
+				Ok((stride.output_stem.clone(), outcome))
+			})
+			.collect::<std::io::Result<Vec<_>>>()
+	})?;
+
+	let mut report = BuildReport::default();
+	for (output_stem, outcome) in outcomes {
+		report.add(output_stem, outcome);
+	}
+	println!("{report}");
 	Ok(())
 }
 
-fn produce_stride_image(
+fn export_stride(
 	output_root: &Path,
-	year: i32,
-	year_files: &Vec<PathBuf>,
+	stride: &Stride,
+	metadata: CdfMetadata,
+	variables: &[String],
+	parameters: &StrideParameters,
+) -> std::io::Result<JobOutcome> {
+	let output_name = output_root.join(format!("{}.png", stride.output_stem));
+	let job = Job::new(
+		output_name.with_extension("build"),
+		vec![
+			output_name.with_extension("metadata"),
+			output_root.join("0").join(format!("{}.png", stride.output_stem)),
+		],
+		&stride.files,
+		parameters,
+	)?;
+
+	job.run(|| produce_stride_image(&output_name, &stride.files, metadata, variables))
+}
+
+/// Opens its own handle for each file, as netCDF handles can't be shared
+/// between workers
+fn produce_stride_image(
+	output_name: &Path,
+	stride_files: &[PathBuf],
 	metadata: CdfMetadata,
 	variables: &[String],
-	month_stride: i32,
 ) {
 	let mut stride_data: Vec<Data2dStatistics<f64>> = Vec::new();
 
-	const MONTH_STRIDE_LENGTH: i32 = 4;
-	for month_cursor in 0..MONTH_STRIDE_LENGTH {
-		let month = month_stride * MONTH_STRIDE_LENGTH + month_cursor;
-		println!("  Month: {}", month + 1);
-
-		let files = paths_from_month(year_files, month);
-		assert_eq!(files.len(), 1);
-
-		let mut data = Nc4::<f64>::open(&files[0], metadata)
-			.expect(format!("Failed to read file {:?}", files[0].file_name().unwrap()).as_str())
+	for file in stride_files {
+		let mut data = Nc4::<f64>::open(file, metadata)
+			.expect(format!("Failed to read file {:?}", file.file_name().unwrap()).as_str())
 			.read_variables(&variables);
 		assert_eq!(data.len(), 1);
 
 		stride_data.push(data.remove(0));
 	}
-	let output_name = output_root.join(format!(
-		"{:0>4}.{:0>2}.{:0>2}.png",
-		year,
-		month_stride * MONTH_STRIDE_LENGTH + 1,
-		month_stride * MONTH_STRIDE_LENGTH + MONTH_STRIDE_LENGTH
-	));
 	save_channels!(output_name, to_array::<Data2dStatistics<f64>, 4>(stride_data.clone()));
 }
 
@@ -0,0 +1,41 @@
+use std::env;
+use std::path::Path;
+
+use ghg_data_processing::export::geometry_map::{GeometryUniverse, ToGeoJson, ToGeometryUniverse};
+use ghg_data_processing::file_type::{DataFile, ShapefileMetadata, Shp};
+use ghg_data_processing::read_data::find_data_files;
+
+/// Converts every shapefile in a directory into a `.geojson` file in the output
+/// directory, keeping each shape's record as feature properties and its
+/// `GeometryUniverse` identity as the feature id. Useful for inspecting our
+/// regions in standard GIS tools.
+fn main() -> std::io::Result<()> {
+	let args: Vec<String> = env::args().collect();
+	assert_eq!(args.len(), 3);
+
+	let data_source = Path::new(&args[1]);
+	let output_root = Path::new(&args[2]);
+	std::fs::create_dir_all(output_root)?;
+
+	let data_files = find_data_files(data_source, &[Shp::<f64>::extension()]);
+	if data_files.is_empty() {
+		println!("No data files found in path {:?}", data_source);
+		return Ok(());
+	}
+
+	// Only used for rasterising, which this doesn't do
+	let metadata = ShapefileMetadata { width: 0, height: 0 };
+
+	for file in &data_files {
+		let geometry_universe: GeometryUniverse = Shp::<f64>::open(file, metadata)
+			.unwrap_or_else(|e| panic!("Failed to read file {:?}: {e}", file.file_name().unwrap()))
+			.to_geometry_universe();
+
+		let output_name = output_root.join(file.file_name().unwrap()).with_extension("geojson");
+		std::fs::write(&output_name, geometry_universe.to_geojson().to_string())?;
+
+		println!("Saved {} features: {:?}", geometry_universe.len(), output_name);
+	}
+
+	Ok(())
+}
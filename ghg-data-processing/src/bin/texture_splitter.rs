@@ -0,0 +1,60 @@
+use std::env;
+use std::path::Path;
+
+use ghg_data_processing::export::pyramid::Downsample;
+use ghg_data_processing::export::tiling::save_tiled_pyramid;
+use image::io::Reader;
+use image::{DynamicImage, ImageBuffer, Pixel, PixelWithColorType};
+
+/// Splits `{image root}/0/full.png` into a pyramid of tiles, for images that
+/// don't come out of one of our exporters (e.g. `earth_color`). Takes one
+/// downsampling method per channel (`average`, `mode` or `max`), or one for
+/// all of them. Expects to be run with CWD in the project root, e.g.:
+///     texture_splitter ghg/www/images/earth_color average
+fn main() {
+	let args: Vec<String> = env::args().collect();
+	assert!(args.len() >= 3, "Usage: texture_splitter <image root> <downsampling>...");
+
+	let image_root = Path::new(&args[1]);
+	let downsampling: Vec<Downsample> = args[2..].iter().map(|arg| parse_downsample(arg)).collect();
+
+	let original = read_image(&image_root.join("0/full.png"));
+	println!("Loaded image: {} x {}", original.width(), original.height());
+
+	match original {
+		DynamicImage::ImageLuma8(image) => split(&image, image_root, &downsampling),
+		DynamicImage::ImageLumaA8(image) => split(&image, image_root, &downsampling),
+		DynamicImage::ImageRgb8(image) => split(&image, image_root, &downsampling),
+		DynamicImage::ImageRgba8(image) => split(&image, image_root, &downsampling),
+		other => panic!("Unsupported color type: {:?}", other.color()),
+	}
+}
+
+fn parse_downsample(arg: &str) -> Downsample {
+	match arg {
+		"average" => Downsample::Average,
+		"mode" => Downsample::Mode,
+		"max" => Downsample::Max,
+		_ => panic!("Unknown downsampling method {arg:?}, expected average, mode or max"),
+	}
+}
+
+fn read_image(path: &Path) -> DynamicImage {
+	let mut reader = Reader::open(path)
+		.expect("Failed to open image.")
+		.with_guessed_format()
+		.expect("Failed to guess format");
+	reader.no_limits();
+
+	reader.decode().expect("Failed to decode image.")
+}
+
+fn split<P: Pixel<Subpixel = u8> + PixelWithColorType + Sync + 'static>(
+	image: &ImageBuffer<P, Vec<u8>>,
+	image_root: &Path,
+	downsampling: &[Downsample],
+) {
+	let tile_set =
+		save_tiled_pyramid(image, image_root, downsampling).expect("Failed to save tiles!");
+	println!("Saved {} levels of tiles", tile_set.levels.len());
+}
@@ -0,0 +1,82 @@
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+
+use ghg_data_processing::export::data_2d_statistics::Data2dStatistics;
+use ghg_data_processing::validate::{validate, Output};
+
+const USAGE: &str = "Usage: validate <output dir>
+
+Checks each exported image in <output dir> against its metadata. If this was built with the read_netcdf
+feature, and an image has a .build record, it's also compared with the data it was exported from.";
+
+/// Reads the channels an output was exported from, with the inputs and
+/// parameters in its `.build` record
+#[cfg(feature = "read_netcdf")]
+fn read_source(root: &Path, stem: &str) -> Option<Vec<Data2dStatistics<f64>>> {
+	use ghg_data_processing::file_type::cdf::{CdfMetadata, Nc4};
+	use ghg_data_processing::file_type::{DataFile, ToStatistics};
+	use ghg_data_processing::incremental::BuildRecord;
+
+	let record = BuildRecord::load(&root.join(format!("{stem}.build"))).ok()??;
+	let variables: Vec<String> =
+		serde_json::from_value(record.parameters.get("variables")?.clone()).ok()?;
+	let dimension = |name: &str| -> Option<usize> {
+		record.parameters.get(name)?.as_u64().map(|dimension| dimension as usize)
+	};
+	let metadata = CdfMetadata {
+		width_dimension: dimension("width_dimension")?,
+		height_dimension: dimension("height_dimension")?,
+	};
+
+	let mut channels = Vec::new();
+	for input in &record.inputs {
+		let file = Nc4::<f64>::open(&input.path, metadata)
+			.unwrap_or_else(|e| panic!("Failed to read file {:?}: {e}", input.path));
+		channels.extend(file.read_variables(&variables));
+	}
+	Some(channels)
+}
+
+#[cfg(not(feature = "read_netcdf"))]
+fn read_source(_root: &Path, _stem: &str) -> Option<Vec<Data2dStatistics<f64>>> { None }
+
+fn main() -> ExitCode {
+	let args: Vec<String> = env::args().collect();
+	assert_eq!(args.len(), 2, "{USAGE}");
+	let root = Path::new(&args[1]);
+
+	let mut stems: Vec<String> = root
+		.read_dir()
+		.unwrap_or_else(|e| panic!("Failed to read {root:?}: {e}"))
+		.filter_map(|entry| {
+			let path = entry.ok()?.path();
+			let is_metadata = path.extension()? == "metadata";
+			is_metadata.then(|| path.file_stem()?.to_str().map(str::to_owned))?
+		})
+		.collect();
+	stems.sort();
+
+	let mut failed = 0;
+	for stem in &stems {
+		let validation = match Output::load(root, stem) {
+			Ok(output) => validate(&output, read_source(root, stem).as_deref()),
+			Err(e) => {
+				println!("{stem}: FAILED\n  {e}");
+				failed += 1;
+				continue;
+			}
+		};
+		println!("{validation}");
+		if !validation.is_ok() {
+			failed += 1;
+		}
+	}
+
+	println!("{} of {} outputs passed", stems.len() - failed, stems.len());
+	if failed > 0 {
+		ExitCode::FAILURE
+	} else {
+		ExitCode::SUCCESS
+	}
+}
@@ -1,30 +1,87 @@
+use std::env;
 use std::error::Error;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
-use std::{env, fs, io};
+use std::path::{Path, PathBuf};
 
-use image::EncodableLayout;
+use ghg_data_processing::download::{
+	BoundingBox, DownloadOutcome, DownloadRequest, Downloader, Grid, Netrc, Subset,
+};
 use reqwest::Url;
-use {regex, reqwest, scraper};
+
+const USAGE: &str = "Usage: web_downloader <url> <link container class> <link regex> <out dir> \
+	[--netrc <path>] [--variables <name,...>] [--bbox <west,south,east,north>] [--time-steps <count>]
+
+Credentials for any host that's redirected to come from --netrc, or else $NETRC or ~/.netrc.
+--variables fetches only those variables through OPeNDAP instead of whole granules, optionally
+within --bbox, on the MERRA-2 grid with --time-steps per granule (default 1).";
+
+struct Options {
+	netrc: Option<PathBuf>,
+	subset: Option<Subset>,
+	time_steps: usize,
+}
+
+fn parse_options(args: &[String]) -> Options {
+	let mut options = Options { netrc: None, subset: None, time_steps: 1 };
+	let mut variables = None;
+	let mut bounding_box = None;
+
+	let mut args = args.iter();
+	while let Some(flag) = args.next() {
+		let value = args.next().unwrap_or_else(|| panic!("Missing value for {flag}\n{USAGE}"));
+		match flag.as_str() {
+			"--netrc" => options.netrc = Some(PathBuf::from(value)),
+			"--variables" => variables = Some(value.split(',').map(str::to_owned).collect()),
+			"--bbox" => {
+				let edges: Vec<f64> = value
+					.split(',')
+					.map(|edge| edge.parse().unwrap_or_else(|_| panic!("Invalid bbox: {value}")))
+					.collect();
+				let [west, south, east, north] = edges[..] else {
+					panic!("Expected 4 bbox edges, got {value}");
+				};
+				bounding_box = Some(BoundingBox { west, south, east, north });
+			}
+			"--time-steps" => {
+				options.time_steps =
+					value.parse().unwrap_or_else(|_| panic!("Invalid time steps: {value}"))
+			}
+			_ => panic!("Unknown option {flag}\n{USAGE}"),
+		}
+	}
+
+	assert!(variables.is_some() || bounding_box.is_none(), "--bbox needs --variables");
+	options.subset = variables.map(|variables| Subset { variables, bounding_box });
+	options
+}
+
+fn load_netrc(path: Option<PathBuf>) -> Result<Netrc, Box<dyn Error>> {
+	match path {
+		Some(path) => Ok(Netrc::load(&path)?),
+		None => match Netrc::default_path().filter(|path| path.exists()) {
+			Some(path) => Ok(Netrc::load(&path)?),
+			None => Ok(Netrc::default()),
+		},
+	}
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
 	let args: Vec<String> = env::args().collect();
-	assert_eq!(args.len(), 5);
+	assert!(args.len() >= 5, "{USAGE}");
+	let options = parse_options(&args[5..]);
 
 	let url: Url =
-		Url::parse(args[1].as_str()).expect(format!("Invalid url: {:?}", args[1]).as_str());
+		Url::parse(args[1].as_str()).unwrap_or_else(|_| panic!("Invalid url: {:?}", args[1]));
 	let link_container_class = &args[2];
 	let regex_arg = &args[3];
 	let out_location = Path::new(&args[4]);
 
 	let a_regex = regex::Regex::new(regex_arg.as_str())
-		.expect(format!("Invalid regex specification: {}", regex_arg).as_str());
+		.unwrap_or_else(|_| panic!("Invalid regex specification: {}", regex_arg));
 
 	let page = reqwest::blocking::get(url.as_str())
-		.expect(format!("Failed to get URL {}", url).as_str())
+		.unwrap_or_else(|_| panic!("Failed to get URL {}", url))
 		.text()
-		.expect(format!("Failed to extract text from URL {}", url).as_str());
+		.unwrap_or_else(|_| panic!("Failed to extract text from URL {}", url));
 	let parsed = scraper::Html::parse_document(page.as_str());
 
 	let container_class_str = format!(".{link_container_class}");
@@ -48,54 +105,40 @@ fn main() -> Result<(), Box<dyn Error>> {
 	}
 
 	println!("Found {} links (skipped {})", links.len(), num_skipped);
-	println!("Ensuring {:?} exists", out_location);
-
-	fs::create_dir_all(out_location)
-		.expect(format!("Failed to create out location {:?}", out_location).as_str());
-
-	let user_agent =
-		"Mozilla/5.0 (Windows NT 6.1; Win64; x64; rv:47.0) Gecko/20100101 Firefox/47.0";
 
-	let client = reqwest::blocking::ClientBuilder::new().user_agent(user_agent).build()?;
+	let mut downloader = Downloader::new(Downloader::default_client()?, out_location)?
+		.with_credentials(load_netrc(options.netrc)?);
+	let grid = Grid::merra2(options.time_steps);
 
+	let mut failures = Vec::new();
 	for (index, link) in links.iter().enumerate() {
-		if link.starts_with("/") {
-			let new_url = url.join(&link).expect("Failed to join link");
-			println!("Downloading {} of {}: {}", index + 1, links.len(), new_url);
-			let file_name =
-				new_url.path_segments().expect("Invalid path").next_back().expect("Invalid path");
-			let file_path = out_location.join(file_name);
-
-			if file_path.exists() {
-				println!("File exists! Skipping... {:?}", &file_path);
-				continue;
-			}
-
-			// let mut file = fs::OpenOptions::new()
-			// 	.create(true)
-			// 	.write(true)
-			// 	.open(&file_path)
-			// 	.expect(format!("Failed to open file for writing: {:?}",
-			// &file_path).as_str());
-
-			let mut file = File::create(&file_path)
-				.expect(format!("Failed to open file for writing: {:?}", &file_path).as_str());
-
-			let contents = client
-				.get(new_url.as_str())
-				.send()
-				.expect(format!("Failed to get URL {}", new_url).as_str())
-				.text()
-				.unwrap();
-
-			println!("RESULT: {}", contents);
-
-		// io::copy(&mut contents.as_bytes(), &mut file)
-		// 	.expect(format!("Failed to write file {:?}", &file_path).as_str());
-		} else {
+		let Ok(link_url) = url.join(link) else {
 			println!("Error: Unsure how to download link: {}", link);
+			continue;
+		};
+
+		// Subsets keep the granule's name, so they're read the same way
+		let request = match &options.subset {
+			Some(subset) => {
+				let file_name = DownloadRequest::new(link_url.clone()).file_name;
+				DownloadRequest::named(subset.opendap_url(&link_url, &grid), file_name)
+			}
+			None => DownloadRequest::new(link_url.clone()),
+		};
+
+		println!("Downloading {} of {}: {}", index + 1, links.len(), request.url);
+		match downloader.download(&request) {
+			Ok(DownloadOutcome::AlreadyPresent) => println!("Already downloaded, skipping"),
+			Ok(DownloadOutcome::Downloaded) => {}
+			Err(error) => {
+				println!("Failed to download {}: {}", link_url, error);
+				failures.push(link_url);
+			}
 		}
 	}
 
+	if !failures.is_empty() {
+		return Err(format!("{} of {} downloads failed", failures.len(), links.len()).into());
+	}
 	Ok(())
 }
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+/// A record of each file the `Downloader` fetched and verified, saved as
+/// `manifest.json` in the output directory
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+	pub files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+	pub url: String,
+	pub size: u64,
+	pub sha256: String,
+}
+
+impl Manifest {
+	pub const FILE_NAME: &'static str = "manifest.json";
+
+	/// Loads the manifest at `path`, or an empty one if there isn't one yet
+	pub fn load(path: &Path) -> io::Result<Self> {
+		match fs::read(path) {
+			Ok(contents) => serde_json::from_slice(&contents)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Writes the whole manifest to a temporary file first, so an interrupted
+	/// save never leaves it half-written
+	pub fn save(&self, path: &Path) -> io::Result<()> {
+		let temporary_path = path.with_extension("json.tmp");
+		let contents = serde_json::to_vec_pretty(self).expect("Failed to serialize manifest");
+		fs::write(&temporary_path, contents)?;
+		fs::rename(temporary_path, path)
+	}
+}
@@ -0,0 +1,752 @@
+#![cfg(feature = "download")]
+//! Bulk downloads of large data files. Bodies are streamed to a `.part` file,
+//! which is resumed with an HTTP `Range` request after a dropped connection or
+//! an interrupted run, then verified and moved into place. Each finished file
+//! is recorded in a `Manifest`, so later runs only fetch what's missing or
+//! doesn't match.
+//!
+//! Servers behind a login, like Earthdata's, redirect to an authorization
+//! server and back, then set a session cookie. Redirects are followed here
+//! rather than by `reqwest`, so credentials from a `Netrc` are only sent to the
+//! host they're for, while the client's cookie store keeps the session.
+
+mod manifest;
+mod netrc;
+mod subset;
+#[cfg(test)]
+mod test_server;
+
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+pub use manifest::{Manifest, ManifestEntry};
+pub use netrc::{Credentials, Netrc};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{CONTENT_RANGE, LOCATION, RANGE};
+use reqwest::redirect::Policy;
+use reqwest::{StatusCode, Url};
+pub use subset::{BoundingBox, Grid, Subset};
+
+use crate::incremental::sha256_file;
+
+const BUFFER_SIZE: usize = 1 << 20;
+
+const MAX_REDIRECTS: usize = 10;
+
+pub struct DownloadRequest {
+	pub url: Url,
+	pub file_name: String,
+	pub expected_size: Option<u64>,
+	/// Lowercase hex
+	pub expected_sha256: Option<String>,
+}
+
+impl DownloadRequest {
+	/// Saves to the last segment of the URL's path
+	pub fn new(url: Url) -> Self {
+		let file_name = url
+			.path_segments()
+			.and_then(|mut segments| segments.next_back())
+			.filter(|name| !name.is_empty())
+			.unwrap_or_else(|| panic!("No file name in URL {url}"))
+			.to_owned();
+		Self::named(url, file_name)
+	}
+
+	pub fn named(url: Url, file_name: String) -> Self {
+		Self { url, file_name, expected_size: None, expected_sha256: None }
+	}
+
+	pub fn with_size(mut self, size: u64) -> Self {
+		self.expected_size = Some(size);
+		self
+	}
+
+	pub fn with_sha256(mut self, sha256: &str) -> Self {
+		self.expected_sha256 = Some(sha256.to_lowercase());
+		self
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+	pub max_attempts: u32,
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 5,
+			initial_backoff: Duration::from_secs(1),
+			max_backoff: Duration::from_secs(60),
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// Doubles after each failed attempt, starting from `initial_backoff`
+	fn backoff(&self, failed_attempts: u32) -> Duration {
+		let factor = 1u32.checked_shl(failed_attempts.saturating_sub(1)).unwrap_or(u32::MAX);
+		self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DownloadOutcome {
+	/// Already downloaded, and still matches the manifest
+	AlreadyPresent,
+	Downloaded,
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+	Http(reqwest::Error),
+	/// The connection failed partway through the body
+	Interrupted(io::Error),
+	Io(io::Error),
+	Status(StatusCode),
+	/// A redirect without a usable `Location`, or too many of them
+	Redirect(String),
+	/// A partial response that doesn't continue from the end of the `.part`
+	/// file
+	RangeMismatch {
+		expected: u64,
+		actual: Option<u64>,
+	},
+	SizeMismatch {
+		expected: u64,
+		actual: u64,
+	},
+	ChecksumMismatch {
+		expected: String,
+		actual: String,
+	},
+}
+
+impl DownloadError {
+	fn is_retryable(&self) -> bool {
+		match self {
+			DownloadError::Http(_) | DownloadError::Interrupted(_) => true,
+			DownloadError::Status(status) => {
+				status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+			}
+			// The partial file is discarded, so the next attempt starts clean
+			DownloadError::RangeMismatch { .. }
+			| DownloadError::SizeMismatch { .. }
+			| DownloadError::ChecksumMismatch { .. } => true,
+			DownloadError::Io(_) | DownloadError::Redirect(_) => false,
+		}
+	}
+}
+
+impl Display for DownloadError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DownloadError::Http(e) => write!(f, "request failed: {e}"),
+			DownloadError::Interrupted(e) => write!(f, "connection interrupted: {e}"),
+			DownloadError::Io(e) => write!(f, "{e}"),
+			DownloadError::Status(status) => write!(f, "server responded {status}"),
+			DownloadError::Redirect(message) => write!(f, "bad redirect: {message}"),
+			DownloadError::RangeMismatch { expected, actual: Some(actual) } => {
+				write!(f, "asked for bytes from {expected}, got them from {actual}")
+			}
+			DownloadError::RangeMismatch { expected, actual: None } => {
+				write!(f, "asked for bytes from {expected}, got no Content-Range")
+			}
+			DownloadError::SizeMismatch { expected, actual } => {
+				write!(f, "expected {expected} bytes, got {actual}")
+			}
+			DownloadError::ChecksumMismatch { expected, actual } => {
+				write!(f, "expected SHA-256 {expected}, got {actual}")
+			}
+		}
+	}
+}
+
+impl std::error::Error for DownloadError {}
+
+impl From<reqwest::Error> for DownloadError {
+	fn from(e: reqwest::Error) -> Self { DownloadError::Http(e) }
+}
+
+impl From<io::Error> for DownloadError {
+	fn from(e: io::Error) -> Self { DownloadError::Io(e) }
+}
+
+pub struct Downloader {
+	client: Client,
+	out_dir: PathBuf,
+	manifest: Manifest,
+	retry_policy: RetryPolicy,
+	netrc: Netrc,
+}
+
+impl Downloader {
+	/// Downloads into `out_dir`, picking up its manifest if it has one
+	pub fn new(client: Client, out_dir: &Path) -> io::Result<Self> {
+		fs::create_dir_all(out_dir)?;
+		let manifest = Manifest::load(&out_dir.join(Manifest::FILE_NAME))?;
+		Ok(Self {
+			client,
+			out_dir: out_dir.to_owned(),
+			manifest,
+			retry_policy: Default::default(),
+			netrc: Default::default(),
+		})
+	}
+
+	/// A client suited to large files: blocking clients give up after 30
+	/// seconds by default, which a granule can easily take. It keeps cookies,
+	/// and leaves redirects to the `Downloader`.
+	pub fn default_client() -> reqwest::Result<Client> {
+		Client::builder()
+			.timeout(None)
+			.connect_timeout(Duration::from_secs(30))
+			.cookie_store(true)
+			.redirect(Policy::none())
+			.build()
+	}
+
+	pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+		self.retry_policy = retry_policy;
+		self
+	}
+
+	/// Sends credentials to the hosts in `netrc`, on their own requests only
+	pub fn with_credentials(mut self, netrc: Netrc) -> Self {
+		self.netrc = netrc;
+		self
+	}
+
+	pub fn manifest(&self) -> &Manifest { &self.manifest }
+
+	pub fn download(
+		&mut self,
+		request: &DownloadRequest,
+	) -> Result<DownloadOutcome, DownloadError> {
+		let file_path = self.out_dir.join(&request.file_name);
+		if self.is_present(request, &file_path)? {
+			return Ok(DownloadOutcome::AlreadyPresent);
+		}
+
+		let mut attempt = 1;
+		loop {
+			match self.try_download(request, &file_path) {
+				Ok(entry) => {
+					self.manifest.files.insert(request.file_name.clone(), entry);
+					self.manifest.save(&self.out_dir.join(Manifest::FILE_NAME))?;
+					return Ok(DownloadOutcome::Downloaded);
+				}
+				Err(error) if error.is_retryable() && attempt < self.retry_policy.max_attempts => {
+					let backoff = self.retry_policy.backoff(attempt);
+					println!(
+						"Attempt {attempt} at {} failed ({error}), retrying in {backoff:?}",
+						request.url
+					);
+					thread::sleep(backoff);
+					attempt += 1;
+				}
+				Err(error) => return Err(error),
+			}
+		}
+	}
+
+	/// Whether `file_path` is already downloaded from the same URL, and still
+	/// matches its manifest entry. Files without an entry are fetched again.
+	fn is_present(&mut self, request: &DownloadRequest, file_path: &Path) -> io::Result<bool> {
+		let Some(entry) = self.manifest.files.get(&request.file_name) else {
+			return Ok(false);
+		};
+		if entry.url != request.url.as_str() || !file_path.exists() {
+			return Ok(false);
+		}
+
+		let matches = fs::metadata(file_path)?.len() == entry.size
+			&& request.expected_size.is_none_or(|size| size == entry.size)
+			&& sha256_file(file_path)? == entry.sha256
+			&& request.expected_sha256.as_ref().is_none_or(|sha256| *sha256 == entry.sha256);
+		if !matches {
+			println!("{:?} doesn't match the manifest, downloading again", file_path);
+			self.manifest.files.remove(&request.file_name);
+		}
+		Ok(matches)
+	}
+
+	fn try_download(
+		&self,
+		request: &DownloadRequest,
+		file_path: &Path,
+	) -> Result<ManifestEntry, DownloadError> {
+		let mut part_name = file_path.as_os_str().to_owned();
+		part_name.push(".part");
+		let part_path = PathBuf::from(part_name);
+
+		let reported_size = self.fetch_to_part(request, &part_path)?;
+
+		let verified = verify(request, &part_path, reported_size);
+		if verified.is_err() {
+			fs::remove_file(&part_path)?;
+		}
+		let (size, sha256) = verified?;
+
+		fs::rename(&part_path, file_path)?;
+		println!("Downloaded {:?} ({size} bytes)", file_path);
+		Ok(ManifestEntry { url: request.url.to_string(), size, sha256 })
+	}
+
+	/// Follows redirects from `requested`, asking for bytes from `range_start`
+	/// on. Only `requested` is asked for a range, not the hops in between, like
+	/// a login server's.
+	fn get(&self, requested: &Url, range_start: u64) -> Result<Response, DownloadError> {
+		let mut url = requested.clone();
+
+		for _ in 0..=MAX_REDIRECTS {
+			let mut builder = self.client.get(url.clone());
+			if range_start > 0 && url == *requested {
+				builder = builder.header(RANGE, format!("bytes={range_start}-"));
+			}
+			if let Some(credentials) = url.host_str().and_then(|host| self.netrc.credentials(host))
+			{
+				builder = builder.basic_auth(&credentials.login, Some(&credentials.password));
+			}
+
+			let response = builder.send()?;
+			if !response.status().is_redirection() {
+				return Ok(response);
+			}
+
+			let location = response
+				.headers()
+				.get(LOCATION)
+				.and_then(|location| location.to_str().ok())
+				.ok_or_else(|| DownloadError::Redirect(format!("no location from {url}")))?;
+			url = url
+				.join(location)
+				.map_err(|e| DownloadError::Redirect(format!("{location:?} from {url}: {e}")))?;
+		}
+
+		Err(DownloadError::Redirect(format!("more than {MAX_REDIRECTS} from {url}")))
+	}
+
+	/// Appends the rest of the body to `part_path`. Returns the full size the
+	/// server reported, if it did.
+	fn fetch_to_part(
+		&self,
+		request: &DownloadRequest,
+		part_path: &Path,
+	) -> Result<Option<u64>, DownloadError> {
+		let existing = fs::metadata(part_path).map(|metadata| metadata.len()).unwrap_or(0);
+
+		if existing > 0 {
+			println!("Resuming {} from byte {existing}", request.url);
+		}
+		let mut response = self.get(&request.url, existing)?;
+
+		let (mut file, reported_size) = match response.status() {
+			StatusCode::PARTIAL_CONTENT => {
+				let start = content_range_start(&response);
+				if existing == 0 || start != Some(existing) {
+					// Whatever it is, it doesn't follow on from what we have, so the next
+					// attempt starts clean
+					File::create(part_path)?;
+					return Err(DownloadError::RangeMismatch { expected: existing, actual: start });
+				}
+				let file = OpenOptions::new().append(true).open(part_path)?;
+				(file, content_range_size(&response))
+			}
+			// What we have is everything there is, or it's wrong; verification decides
+			StatusCode::RANGE_NOT_SATISFIABLE => return Ok(content_range_size(&response)),
+			// The server ignored the range, so start over
+			status if status.is_success() => (File::create(part_path)?, response.content_length()),
+			status => return Err(DownloadError::Status(status)),
+		};
+
+		let mut buffer = vec![0u8; BUFFER_SIZE];
+		loop {
+			let read = response.read(&mut buffer).map_err(DownloadError::Interrupted)?;
+			if read == 0 {
+				break;
+			}
+			file.write_all(&buffer[..read])?;
+		}
+		file.sync_all()?;
+
+		Ok(reported_size)
+	}
+}
+
+/// Where the body starts, from a `Content-Range` of `bytes
+/// {start}-{end}/{size}`
+fn content_range_start(response: &reqwest::blocking::Response) -> Option<u64> {
+	let content_range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+	content_range.strip_prefix("bytes ")?.split_once('-')?.0.parse().ok()
+}
+
+/// The total size from a `Content-Range` of `bytes {start}-{end}/{size}` or
+/// `bytes */{size}`
+fn content_range_size(response: &reqwest::blocking::Response) -> Option<u64> {
+	let content_range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+	content_range.rsplit_once('/')?.1.parse().ok()
+}
+
+/// Checks `path` against what's expected, or failing that what the server
+/// reported. Returns its size and SHA-256.
+fn verify(
+	request: &DownloadRequest,
+	path: &Path,
+	reported_size: Option<u64>,
+) -> Result<(u64, String), DownloadError> {
+	let size = fs::metadata(path)?.len();
+	if let Some(expected) = request.expected_size.or(reported_size) {
+		if size != expected {
+			return Err(DownloadError::SizeMismatch { expected, actual: size });
+		}
+	}
+
+	let sha256 = sha256_file(path)?;
+	if let Some(expected) = &request.expected_sha256 {
+		if sha256 != *expected {
+			return Err(DownloadError::ChecksumMismatch {
+				expected: expected.clone(),
+				actual: sha256,
+			});
+		}
+	}
+
+	Ok((size, sha256))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	use sha2::{Digest, Sha256};
+
+	use super::test_server::{Request, Response, TestServer};
+	use super::*;
+
+	const BODY: &[u8] = b"MERRA-2 stand-in granule, long enough to split in two";
+
+	fn downloader(out_dir: &Path) -> Downloader {
+		let no_backoff = RetryPolicy {
+			max_attempts: 3,
+			initial_backoff: Duration::ZERO,
+			max_backoff: Duration::ZERO,
+		};
+		Downloader::new(Downloader::default_client().unwrap(), out_dir)
+			.unwrap()
+			.with_retry_policy(no_backoff)
+	}
+
+	fn sha256(bytes: &[u8]) -> String { format!("{:x}", Sha256::digest(bytes)) }
+
+	#[test]
+	fn downloads_and_records_in_manifest() {
+		let server = TestServer::start(|request| Response::ranged(request, BODY));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		let request = DownloadRequest::new(server.url("/data/granule.nc4"));
+
+		let outcome = downloader(out_dir).download(&request).unwrap();
+
+		assert_eq!(outcome, DownloadOutcome::Downloaded);
+		assert_eq!(fs::read(out_dir.join("granule.nc4")).unwrap(), BODY);
+		assert!(!out_dir.join("granule.nc4.part").exists());
+		let requests = server.requests();
+		assert_eq!(
+			(requests[0].method.as_str(), requests[0].path.as_str()),
+			("GET", "/data/granule.nc4")
+		);
+
+		let manifest = Manifest::load(&out_dir.join(Manifest::FILE_NAME)).unwrap();
+		let entry = &manifest.files["granule.nc4"];
+		assert_eq!(entry.size, BODY.len() as u64);
+		assert_eq!(entry.sha256, sha256(BODY));
+	}
+
+	#[test]
+	fn skips_verified_files() {
+		let server = TestServer::start(|request| Response::ranged(request, BODY));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+
+		downloader(out_dir).download(&request).unwrap();
+		let outcome = downloader(out_dir).download(&request).unwrap();
+
+		assert_eq!(outcome, DownloadOutcome::AlreadyPresent);
+		assert_eq!(server.requests().len(), 1);
+	}
+
+	#[test]
+	fn downloads_corrupted_files_again() {
+		let server = TestServer::start(|request| Response::ranged(request, BODY));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+
+		downloader(out_dir).download(&request).unwrap();
+		let mut corrupted = BODY.to_vec();
+		corrupted[0] ^= 1;
+		fs::write(out_dir.join("granule.nc4"), corrupted).unwrap();
+
+		let outcome = downloader(out_dir).download(&request).unwrap();
+		assert_eq!(outcome, DownloadOutcome::Downloaded);
+		assert_eq!(fs::read(out_dir.join("granule.nc4")).unwrap(), BODY);
+	}
+
+	#[test]
+	fn resumes_part_file() {
+		let server = TestServer::start(|request| Response::ranged(request, BODY));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		fs::create_dir_all(out_dir).unwrap();
+		fs::write(out_dir.join("granule.nc4.part"), &BODY[..20]).unwrap();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4")).with_sha256(&sha256(BODY));
+		downloader(out_dir).download(&request).unwrap();
+
+		assert_eq!(fs::read(out_dir.join("granule.nc4")).unwrap(), BODY);
+		assert_eq!(server.requests()[0].header("range"), Some("bytes=20-"));
+	}
+
+	#[test]
+	fn resumes_after_dropped_connection() {
+		let served = AtomicUsize::new(0);
+		let server = TestServer::start(move |request| {
+			let response = Response::ranged(request, BODY);
+			match served.fetch_add(1, Ordering::SeqCst) {
+				0 => response.truncated(BODY.len() / 2),
+				_ => response,
+			}
+		});
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+		downloader(out_dir).download(&request).unwrap();
+
+		assert_eq!(fs::read(out_dir.join("granule.nc4")).unwrap(), BODY);
+		let requests = server.requests();
+		assert_eq!(requests.len(), 2);
+		assert!(requests[1].header("range").is_some());
+	}
+
+	#[test]
+	fn restarts_when_range_is_ignored() {
+		let server = TestServer::start(|_| Response::ok(BODY));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		fs::create_dir_all(out_dir).unwrap();
+		fs::write(out_dir.join("granule.nc4.part"), b"stale").unwrap();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+		downloader(out_dir).download(&request).unwrap();
+
+		assert_eq!(fs::read(out_dir.join("granule.nc4")).unwrap(), BODY);
+	}
+
+	#[test]
+	fn restarts_when_range_starts_elsewhere() {
+		let server = TestServer::start(|request| match request.header("range") {
+			Some(_) => Response::status(206).with_body(&BODY[10..]).with_header(
+				"Content-Range",
+				&format!("bytes 10-{}/{}", BODY.len() - 1, BODY.len()),
+			),
+			None => Response::ok(BODY),
+		});
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		fs::create_dir_all(out_dir).unwrap();
+		fs::write(out_dir.join("granule.nc4.part"), &BODY[..20]).unwrap();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+		downloader(out_dir).download(&request).unwrap();
+
+		assert_eq!(fs::read(out_dir.join("granule.nc4")).unwrap(), BODY);
+		let requests = server.requests();
+		assert_eq!(requests.len(), 2);
+		assert!(requests[1].header("range").is_none());
+	}
+
+	#[test]
+	fn retries_server_errors() {
+		let served = AtomicUsize::new(0);
+		let server =
+			TestServer::start(move |request| match served.fetch_add(1, Ordering::SeqCst) {
+				0 | 1 => Response::status(503),
+				_ => Response::ranged(request, BODY),
+			});
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+		downloader(out_dir).download(&request).unwrap();
+
+		assert_eq!(server.requests().len(), 3);
+	}
+
+	#[test]
+	fn gives_up_after_max_attempts() {
+		let server = TestServer::start(|_| Response::status(500));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+		let result = downloader(out_dir).download(&request);
+
+		assert!(matches!(result, Err(DownloadError::Status(StatusCode::INTERNAL_SERVER_ERROR))));
+		assert_eq!(server.requests().len(), 3);
+	}
+
+	#[test]
+	fn client_errors_are_not_retried() {
+		let server = TestServer::start(|_| Response::status(404));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4"));
+		assert!(downloader(out_dir).download(&request).is_err());
+		assert_eq!(server.requests().len(), 1);
+	}
+
+	#[test]
+	fn checksum_mismatch_keeps_nothing() {
+		let server = TestServer::start(|request| Response::ranged(request, BODY));
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+
+		let request = DownloadRequest::new(server.url("/granule.nc4"))
+			.with_sha256(&sha256(b"something else"));
+		let mut downloader = downloader(out_dir);
+		let result = downloader.download(&request);
+
+		assert!(matches!(result, Err(DownloadError::ChecksumMismatch { .. })));
+		assert!(!out_dir.join("granule.nc4").exists());
+		assert!(!out_dir.join("granule.nc4.part").exists());
+		assert!(downloader.manifest().files.is_empty());
+	}
+
+	/// Imitates Earthdata: the data server sends anyone without a session to
+	/// the authorization server, which checks their credentials and sends them
+	/// back with a code, which the data server swaps for a session cookie. The
+	/// servers are on different hosts, `127.0.0.1` and `localhost`.
+	fn earthdata_stand_in() -> (TestServer, TestServer) {
+		let query = |request: &Request, name: &str| {
+			let url = Url::parse(&format!("http://stand-in{}", request.path)).unwrap();
+			url.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned())
+		};
+
+		let authorization = TestServer::start(move |request| {
+			// "alice:s3cret"
+			if request.header("authorization") != Some("Basic YWxpY2U6czNjcmV0") {
+				return Response::status(401);
+			}
+			let mut callback = Url::parse(&query(request, "redirect_uri").unwrap()).unwrap();
+			callback
+				.query_pairs_mut()
+				.append_pair("code", "granted")
+				.append_pair("state", &query(request, "state").unwrap());
+			Response::status(302).with_header("Location", callback.as_str())
+		});
+
+		let authorization_port = authorization.port();
+		let data = TestServer::start(move |request| {
+			let host = request.header("host").unwrap().to_owned();
+			if request.path.starts_with("/callback") {
+				assert_eq!(query(request, "code").as_deref(), Some("granted"));
+				return Response::status(302)
+					.with_header("Set-Cookie", "session=valid; Path=/")
+					.with_header("Location", &query(request, "state").unwrap());
+			}
+			if request.header("cookie") == Some("session=valid") {
+				return Response::ranged(request, BODY);
+			}
+
+			let mut login =
+				Url::parse(&format!("http://localhost:{authorization_port}/oauth/authorize"))
+					.unwrap();
+			login
+				.query_pairs_mut()
+				.append_pair("redirect_uri", &format!("http://{host}/callback"))
+				.append_pair("state", &request.path);
+			Response::status(302).with_header("Location", login.as_str())
+		});
+
+		(data, authorization)
+	}
+
+	#[test]
+	fn logs_in_with_netrc_credentials() {
+		let (data, authorization) = earthdata_stand_in();
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		let netrc = Netrc::parse("machine localhost login alice password s3cret");
+		let mut downloader = downloader(out_dir).with_credentials(netrc);
+
+		downloader.download(&DownloadRequest::new(data.url("/data/first.nc4"))).unwrap();
+		downloader.download(&DownloadRequest::new(data.url("/data/second.nc4"))).unwrap();
+
+		assert_eq!(fs::read(out_dir.join("first.nc4")).unwrap(), BODY);
+		assert_eq!(fs::read(out_dir.join("second.nc4")).unwrap(), BODY);
+		// The session cookie covers the second download
+		assert_eq!(authorization.requests().len(), 1);
+		// Credentials only go to the host they're for
+		assert!(data.requests().iter().all(|request| request.header("authorization").is_none()));
+	}
+
+	#[test]
+	fn resumes_after_logging_in() {
+		let (data, authorization) = earthdata_stand_in();
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+		fs::create_dir_all(out_dir).unwrap();
+		fs::write(out_dir.join("first.nc4.part"), &BODY[..20]).unwrap();
+		let netrc = Netrc::parse("machine localhost login alice password s3cret");
+
+		let request = DownloadRequest::new(data.url("/data/first.nc4")).with_sha256(&sha256(BODY));
+		downloader(out_dir).with_credentials(netrc).download(&request).unwrap();
+
+		assert_eq!(fs::read(out_dir.join("first.nc4")).unwrap(), BODY);
+		// Only the file itself is asked for a range
+		assert!(authorization.requests().iter().all(|request| request.header("range").is_none()));
+		let ranges: Vec<_> = data
+			.requests()
+			.iter()
+			.map(|request| (request.path.clone(), request.header("range").map(str::to_owned)))
+			.filter(|(_, range)| range.is_some())
+			.collect();
+		assert!(ranges.iter().all(|(path, _)| path == "/data/first.nc4"));
+		assert_eq!(ranges.last().unwrap().1.as_deref(), Some("bytes=20-"));
+	}
+
+	#[test]
+	fn login_without_credentials_fails() {
+		let (data, authorization) = earthdata_stand_in();
+		let temp = tempfile::tempdir().unwrap();
+		let out_dir = temp.path();
+
+		let result =
+			downloader(out_dir).download(&DownloadRequest::new(data.url("/data/first.nc4")));
+
+		assert!(matches!(result, Err(DownloadError::Status(StatusCode::UNAUTHORIZED))));
+		assert_eq!(authorization.requests().len(), 1);
+	}
+
+	#[test]
+	fn backoff_doubles_up_to_max() {
+		let policy = RetryPolicy {
+			max_attempts: 10,
+			initial_backoff: Duration::from_secs(1),
+			max_backoff: Duration::from_secs(5),
+		};
+		let backoffs: Vec<u64> = (1..=5).map(|attempt| policy.backoff(attempt).as_secs()).collect();
+		assert_eq!(backoffs, vec![1, 2, 4, 5, 5]);
+	}
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+	pub login: String,
+	pub password: String,
+}
+
+/// Credentials from a `.netrc` file, by host. `default` entries are ignored, so
+/// credentials are only ever sent to hosts named in the file.
+#[derive(Clone, Debug, Default)]
+pub struct Netrc {
+	machines: HashMap<String, Credentials>,
+}
+
+impl Netrc {
+	/// `$NETRC` if it's set, otherwise `.netrc` in the home directory
+	pub fn default_path() -> Option<PathBuf> {
+		env::var_os("NETRC")
+			.map(PathBuf::from)
+			.or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".netrc")))
+	}
+
+	pub fn load(path: &Path) -> io::Result<Self> { Ok(Self::parse(&fs::read_to_string(path)?)) }
+
+	pub fn parse(contents: &str) -> Self {
+		let mut machines = HashMap::new();
+
+		let mut machine: Option<String> = None;
+		let mut login = None;
+		let mut password = None;
+		let mut finish_machine =
+			|machine: Option<String>, login: Option<String>, password: Option<String>| {
+				if let (Some(machine), Some(login), Some(password)) = (machine, login, password) {
+					machines.insert(machine, Credentials { login, password });
+				}
+			};
+
+		let mut in_macro = false;
+		for line in contents.lines() {
+			// Macros run until the next blank line
+			if in_macro {
+				in_macro = !line.trim().is_empty();
+				continue;
+			}
+			if line.trim_start().starts_with('#') {
+				continue;
+			}
+
+			let mut tokens = line.split_whitespace();
+			while let Some(token) = tokens.next() {
+				match token {
+					"machine" | "default" => {
+						finish_machine(machine.take(), login.take(), password.take());
+						if token == "machine" {
+							machine = tokens.next().map(str::to_owned);
+						}
+					}
+					"login" => login = tokens.next().map(str::to_owned),
+					"password" => password = tokens.next().map(str::to_owned),
+					"account" => {
+						tokens.next();
+					}
+					"macdef" => {
+						finish_machine(machine.take(), login.take(), password.take());
+						in_macro = true;
+						break;
+					}
+					_ => {}
+				}
+			}
+		}
+		finish_machine(machine, login, password);
+
+		Self { machines }
+	}
+
+	pub fn credentials(&self, host: &str) -> Option<&Credentials> { self.machines.get(host) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_machines() {
+		let netrc = Netrc::parse(
+			"machine urs.earthdata.nasa.gov login alice password s3cret\n\
+			 # A comment\n\
+			 machine example.com\n  login bob\n  account ignored\n  password hunter2\n\
+			 default login anyone password anything\n",
+		);
+
+		let earthdata = netrc.credentials("urs.earthdata.nasa.gov").unwrap();
+		assert_eq!((earthdata.login.as_str(), earthdata.password.as_str()), ("alice", "s3cret"));
+		assert_eq!(netrc.credentials("example.com").unwrap().password, "hunter2");
+		assert_eq!(netrc.credentials("elsewhere.org"), None);
+	}
+
+	#[test]
+	fn skips_macros() {
+		let netrc = Netrc::parse(
+			"macdef init\nmachine fake login in password macro\n\n\
+			 machine real.org login carol password p4ss\n",
+		);
+
+		assert_eq!(netrc.credentials("fake"), None);
+		assert_eq!(netrc.credentials("real.org").unwrap().login, "carol");
+	}
+}
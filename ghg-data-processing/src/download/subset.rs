@@ -0,0 +1,165 @@
+use reqwest::Url;
+
+/// A regular latitude/longitude grid, to turn a bounding box into indices
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Grid {
+	pub latitude_start: f64,
+	pub latitude_step: f64,
+	pub latitude_count: usize,
+	pub longitude_start: f64,
+	pub longitude_step: f64,
+	pub longitude_count: usize,
+	/// Time steps in each granule
+	pub time_count: usize,
+}
+
+impl Grid {
+	/// The 0.5° x 0.625° grid of MERRA-2 2D collections, for a collection with
+	/// `time_count` steps per granule (e.g. 1 for monthly, 24 for hourly)
+	pub fn merra2(time_count: usize) -> Self {
+		Self {
+			latitude_start: -90.0,
+			latitude_step: 0.5,
+			latitude_count: 361,
+			longitude_start: -180.0,
+			longitude_step: 0.625,
+			longitude_count: 576,
+			time_count,
+		}
+	}
+
+	/// The indices covering `min..=max`, rounded outwards
+	fn indices(start: f64, step: f64, count: usize, min: f64, max: f64) -> (usize, usize) {
+		let last = count as f64 - 1.0;
+		let first_index = ((min - start) / step).floor().clamp(0.0, last);
+		let last_index = ((max - start) / step).ceil().clamp(0.0, last);
+		(first_index as usize, last_index as usize)
+	}
+}
+
+/// In degrees. Boxes across the antimeridian aren't supported; `west` must not
+/// be greater than `east`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingBox {
+	pub west: f64,
+	pub south: f64,
+	pub east: f64,
+	pub north: f64,
+}
+
+/// Server-side subsetting of a granule with an OPeNDAP constraint expression,
+/// so only the variables (and area) needed are fetched
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subset {
+	pub variables: Vec<String>,
+	pub bounding_box: Option<BoundingBox>,
+}
+
+impl Subset {
+	/// Turns a granule's download URL into an OPeNDAP request for this subset,
+	/// as netCDF-4. GES DISC serves OPeNDAP from `/opendap/` rather than
+	/// `/data/`, under the same path.
+	pub fn opendap_url(&self, granule_url: &Url, grid: &Grid) -> Url {
+		let mut url = granule_url.clone();
+		let path = granule_url.path();
+		let opendap_path = match path.strip_prefix("/data/") {
+			Some(rest) => format!("/opendap/{rest}"),
+			None => path.to_owned(),
+		};
+		url.set_path(&format!("{opendap_path}.nc4"));
+		url.set_query(Some(&self.constraint_expression(grid)));
+		url
+	}
+
+	fn constraint_expression(&self, grid: &Grid) -> String {
+		let Some(bounding_box) = self.bounding_box else {
+			let mut projection = self.variables.clone();
+			projection.extend(["lat", "lon", "time"].map(str::to_owned));
+			return projection.join(",");
+		};
+		assert!(
+			bounding_box.west <= bounding_box.east,
+			"Bounding boxes across the antimeridian aren't supported: {bounding_box:?}"
+		);
+
+		let (lat_first, lat_last) = Grid::indices(
+			grid.latitude_start,
+			grid.latitude_step,
+			grid.latitude_count,
+			bounding_box.south,
+			bounding_box.north,
+		);
+		let (lon_first, lon_last) = Grid::indices(
+			grid.longitude_start,
+			grid.longitude_step,
+			grid.longitude_count,
+			bounding_box.west,
+			bounding_box.east,
+		);
+		let time = format!("[0:1:{}]", grid.time_count - 1);
+		let lat = format!("[{lat_first}:1:{lat_last}]");
+		let lon = format!("[{lon_first}:1:{lon_last}]");
+
+		let mut projection: Vec<String> =
+			self.variables.iter().map(|variable| format!("{variable}{time}{lat}{lon}")).collect();
+		projection.extend([format!("lat{lat}"), format!("lon{lon}"), format!("time{time}")]);
+		projection.join(",")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const GRANULE: &str = "https://goldsmr4.gesdisc.eosdis.nasa.gov/data/MERRA2_MONTHLY/\
+	                       M2IMNXASM.5.12.4/1980/MERRA2_100.instM_2d_asm_Nx.198001.nc4";
+
+	#[test]
+	fn variables_only() {
+		let subset = Subset { variables: vec!["T2M".to_owned()], bounding_box: None };
+		let url = subset.opendap_url(&Url::parse(GRANULE).unwrap(), &Grid::merra2(1));
+
+		assert_eq!(
+			url.as_str(),
+			"https://goldsmr4.gesdisc.eosdis.nasa.gov/opendap/MERRA2_MONTHLY/M2IMNXASM.5.12.4/1980/\
+			 MERRA2_100.instM_2d_asm_Nx.198001.nc4.nc4?T2M,lat,lon,time"
+		);
+	}
+
+	#[test]
+	fn bounding_box_rounds_outwards() {
+		let subset = Subset {
+			variables: vec!["T2M".to_owned()],
+			bounding_box: Some(BoundingBox { west: -10.3, south: 40.2, east: 5.0, north: 60.0 }),
+		};
+		let url = subset.opendap_url(&Url::parse(GRANULE).unwrap(), &Grid::merra2(1));
+
+		// Latitude 40.0 is index 260, longitude -10.625 index 271
+		assert_eq!(
+			url.query(),
+			Some("T2M[0:1:0][260:1:300][271:1:296],lat[260:1:300],lon[271:1:296],time[0:1:0]")
+		);
+	}
+
+	#[test]
+	fn bounding_box_is_clamped_to_grid() {
+		let subset = Subset {
+			variables: vec!["T2M".to_owned(), "PS".to_owned()],
+			bounding_box: Some(BoundingBox {
+				west: -200.0,
+				south: -100.0,
+				east: 200.0,
+				north: 100.0,
+			}),
+		};
+		let url = subset.opendap_url(&Url::parse(GRANULE).unwrap(), &Grid::merra2(24));
+
+		assert_eq!(
+			url.query(),
+			Some(
+				"T2M[0:1:23][0:1:360][0:1:575],PS[0:1:23][0:1:360][0:1:575],lat[0:1:360],\
+				 lon[0:1:575],time[0:1:23]"
+			)
+		);
+	}
+}
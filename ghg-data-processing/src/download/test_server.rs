@@ -0,0 +1,150 @@
+//! A minimal HTTP/1.1 stand-in server, so downloads can be tested without a
+//! network. Each connection serves one request, answered by a handler.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use reqwest::Url;
+
+#[derive(Clone, Debug)]
+pub(crate) struct Request {
+	pub method: String,
+	pub path: String,
+	headers: Vec<(String, String)>,
+}
+
+impl Request {
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(header, _)| header.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+}
+
+pub(crate) struct Response {
+	status: u16,
+	headers: Vec<(String, String)>,
+	body: Vec<u8>,
+	/// Closes the connection after this many bytes of the body, while still
+	/// advertising the full length
+	truncate_at: Option<usize>,
+}
+
+impl Response {
+	pub fn ok(body: &[u8]) -> Self { Self::status(200).with_body(body) }
+
+	pub fn status(status: u16) -> Self {
+		Self { status, headers: Vec::new(), body: Vec::new(), truncate_at: None }
+	}
+
+	/// Serves `body`, honouring a `Range: bytes={start}-` header
+	pub fn ranged(request: &Request, body: &[u8]) -> Self {
+		let start = request
+			.header("range")
+			.and_then(|range| range.strip_prefix("bytes="))
+			.and_then(|range| range.strip_suffix('-'))
+			.and_then(|start| start.parse::<usize>().ok());
+
+		match start {
+			None => Self::ok(body),
+			Some(start) if start >= body.len() => {
+				Self::status(416).with_header("Content-Range", &format!("bytes */{}", body.len()))
+			}
+			Some(start) => Self::status(206).with_body(&body[start..]).with_header(
+				"Content-Range",
+				&format!("bytes {start}-{}/{}", body.len() - 1, body.len()),
+			),
+		}
+	}
+
+	pub fn with_body(mut self, body: &[u8]) -> Self {
+		self.body = body.to_vec();
+		self
+	}
+
+	pub fn with_header(mut self, name: &str, value: &str) -> Self {
+		self.headers.push((name.to_owned(), value.to_owned()));
+		self
+	}
+
+	pub fn truncated(mut self, length: usize) -> Self {
+		self.truncate_at = Some(length);
+		self
+	}
+
+	fn write_to(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+		write!(stream, "HTTP/1.1 {} Stand-in\r\n", self.status)?;
+		write!(stream, "Content-Length: {}\r\nConnection: close\r\n", self.body.len())?;
+		for (name, value) in &self.headers {
+			write!(stream, "{name}: {value}\r\n")?;
+		}
+		write!(stream, "\r\n")?;
+
+		let length = self.truncate_at.unwrap_or(self.body.len());
+		stream.write_all(&self.body[..length])?;
+		stream.flush()
+	}
+}
+
+pub(crate) struct TestServer {
+	address: SocketAddr,
+	requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+	/// Serves requests on a background thread for the rest of the test
+	pub fn start(handler: impl Fn(&Request) -> Response + Send + 'static) -> Self {
+		let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+		let address = listener.local_addr().unwrap();
+		let requests = Arc::new(Mutex::new(Vec::new()));
+
+		let served = requests.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				let Ok(mut stream) = stream else {
+					continue;
+				};
+				if let Some(request) = read_request(&stream) {
+					served.lock().unwrap().push(request.clone());
+					// The client may hang up first, e.g. on a failed download
+					let _ = handler(&request).write_to(&mut stream);
+				}
+			}
+		});
+
+		Self { address, requests }
+	}
+
+	pub fn url(&self, path: &str) -> Url {
+		Url::parse(&format!("http://{}{path}", self.address)).unwrap()
+	}
+
+	pub fn port(&self) -> u16 { self.address.port() }
+
+	/// Every request served so far, in order
+	pub fn requests(&self) -> Vec<Request> { self.requests.lock().unwrap().clone() }
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+	let mut lines = BufReader::new(stream).lines();
+
+	let request_line = lines.next()?.ok()?;
+	let mut parts = request_line.split_whitespace();
+	let method = parts.next()?.to_owned();
+	let path = parts.next()?.to_owned();
+
+	let mut headers = Vec::new();
+	for line in lines {
+		let line = line.ok()?;
+		if line.is_empty() {
+			break;
+		}
+		let (name, value) = line.split_once(':')?;
+		headers.push((name.trim().to_owned(), value.trim().to_owned()));
+	}
+
+	Some(Request { method, path, headers })
+}
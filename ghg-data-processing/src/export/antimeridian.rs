@@ -0,0 +1,356 @@
+#![cfg(any(feature = "read_shapefile", feature = "read_geojson"))]
+//! Longitude/latitude geometry doesn't rasterise cleanly with a plain
+//! translate-and-scale. A ring that crosses ±180° has an edge spanning the
+//! whole map, so it smears across every column in between. A ring that
+//! encloses a pole never closes in longitude at all.
+//!
+//! These helpers unwrap longitudes so no edge is longer than half the globe,
+//! and close pole-enclosing rings along the pole. Then they split the result
+//! at the antimeridian: one copy per whole turn the geometry reaches into,
+//! shifted back onto the map and clipped to -180°..180°.
+
+use geo::{
+	coord, BoundingRect, Coord, Geometry, LineString, MultiLineString, MultiPolygon, Polygon, Rect,
+};
+
+use crate::export::clip::{clip_polygon, crossing_x};
+
+const FULL_TURN: f64 = 360.0;
+const HALF_TURN: f64 = 180.0;
+
+/// Splits `geometry` into pieces that each lie within the -180°..180° by
+/// -90°..90° map, so they can be rasterised with a plain translate-and-scale.
+pub fn split_at_antimeridian(geometry: &Geometry) -> Vec<Geometry> {
+	match geometry {
+		Geometry::Polygon(polygon) => split_polygon(polygon),
+		Geometry::MultiPolygon(MultiPolygon(polygons)) => {
+			polygons.iter().flat_map(split_polygon).collect()
+		}
+		Geometry::LineString(line_string) => split_line_string(line_string),
+		Geometry::MultiLineString(MultiLineString(line_strings)) => {
+			line_strings.iter().flat_map(split_line_string).collect()
+		}
+		Geometry::Rect(rect) => split_polygon(&rect.to_polygon()),
+		Geometry::Triangle(triangle) => split_polygon(&triangle.to_polygon()),
+		Geometry::GeometryCollection(collection) => {
+			collection.iter().flat_map(split_at_antimeridian).collect()
+		}
+		other => vec![other.clone()],
+	}
+}
+
+fn split_polygon(polygon: &Polygon) -> Vec<Geometry> {
+	let (mut exterior, turns) = unwrap_longitudes(polygon.exterior());
+	if turns != 0 {
+		close_around_pole(&mut exterior);
+	}
+	let center = longitude_center(&exterior);
+
+	let interiors: Vec<LineString> = polygon
+		.interiors()
+		.iter()
+		.map(|interior| {
+			let (unwrapped, _) = unwrap_longitudes(interior);
+			let offset = nearest_turn(center - longitude_center(&unwrapped));
+			shift_longitudes(&unwrapped, offset)
+		})
+		.collect();
+
+	window_offsets(&exterior)
+		.filter_map(|offset| {
+			let shifted = Polygon::new(
+				shift_longitudes(&exterior, offset),
+				interiors.iter().map(|interior| shift_longitudes(interior, offset)).collect(),
+			);
+			clip_polygon(&shifted, map_bounds()).map(Geometry::from)
+		})
+		.collect()
+}
+
+fn split_line_string(line_string: &LineString) -> Vec<Geometry> {
+	let (unwrapped, _) = unwrap_longitudes(line_string);
+	window_offsets(&unwrapped)
+		.flat_map(|offset| clip_line_string(&shift_longitudes(&unwrapped, offset)))
+		.map(Geometry::from)
+		.collect()
+}
+
+/// Rebuilds `line_string` so that consecutive points never jump by more than
+/// half a turn in longitude, starting within -180°..180°. Also returns the
+/// number of whole turns the line travels around the globe, which is non-zero
+/// for a ring that encloses a pole.
+fn unwrap_longitudes(line_string: &LineString) -> (LineString, i32) {
+	let mut coords: Vec<Coord> = Vec::with_capacity(line_string.0.len());
+	let mut turns = 0;
+
+	for coord in line_string.coords() {
+		let mut unwrapped = Coord { x: coord.x, y: coord.y.clamp(-90.0, 90.0) };
+		match coords.last() {
+			None => unwrapped.x -= nearest_turn(coord.x),
+			Some(previous) => {
+				unwrapped.x += f64::from(turns) * FULL_TURN;
+				let jump = unwrapped.x - previous.x;
+				if jump > HALF_TURN {
+					turns -= 1;
+					unwrapped.x -= FULL_TURN;
+				} else if jump < -HALF_TURN {
+					turns += 1;
+					unwrapped.x += FULL_TURN;
+				}
+			}
+		}
+		coords.push(unwrapped);
+	}
+
+	let net_turns = match (coords.first(), coords.last()) {
+		(Some(first), Some(last)) => ((last.x - first.x) / FULL_TURN).round() as i32,
+		_ => 0,
+	};
+	(LineString(coords), net_turns)
+}
+
+/// A ring that encloses a pole ends a whole turn away from where it started.
+/// Walks it over to the pole and back along the pole, so it becomes an
+/// ordinary polygon covering everything between the ring and the pole.
+///
+/// The enclosed pole is taken to be the one in the ring's hemisphere; that's
+/// true of every real coastline we rasterise (Antarctica, the Arctic).
+fn close_around_pole(ring: &mut LineString) {
+	let (Some(&first), Some(&last)) = (ring.0.first(), ring.0.last()) else {
+		return;
+	};
+
+	let mean_latitude = ring.0.iter().map(|c| c.y).sum::<f64>() / ring.0.len() as f64;
+	let pole = if mean_latitude < 0.0 { -90.0 } else { 90.0 };
+
+	ring.0.push(Coord { x: last.x, y: pole });
+	ring.0.push(Coord { x: first.x, y: pole });
+	ring.0.push(first);
+}
+
+fn map_bounds() -> Rect {
+	Rect::new(coord! { x: -HALF_TURN, y: -90.0 }, coord! { x: HALF_TURN, y: 90.0 })
+}
+
+/// The closest multiple of 360° to `longitude`, so that subtracting it brings
+/// `longitude` within -180°..180°.
+fn nearest_turn(longitude: f64) -> f64 { (longitude / FULL_TURN).round() * FULL_TURN }
+
+fn longitude_center(line_string: &LineString) -> f64 {
+	match line_string.bounding_rect() {
+		Some(bounds) => bounds.center().x,
+		None => 0.0,
+	}
+}
+
+fn shift_longitudes(line_string: &LineString, offset: f64) -> LineString {
+	line_string.coords().map(|c| Coord { x: c.x + offset, y: c.y }).collect()
+}
+
+/// The offsets that bring each -180°..180° window `line_string` reaches into
+/// back onto the map.
+fn window_offsets(line_string: &LineString) -> impl Iterator<Item = f64> {
+	let (first_window, last_window) = match line_string.bounding_rect() {
+		// Window `k` covers longitudes `360k - 180..360k + 180`
+		Some(bounds) => (
+			((bounds.min().x + HALF_TURN) / FULL_TURN).floor() as i32,
+			((bounds.max().x + HALF_TURN) / FULL_TURN).ceil() as i32 - 1,
+		),
+		None => (0, -1),
+	};
+	(first_window..=last_window.max(first_window - 1)).map(|k| -f64::from(k) * FULL_TURN)
+}
+
+/// Splits an open line into the runs that lie within -180°..180°
+fn clip_line_string(line_string: &LineString) -> Vec<LineString> {
+	let mut runs = Vec::new();
+	let mut run: Vec<Coord> = Vec::new();
+	let on_map = |c: Coord| (-HALF_TURN..=HALF_TURN).contains(&c.x);
+
+	for line in line_string.lines() {
+		let (mut start, mut end) = (line.start, line.end);
+		for edge in [-HALF_TURN, HALF_TURN] {
+			if (start.x - edge) * (end.x - edge) < 0.0 {
+				let at_edge = crossing_x(start, end, edge);
+				if on_map(start) {
+					end = at_edge;
+				} else {
+					start = at_edge;
+				}
+			}
+		}
+
+		if !on_map(start) || !on_map(end) {
+			continue;
+		}
+		if run.last() != Some(&start) {
+			if run.len() >= 2 {
+				runs.push(LineString(std::mem::take(&mut run)));
+			}
+			run = vec![start];
+		}
+		run.push(end);
+	}
+
+	if run.len() >= 2 {
+		runs.push(LineString(run));
+	}
+	runs
+}
+
+#[cfg(test)]
+mod tests {
+	use geo::polygon;
+
+	use super::*;
+	use crate::export::geometry_map::{GeometryUniverse, IntoGeometryMap, Properties};
+
+	// 10° per pixel: column `x` covers longitudes -180 + 10x..-170 + 10x, and
+	// row `y` covers latitudes -90 + 10y..-80 + 10y
+	const WIDTH: usize = 36;
+	const HEIGHT: usize = 18;
+
+	fn rasterise(geometry: Geometry) -> Vec<Vec<usize>> {
+		let mut universe = GeometryUniverse::default();
+		universe.insert(geometry, Properties::new());
+		let map = universe.into_geometry_map(WIDTH, HEIGHT);
+		map.map.rows.into_iter().map(|row| row.columns).collect()
+	}
+
+	fn labelled_columns(row: &[usize]) -> Vec<usize> {
+		row.iter().enumerate().filter(|(_, &label)| label != 0).map(|(x, _)| x).collect()
+	}
+
+	fn ring_at_latitude(latitude: f64) -> Polygon {
+		let coords: Vec<(f64, f64)> =
+			(0..=12).map(|i| (-175.0 + 30.0 * f64::from(i % 12), latitude)).collect();
+		Polygon::new(LineString::from(coords), vec![])
+	}
+
+	#[test]
+	fn ordinary_polygon_is_unchanged() {
+		let square = polygon![
+			(x: 12.0, y: 2.0),
+			(x: 28.0, y: 2.0),
+			(x: 28.0, y: 18.0),
+			(x: 12.0, y: 18.0),
+		];
+		let pieces = split_at_antimeridian(&square.clone().into());
+		assert_eq!(pieces, vec![Geometry::Polygon(square.clone())]);
+
+		let rows = rasterise(square.into());
+		assert_eq!(labelled_columns(&rows[9]), vec![19, 20]);
+		assert_eq!(labelled_columns(&rows[10]), vec![19, 20]);
+		assert!(labelled_columns(&rows[8]).is_empty());
+		assert!(labelled_columns(&rows[11]).is_empty());
+	}
+
+	#[test]
+	fn polygon_crossing_antimeridian_is_split() {
+		// Like Fiji: 172°E to 172°W
+		let crossing = polygon![
+			(x: 172.0, y: -8.0),
+			(x: -172.0, y: -8.0),
+			(x: -172.0, y: 8.0),
+			(x: 172.0, y: 8.0),
+		];
+		let pieces = split_at_antimeridian(&crossing.clone().into());
+		assert_eq!(pieces.len(), 2);
+		for piece in pieces {
+			let bounds = piece.bounding_rect().unwrap();
+			assert!(bounds.min().x >= -180.0 && bounds.max().x <= 180.0);
+		}
+
+		let rows = rasterise(crossing.into());
+		for (y, row) in rows.iter().enumerate() {
+			if y == 8 || y == 9 {
+				assert_eq!(labelled_columns(row), vec![0, 35], "row {y}");
+			} else {
+				assert!(labelled_columns(row).is_empty(), "row {y}");
+			}
+		}
+	}
+
+	#[test]
+	fn polygon_beyond_antimeridian_is_wrapped() {
+		// Chukotka is sometimes written with longitudes past 180°
+		let beyond = polygon![
+			(x: 182.0, y: 62.0),
+			(x: 188.0, y: 62.0),
+			(x: 188.0, y: 68.0),
+			(x: 182.0, y: 68.0),
+		];
+		let rows = rasterise(beyond.into());
+		assert_eq!(labelled_columns(&rows[15]), vec![0]);
+		assert_eq!(rows.iter().map(|row| labelled_columns(row).len()).sum::<usize>(), 1);
+	}
+
+	#[test]
+	fn ring_around_south_pole_fills_to_pole() {
+		let rows = rasterise(ring_at_latitude(-75.0).into());
+		for (y, row) in rows.iter().enumerate() {
+			if y <= 1 {
+				assert_eq!(labelled_columns(row).len(), WIDTH, "row {y}");
+			} else {
+				assert!(labelled_columns(row).is_empty(), "row {y}");
+			}
+		}
+	}
+
+	#[test]
+	fn ring_around_north_pole_fills_to_pole() {
+		let rows = rasterise(ring_at_latitude(75.0).into());
+		for (y, row) in rows.iter().enumerate() {
+			if y >= HEIGHT - 2 {
+				assert_eq!(labelled_columns(row).len(), WIDTH, "row {y}");
+			} else {
+				assert!(labelled_columns(row).is_empty(), "row {y}");
+			}
+		}
+	}
+
+	#[test]
+	fn pole_polygon_with_explicit_seam_fills_to_pole() {
+		// The way Natural Earth draws Antarctica: down the seam at -180°, along
+		// the pole, and back up at 180°
+		let antarctica = polygon![
+			(x: -180.0, y: -90.0),
+			(x: -180.0, y: -75.0),
+			(x: -90.0, y: -75.0),
+			(x: 0.0, y: -75.0),
+			(x: 90.0, y: -75.0),
+			(x: 180.0, y: -75.0),
+			(x: 180.0, y: -90.0),
+		];
+		let rows = rasterise(antarctica.into());
+		for (y, row) in rows.iter().enumerate() {
+			if y <= 1 {
+				assert_eq!(labelled_columns(row).len(), WIDTH, "row {y}");
+			} else {
+				assert!(labelled_columns(row).is_empty(), "row {y}");
+			}
+		}
+	}
+
+	#[test]
+	fn hole_follows_its_exterior_across_antimeridian() {
+		let exterior = LineString::from(vec![
+			(122.0, -28.0),
+			(-122.0, -28.0),
+			(-122.0, 28.0),
+			(122.0, 28.0),
+			(122.0, -28.0),
+		]);
+		let hole = LineString::from(vec![
+			(-172.0, -18.0),
+			(-138.0, -18.0),
+			(-138.0, 18.0),
+			(-172.0, 18.0),
+			(-172.0, -18.0),
+		]);
+		let rows = rasterise(Polygon::new(exterior, vec![hole]).into());
+
+		assert_eq!(labelled_columns(&rows[6]), vec![0, 1, 2, 3, 4, 5, 30, 31, 32, 33, 34, 35]);
+		assert_eq!(labelled_columns(&rows[9]), vec![0, 4, 5, 30, 31, 32, 33, 34, 35]);
+	}
+}
@@ -0,0 +1,95 @@
+#![cfg(any(feature = "read_shapefile", feature = "read_geojson"))]
+use geo::{Coord, LineString, Polygon, Rect};
+
+/// One side of a clipping rectangle, and the coordinate it sits at
+#[derive(Copy, Clone)]
+enum Edge {
+	Left(f64),
+	Right(f64),
+	Bottom(f64),
+	Top(f64),
+}
+
+impl Edge {
+	fn contains(&self, coord: Coord) -> bool {
+		match *self {
+			Edge::Left(x) => coord.x >= x,
+			Edge::Right(x) => coord.x <= x,
+			Edge::Bottom(y) => coord.y >= y,
+			Edge::Top(y) => coord.y <= y,
+		}
+	}
+
+	fn crossing(&self, from: Coord, to: Coord) -> Coord {
+		match *self {
+			Edge::Left(x) | Edge::Right(x) => crossing_x(from, to, x),
+			Edge::Bottom(y) | Edge::Top(y) => {
+				let t = (y - from.y) / (to.y - from.y);
+				Coord { x: from.x + t * (to.x - from.x), y }
+			}
+		}
+	}
+}
+
+/// Clips the exterior and each interior of `polygon` to `bounds`. Returns
+/// `None` if nothing of the exterior is left.
+pub(crate) fn clip_polygon(polygon: &Polygon, bounds: Rect) -> Option<Polygon> {
+	let exterior = clip_ring(polygon.exterior(), bounds)?;
+	let interiors =
+		polygon.interiors().iter().filter_map(|interior| clip_ring(interior, bounds)).collect();
+	Some(Polygon::new(exterior, interiors))
+}
+
+/// Sutherland-Hodgman clipping of a closed ring to `bounds`. A rectangle is
+/// convex, so any ring clips to a single ring (possibly with zero-width spans
+/// along the edge), or to nothing.
+pub(crate) fn clip_ring(ring: &LineString, bounds: Rect) -> Option<LineString> {
+	let mut coords: Vec<Coord> = ring.0.clone();
+	if ring.is_closed() {
+		coords.pop();
+	}
+
+	let edges = [
+		Edge::Left(bounds.min().x),
+		Edge::Right(bounds.max().x),
+		Edge::Bottom(bounds.min().y),
+		Edge::Top(bounds.max().y),
+	];
+	for edge in edges {
+		coords = clip_to_edge(&coords, edge);
+	}
+
+	if coords.len() < 3 {
+		return None;
+	}
+	coords.push(coords[0]);
+	Some(LineString(coords))
+}
+
+fn clip_to_edge(coords: &[Coord], edge: Edge) -> Vec<Coord> {
+	let mut clipped = Vec::with_capacity(coords.len() + 2);
+	let Some(&last) = coords.last() else {
+		return clipped;
+	};
+
+	let mut previous = last;
+	for &current in coords {
+		match (edge.contains(previous), edge.contains(current)) {
+			(true, true) => clipped.push(current),
+			(true, false) => clipped.push(edge.crossing(previous, current)),
+			(false, true) => {
+				clipped.push(edge.crossing(previous, current));
+				clipped.push(current);
+			}
+			(false, false) => {}
+		}
+		previous = current;
+	}
+	clipped
+}
+
+/// Where the segment `from`..`to` crosses the vertical line at `x`
+pub(crate) fn crossing_x(from: Coord, to: Coord, x: f64) -> Coord {
+	let t = (x - from.x) / (to.x - from.x);
+	Coord { x, y: from.y + t * (to.y - from.y) }
+}
@@ -1,27 +1,96 @@
-#![cfg(feature = "read_shapefile")]
+#![cfg(any(feature = "read_shapefile", feature = "read_geojson"))]
 use std::collections::HashMap;
 
-use euclid::approxeq::ApproxEq;
 use euclid::{Transform2D, UnknownUnit, Vector2D};
 use geo::Geometry;
 use geo_rasterize::LabelBuilder;
+use ghg_data_core::countries::{identity_to_byte, CountryIndex};
 use image::{ImageBuffer, LumaA, Pixel};
 use itertools::Itertools;
 
+use crate::export::antimeridian::split_at_antimeridian;
 use crate::export::data_2d_statistics::Data2d;
 use crate::export::image::{PixelMappable, ToImage};
+#[cfg(feature = "read_geojson")]
+use crate::file_type::GeoJson;
+#[cfg(feature = "read_shapefile")]
 use crate::file_type::Shp;
 
 pub type Identity = usize;
 
+/// Properties that may hold a country's name, in order of preference. Natural
+/// Earth shapefiles have `NAME` and `ADMIN`, and GeoJSON often has `name`.
+const NAME_PROPERTIES: [&str; 3] = ["NAME", "ADMIN", "name"];
+
+/// Arbitrary key-value attributes attached to a piece of geometry, e.g. the
+/// country name from a shapefile record or a GeoJSON feature's `properties`
+pub type Properties = serde_json::Map<String, serde_json::Value>;
+
 /// Represents all relevant groupings of `PolygonCollection`s, each with a
 /// unique identity
 #[derive(Default)]
 pub struct GeometryUniverse {
 	geometry: HashMap<Identity, Geometry>,
+	properties: HashMap<Identity, Properties>,
 	pub(crate) max_identity: Identity,
 }
 
+impl GeometryUniverse {
+	/// Adds geometry with the next unused identity, and returns that identity
+	pub fn insert(&mut self, geometry: Geometry, properties: Properties) -> Identity {
+		let identity = self.max_identity + 1; // Never 0, that's the ocean
+		self.insert_with_identity(identity, geometry, properties);
+		identity
+	}
+
+	/// Adds geometry with a specific identity, replacing anything already
+	/// stored there
+	pub fn insert_with_identity(
+		&mut self,
+		identity: Identity,
+		geometry: Geometry,
+		properties: Properties,
+	) {
+		assert_ne!(identity, 0, "Identity 0 is reserved for the ocean");
+		self.geometry.insert(identity, geometry);
+		self.properties.insert(identity, properties);
+		self.max_identity = self.max_identity.max(identity);
+	}
+
+	pub fn geometry(&self, identity: Identity) -> Option<&Geometry> { self.geometry.get(&identity) }
+
+	pub fn properties(&self, identity: Identity) -> Option<&Properties> {
+		self.properties.get(&identity)
+	}
+
+	pub fn max_identity(&self) -> Identity { self.max_identity }
+
+	pub fn len(&self) -> usize { self.geometry.len() }
+
+	pub fn is_empty(&self) -> bool { self.geometry.is_empty() }
+
+	/// Names each identity from its properties, so the viewer can tell which
+	/// country is where on the exported map
+	pub fn country_index(&self) -> CountryIndex {
+		let names = self
+			.iter()
+			.filter_map(|(identity, _, properties)| {
+				let name = NAME_PROPERTIES.iter().find_map(|key| properties.get(*key)?.as_str())?;
+				Some((identity as u32, name.trim().to_owned()))
+			})
+			.collect();
+		CountryIndex { max_identity: self.max_identity as u32, names }
+	}
+
+	/// Iterates in order of identity, so output built from it is deterministic
+	pub fn iter(&self) -> impl Iterator<Item = (Identity, &Geometry, &Properties)> {
+		self.geometry
+			.iter()
+			.sorted_by_key(|(identity, _)| **identity)
+			.map(|(identity, geometry)| (*identity, geometry, &self.properties[identity]))
+	}
+}
+
 pub struct GeometryMap {
 	pub(crate) universe: GeometryUniverse,
 	pub(crate) map: Data2d<Identity>,
@@ -35,41 +104,133 @@ pub trait IntoGeometryMap {
 	fn into_geometry_map(self, width: usize, height: usize) -> GeometryMap;
 }
 
+#[cfg(feature = "read_shapefile")]
 impl ToGeometryUniverse for Shp<f64> {
 	fn to_geometry_universe(&self) -> GeometryUniverse {
 		let mut reader = self.reader.borrow_mut();
 
 		let mut universe = GeometryUniverse::default();
-
-		let increment = 1usize;
-		let mut identity = 1usize; // Not 0, that's the ocean
 		let mut printed_keys = false;
 
 		for shape_record in reader.iter_shapes_and_records() {
 			let (shape, record) = shape_record.expect("Failed to get shape/record");
-			universe.geometry.insert(
-				identity,
+			let properties: Properties = record
+				.into_iter()
+				.sorted_by_key(|(k, _v)| k.clone())
+				.map(|(key, value)| (key, field_value_to_json(value)))
+				.collect();
+
+			if !printed_keys {
+				for (key, value) in properties.iter() {
+					println!("  {key} = {value}");
+				}
+				printed_keys = true;
+			}
+
+			universe.insert(
 				shape
 					.try_into()
 					.expect("Failed to convert shapefile::Polygon to geo::MultiPolygon"),
+				properties,
 			);
-			identity = identity + increment;
+		}
+		universe
+	}
+}
 
-			if !printed_keys {
-				for (key, value) in record.into_iter().sorted_by_key(|(k, v)| k.clone()) {
-					println!("  {key} = {value:?}");
+#[cfg(feature = "read_shapefile")]
+fn field_value_to_json(value: shapefile::dbase::FieldValue) -> serde_json::Value {
+	use serde_json::Value;
+	use shapefile::dbase::FieldValue;
+
+	match value {
+		FieldValue::Character(Some(s)) | FieldValue::Memo(s) => Value::String(s.trim().to_owned()),
+		FieldValue::Numeric(Some(n)) | FieldValue::Currency(n) | FieldValue::Double(n) => n.into(),
+		FieldValue::Float(Some(n)) => n.into(),
+		FieldValue::Integer(n) => n.into(),
+		FieldValue::Logical(Some(b)) => b.into(),
+		FieldValue::Date(Some(d)) => Value::String(d.to_string()),
+		FieldValue::DateTime(dt) => Value::String(format!("{dt:?}")),
+		FieldValue::Character(None)
+		| FieldValue::Numeric(None)
+		| FieldValue::Float(None)
+		| FieldValue::Logical(None)
+		| FieldValue::Date(None) => Value::Null,
+	}
+}
+
+/// Features with a numeric `id` keep it as their identity, so a universe
+/// written with `ToGeoJson` reads back with the same identities. Any others
+/// are numbered after the largest of those.
+#[cfg(feature = "read_geojson")]
+impl ToGeometryUniverse for GeoJson<f64> {
+	fn to_geometry_universe(&self) -> GeometryUniverse {
+		let mut universe = GeometryUniverse::default();
+		let mut unnumbered = Vec::new();
+
+		for (index, feature) in self.contents.features.iter().enumerate() {
+			let Some(geometry) = &feature.geometry else {
+				println!("Skipping feature {index}: it has no geometry");
+				continue;
+			};
+			let geometry: Geometry = Geometry::try_from(&geometry.value).unwrap_or_else(|e| {
+				panic!("Failed to convert feature {index} to geo::Geometry: {e}")
+			});
+			let properties = feature.properties.clone().unwrap_or_default();
+
+			match feature_identity(feature) {
+				Some(identity) if universe.geometry(identity).is_none() => {
+					universe.insert_with_identity(identity, geometry, properties)
 				}
-				printed_keys = true;
+				_ => unnumbered.push((geometry, properties)),
 			}
 		}
-		universe.max_identity = identity - increment;
+
+		for (geometry, properties) in unnumbered {
+			universe.insert(geometry, properties);
+		}
 		universe
 	}
 }
 
+#[cfg(feature = "read_geojson")]
+fn feature_identity(feature: &geojson::Feature) -> Option<Identity> {
+	match &feature.id {
+		Some(geojson::feature::Id::Number(n)) => {
+			n.as_u64().filter(|&n| n != 0).map(|n| n as Identity)
+		}
+		_ => None,
+	}
+}
+
+#[cfg(feature = "read_geojson")]
+pub trait ToGeoJson {
+	fn to_geojson(&self) -> geojson::FeatureCollection;
+}
+
+/// Each piece of geometry becomes a feature whose `id` is its identity, so the
+/// values in a rasterised `GeometryMap` can be traced back to their features
+#[cfg(feature = "read_geojson")]
+impl ToGeoJson for GeometryUniverse {
+	fn to_geojson(&self) -> geojson::FeatureCollection {
+		let features = self
+			.iter()
+			.map(|(identity, geometry, properties)| geojson::Feature {
+				bbox: None,
+				geometry: Some(geojson::Geometry::new(geometry.into())),
+				id: Some(geojson::feature::Id::Number(identity.into())),
+				properties: Some(properties.clone()),
+				foreign_members: None,
+			})
+			.collect();
+
+		geojson::FeatureCollection { bbox: None, features, foreign_members: None }
+	}
+}
+
 type Transform = Transform2D<f64, UnknownUnit, UnknownUnit>;
 
-fn get_longitude_latitude_transform(width: usize, height: usize) -> Transform {
+pub(crate) fn get_longitude_latitude_transform(width: usize, height: usize) -> Transform {
 	let x_translate = 180.0;
 	let y_translate = 90.0;
 
@@ -92,11 +253,12 @@ impl IntoGeometryMap for GeometryUniverse {
 			.build()
 			.expect("Failed to build rasterizer");
 
-		for (identity, polygon) in self.geometry.iter() {
-			rasterizer.rasterize(polygon, *identity).expect(
-				format!("Failed to rasterize Polygon with identity {identity:?}: {polygon:?}")
-					.as_str(),
-			)
+		for (identity, polygon, _) in self.iter() {
+			for piece in split_at_antimeridian(polygon) {
+				rasterizer.rasterize(&piece, identity).unwrap_or_else(|_| {
+					panic!("Failed to rasterize Polygon with identity {identity:?}: {piece:?}")
+				})
+			}
 		}
 
 		let pixels = rasterizer.finish();
@@ -107,17 +269,18 @@ impl IntoGeometryMap for GeometryUniverse {
 
 impl PixelMappable<Identity> for GeometryMap {
 	fn get_pixel_map(&self) -> Box<dyn Fn(&Identity) -> u8> {
-		assert!(self.universe.max_identity < 256);
-
-		let range = self.universe.max_identity as f64;
-		Box::new(move |value: &Identity| {
-			let val_f = *value as f64;
-			let portion = val_f / range;
-			(255.0 * portion) as u8
-		})
+		identity_pixel_map(self.universe.max_identity)
 	}
 }
 
+/// Spreads identities `0..=max_identity` evenly over the 8-bit channel, as
+/// `CountryIndex` reads them back
+pub(crate) fn identity_pixel_map(max_identity: Identity) -> Box<dyn Fn(&Identity) -> u8> {
+	assert!(max_identity < 256);
+
+	Box::new(move |value: &Identity| identity_to_byte(*value as u32, max_identity as u32))
+}
+
 impl ToImage<LumaA<u8>> for GeometryMap {
 	type Data = Identity;
 
@@ -131,16 +294,15 @@ impl ToImage<LumaA<u8>> for GeometryMap {
 		let pixel_map = PixelMappable::<Identity>::get_pixel_map(self);
 		let mut output_buffer = Vec::with_capacity(self.width() * self.height() * NUM_CHANNELS);
 
-		let outline_kernel = [[0.0, 0.2, 0.0], [0.2, 0.2, 0.2], [0.0, 0.2, 0.0]];
+		let (width, height) = (self.width(), self.height());
+		let label_at = |x: usize, y: usize| self.map.rows[y].columns[x];
 
 		for (row_index, row) in self.map.rows.iter().enumerate().rev() {
 			for (col_index, val) in row.columns.iter().enumerate() {
-				let within_country =
-					self.sum_kernel_to_center(&outline_kernel, col_index, row_index);
-				if within_country {
-					output_buffer.push(0u8);
-				} else {
+				if on_border(label_at, width, height, col_index, row_index) {
 					output_buffer.push(255u8);
+				} else {
+					output_buffer.push(0u8);
 				}
 				output_buffer.push(pixel_map(val));
 			}
@@ -151,30 +313,105 @@ impl ToImage<LumaA<u8>> for GeometryMap {
 	}
 }
 
-impl GeometryMap {
-	fn sum_kernel_to_center<const N: usize>(
-		&self,
-		kernel: &[[f32; N]; N],
-		center_x: usize,
-		center_y: usize,
-	) -> bool {
-		let center_value = self.map.rows[center_y].columns[center_x];
-		let kernel_radius = (N - 1) / 2;
-		let kernel_start_y = center_y.saturating_sub(kernel_radius);
-		let kernel_start_x = center_x.saturating_sub(kernel_radius);
-
-		let mut sum = 0.0;
-
-		for (map_row, kernel_row) in
-			self.map.rows.iter().skip(kernel_start_y).take(N).zip(kernel.iter())
-		{
-			for (map_val, kernel_val) in
-				map_row.columns.iter().skip(kernel_start_x).take(N).zip(kernel_row.iter())
-			{
-				sum += (*map_val as f32) * *kernel_val;
-			}
+/// Whether the pixel at `(x, y)` touches a different identity. Only the four
+/// direct neighbours count, and neighbours off the edge of the map are
+/// ignored, so no outline is drawn along the antimeridian or the poles.
+pub(crate) fn on_border(
+	label_at: impl Fn(usize, usize) -> Identity,
+	width: usize,
+	height: usize,
+	x: usize,
+	y: usize,
+) -> bool {
+	let center = label_at(x, y);
+	let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+	neighbours
+		.into_iter()
+		.filter(|&(neighbour_x, neighbour_y)| neighbour_x < width && neighbour_y < height)
+		.any(|(neighbour_x, neighbour_y)| label_at(neighbour_x, neighbour_y) != center)
+}
+
+#[cfg(all(test, feature = "read_geojson"))]
+mod tests {
+	use geo::{polygon, MultiPolygon};
+
+	use super::*;
+
+	fn square(x: f64, y: f64, size: f64) -> Geometry {
+		Geometry::MultiPolygon(MultiPolygon(vec![polygon![
+			(x: x, y: y),
+			(x: x + size, y: y),
+			(x: x + size, y: y + size),
+			(x: x, y: y + size),
+		]]))
+	}
+
+	#[test]
+	fn geojson_round_trip() {
+		let mut universe = GeometryUniverse::default();
+		let mut properties = Properties::new();
+		properties.insert("NAME".to_owned(), "Squareland".into());
+		universe.insert_with_identity(3, square(0.0, 0.0, 10.0), properties.clone());
+		universe.insert(square(20.0, 20.0, 5.0), Properties::new());
+
+		let text = universe.to_geojson().to_string();
+		let read_back = GeoJson::<f64>::parse(text.as_str()).unwrap().to_geometry_universe();
+
+		assert_eq!(read_back.len(), 2);
+		assert_eq!(read_back.max_identity(), 4);
+		assert_eq!(read_back.properties(3), Some(&properties));
+		assert_eq!(read_back.geometry(3), universe.geometry(3));
+		assert_eq!(read_back.geometry(4), universe.geometry(4));
+	}
+
+	#[test]
+	fn country_index_names_identities() {
+		let mut universe = GeometryUniverse::default();
+		let named = |key: &str, name: &str| {
+			let mut properties = Properties::new();
+			properties.insert(key.to_owned(), name.into());
+			properties
+		};
+		universe.insert(square(0.0, 0.0, 1.0), named("ADMIN", "Firstland"));
+		universe.insert(square(2.0, 0.0, 1.0), Properties::new());
+		universe.insert(square(4.0, 0.0, 1.0), named("name", " Thirdland "));
+
+		let index = universe.country_index();
+		assert_eq!(index.max_identity, 3);
+		assert_eq!(index.names.len(), 2);
+		assert_eq!(index.name_of_byte(identity_pixel_map(3)(&1)), Some("Firstland"));
+		assert_eq!(index.names.get(&3).map(String::as_str), Some("Thirdland"));
+	}
+
+	#[test]
+	fn geojson_without_ids_is_numbered_from_one() {
+		let text = r#"{
+			"type": "FeatureCollection",
+			"features": [
+				{"type": "Feature", "properties": {"NAME": "A"},
+				 "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}},
+				{"type": "Feature", "properties": null,
+				 "geometry": {"type": "Polygon", "coordinates": [[[2, 2], [3, 2], [3, 3], [2, 2]]]}}
+			]
+		}"#;
+		let universe = GeoJson::<f64>::parse(text).unwrap().to_geometry_universe();
+
+		let identities: Vec<Identity> = universe.iter().map(|(identity, _, _)| identity).collect();
+		assert_eq!(identities, vec![1, 2]);
+		assert_eq!(universe.properties(1).unwrap()["NAME"], "A");
+		assert!(universe.properties(2).unwrap().is_empty());
+	}
+
+	#[test]
+	fn geojson_can_be_rasterised() {
+		let text = r#"{"type": "Feature", "id": 7, "properties": {},
+			"geometry": {"type": "Polygon",
+				"coordinates": [[[-170, -80], [-10, -80], [-10, 80], [-170, 80], [-170, -80]]]}}"#;
+		let map =
+			GeoJson::<f64>::parse(text).unwrap().to_geometry_universe().into_geometry_map(4, 2);
+
+		for row in map.map.rows.iter() {
+			assert_eq!(row.columns, vec![7, 7, 0, 0]);
 		}
-		let eps: f32 = 0.1 / 255.0;
-		sum.approx_eq_eps(&(center_value as f32), &eps)
 	}
 }
@@ -0,0 +1,274 @@
+#![cfg(any(feature = "read_shapefile", feature = "read_geojson"))]
+//! A full-resolution country map is 21600x10800 identities, several gigabytes
+//! as one `GeometryMap`. `TiledGeometryMap` keeps only the geometry, and
+//! rasterises each requested tile in bands of rows, in parallel. Memory use is
+//! bounded by the tile being produced plus one band per worker.
+
+use euclid::Point2D;
+use geo::{coord, BoundingRect, Geometry, MapCoords, Rect};
+use geo_rasterize::LabelBuilder;
+use image::{ImageBuffer, LumaA};
+use ndarray::Array2;
+use rayon::prelude::*;
+
+use crate::export::antimeridian::split_at_antimeridian;
+use crate::export::clip::clip_polygon;
+use crate::export::geometry_map::{
+	get_longitude_latitude_transform, identity_pixel_map, on_border, GeometryUniverse, Identity,
+};
+
+/// Rows rasterised at once by each worker
+const BAND_ROWS: usize = 256;
+
+/// Extra pixels rasterised around each band. The border test needs one;
+/// beyond that, clipping to the raster leaves zero-width spans along its edge,
+/// which geo-rasterize burns into the outermost pixels.
+const BAND_MARGIN: usize = 3;
+
+/// A piece of geometry in map pixel space (row 0 at latitude -90), already
+/// split at the antimeridian
+struct Piece {
+	identity: Identity,
+	geometry: Geometry,
+	bounds: Rect,
+}
+
+pub struct TiledGeometryMap {
+	pieces: Vec<Piece>,
+	max_identity: Identity,
+	width: usize,
+	height: usize,
+}
+
+pub trait IntoTiledGeometryMap {
+	fn into_tiled_geometry_map(self, width: usize, height: usize) -> TiledGeometryMap;
+}
+
+impl IntoTiledGeometryMap for GeometryUniverse {
+	fn into_tiled_geometry_map(self, width: usize, height: usize) -> TiledGeometryMap {
+		let transform = get_longitude_latitude_transform(width, height);
+
+		// Same order as `into_geometry_map`, so overlaps resolve the same way
+		let pieces = self
+			.iter()
+			.flat_map(|(identity, geometry, _)| {
+				split_at_antimeridian(geometry).into_iter().map(move |piece| (identity, piece))
+			})
+			.filter_map(|(identity, piece)| {
+				let geometry = piece.map_coords(|c| {
+					let point = transform.transform_point(Point2D::new(c.x, c.y));
+					coord! { x: point.x, y: point.y }
+				});
+				let bounds = geometry.bounding_rect()?;
+				Some(Piece { identity, geometry, bounds })
+			})
+			.collect();
+
+		TiledGeometryMap { pieces, max_identity: self.max_identity, width, height }
+	}
+}
+
+impl TiledGeometryMap {
+	pub fn width(&self) -> usize { self.width }
+
+	pub fn height(&self) -> usize { self.height }
+
+	/// Produces the same pixels as the matching part of
+	/// `GeometryMap::to_image`, with `(x, y)` the top-left corner in image
+	/// space (row 0 at latitude 90).
+	pub fn tile_image(
+		&self,
+		x: usize,
+		y: usize,
+		width: usize,
+		height: usize,
+	) -> ImageBuffer<LumaA<u8>, Vec<u8>> {
+		assert!(
+			x + width <= self.width && y + height <= self.height,
+			"Tile {width}x{height} at ({x}, {y}) is outside the {}x{} map",
+			self.width,
+			self.height
+		);
+
+		let band_starts: Vec<usize> = (0..height).step_by(BAND_ROWS).collect();
+		let bands: Vec<Vec<u8>> = band_starts
+			.into_par_iter()
+			.map(|band_y| self.band_pixels(x, y + band_y, width, BAND_ROWS.min(height - band_y)))
+			.collect();
+
+		ImageBuffer::from_raw(width as u32, height as u32, bands.concat())
+			.expect("Failed to create tile image!")
+	}
+
+	fn band_pixels(&self, x: usize, image_y: usize, width: usize, height: usize) -> Vec<u8> {
+		const NUM_CHANNELS: usize = 2;
+
+		// Image rows run north to south, map rows south to north
+		let map_y = self.height - image_y - height;
+
+		let origin_x = x as isize - BAND_MARGIN as isize;
+		let origin_y = map_y as isize - BAND_MARGIN as isize;
+		let labels =
+			self.rasterise(origin_x, origin_y, width + 2 * BAND_MARGIN, height + 2 * BAND_MARGIN);
+		let label_at = |map_x: usize, map_y: usize| {
+			labels[[(map_y as isize - origin_y) as usize, (map_x as isize - origin_x) as usize]]
+		};
+
+		let pixel_map = identity_pixel_map(self.max_identity);
+		let mut output_buffer = Vec::with_capacity(width * height * NUM_CHANNELS);
+
+		for row_index in (map_y..map_y + height).rev() {
+			for col_index in x..x + width {
+				if on_border(label_at, self.width, self.height, col_index, row_index) {
+					output_buffer.push(255u8);
+				} else {
+					output_buffer.push(0u8);
+				}
+				output_buffer.push(pixel_map(&label_at(col_index, row_index)));
+			}
+		}
+
+		output_buffer
+	}
+
+	/// Rasterises the `width`x`height` window of map pixels starting at
+	/// `(origin_x, origin_y)`, which may hang off the edge of the map
+	fn rasterise(
+		&self,
+		origin_x: isize,
+		origin_y: isize,
+		width: usize,
+		height: usize,
+	) -> Array2<Identity> {
+		let window = Rect::new(
+			coord! { x: origin_x as f64, y: origin_y as f64 },
+			coord! { x: (origin_x + width as isize) as f64, y: (origin_y + height as isize) as f64 },
+		);
+		let transform = euclid::Transform2D::translation(-window.min().x, -window.min().y);
+
+		let mut rasterizer = LabelBuilder::<Identity>::background(0u8.into())
+			.width(width)
+			.height(height)
+			.geo_to_pix(transform)
+			.build()
+			.expect("Failed to build rasterizer");
+
+		let overlapping = self.pieces.iter().filter(|piece| {
+			piece.bounds.min().x < window.max().x
+				&& piece.bounds.max().x > window.min().x
+				&& piece.bounds.min().y < window.max().y
+				&& piece.bounds.max().y > window.min().y
+		});
+
+		for piece in overlapping {
+			// geo-rasterize doesn't clip horizontal edges that run off the raster
+			let clipped = match &piece.geometry {
+				Geometry::Polygon(polygon) => clip_polygon(polygon, window).map(Geometry::from),
+				other => Some(other.clone()),
+			};
+			if let Some(clipped) = clipped {
+				rasterizer.rasterize(&clipped, piece.identity).unwrap_or_else(|_| {
+					panic!("Failed to rasterize piece of identity {:?}", piece.identity)
+				})
+			}
+		}
+
+		rasterizer.finish()
+	}
+}
+
+#[cfg(all(test, feature = "read_geojson"))]
+mod tests {
+	use geo::polygon;
+	use image::GenericImageView;
+
+	use super::*;
+	use crate::export::geometry_map::{IntoGeometryMap, Properties};
+	use crate::export::image::ToImage;
+
+	const WIDTH: usize = 72;
+	const HEIGHT: usize = 36;
+
+	fn universe() -> GeometryUniverse {
+		let mut universe = GeometryUniverse::default();
+		// Concave, and crossing every tile boundary
+		universe.insert(
+			polygon![
+				(x: -100.3, y: -60.7),
+				(x: 95.1, y: -58.2),
+				(x: 97.6, y: 61.9),
+				(x: -98.2, y: 59.4),
+				(x: -97.7, y: 31.3),
+				(x: 42.9, y: 28.8),
+				(x: 43.3, y: -27.6),
+				(x: -99.1, y: -29.2),
+			]
+			.into(),
+			Properties::new(),
+		);
+		// With a hole
+		universe.insert(
+			polygon!(
+				exterior: [
+					(x: -60.9, y: -10.3),
+					(x: 20.2, y: -11.1),
+					(x: 21.7, y: 12.6),
+					(x: -61.4, y: 11.8),
+				],
+				interiors: [[
+					(x: -20.3, y: -4.1),
+					(x: 1.9, y: -3.8),
+					(x: 2.2, y: 4.7),
+					(x: -19.6, y: 5.2),
+				]],
+			)
+			.into(),
+			Properties::new(),
+		);
+		// Across the antimeridian
+		universe.insert(
+			polygon![
+				(x: 150.3, y: 40.2),
+				(x: -140.6, y: 41.1),
+				(x: -141.2, y: 70.7),
+				(x: 151.4, y: 69.3),
+			]
+			.into(),
+			Properties::new(),
+		);
+		universe
+	}
+
+	#[test]
+	fn tiles_match_whole_map() {
+		let whole = universe().into_geometry_map(WIDTH, HEIGHT).to_image();
+		let tiled = universe().into_tiled_geometry_map(WIDTH, HEIGHT);
+
+		let (columns, rows) = (4, 2);
+		let (tile_width, tile_height) = (WIDTH / columns, HEIGHT / rows);
+		for column in 0..columns {
+			for row in 0..rows {
+				let (x, y) = (column * tile_width, row * tile_height);
+				let tile = tiled.tile_image(x, y, tile_width, tile_height);
+				let expected = whole
+					.view(x as u32, y as u32, tile_width as u32, tile_height as u32)
+					.to_image();
+				assert_eq!(tile, expected, "Tile {column}.{row} differs");
+			}
+		}
+	}
+
+	#[test]
+	fn whole_map_as_one_tile() {
+		let whole = universe().into_geometry_map(WIDTH, HEIGHT).to_image();
+		let tiled = universe().into_tiled_geometry_map(WIDTH, HEIGHT);
+
+		assert_eq!(tiled.tile_image(0, 0, WIDTH, HEIGHT), whole);
+	}
+
+	#[test]
+	#[should_panic]
+	fn tile_outside_map() {
+		universe().into_tiled_geometry_map(WIDTH, HEIGHT).tile_image(60, 0, 20, 10);
+	}
+}
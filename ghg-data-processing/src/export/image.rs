@@ -1,3 +1,4 @@
+use ghg_data_core::metadata::ChannelMetadata;
 use image::{
 	GrayAlphaImage, GrayImage, ImageBuffer, Luma, LumaA, Pixel, Rgb, RgbImage, Rgba, RgbaImage,
 };
@@ -24,12 +25,8 @@ pub trait PixelMappable<T> {
 
 impl PixelMappable<f64> for Data2dStatistics<f64> {
 	fn get_pixel_map(&self) -> Box<dyn Fn(&f64) -> u8> {
-		let range = self.max.unwrap() - self.min.unwrap();
-		let offset = self.min.unwrap();
-		Box::new(move |value: &f64| {
-			let portion = (*value - offset) / range;
-			(255.0 * portion) as u8
-		})
+		let channel = ChannelMetadata { min: self.min.unwrap(), max: self.max.unwrap() };
+		Box::new(move |value: &f64| channel.encode(*value))
 	}
 }
 
@@ -1,3 +1,8 @@
+pub mod antimeridian;
+mod clip;
 pub mod data_2d_statistics;
 pub mod geometry_map;
+pub mod geometry_tiles;
 pub mod image;
+pub mod pyramid;
+pub mod tiling;
@@ -0,0 +1,210 @@
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use image::{ImageBuffer, ImageResult, Pixel, PixelWithColorType};
+use rayon::prelude::*;
+
+/// WebGL2 guarantees a `MAX_TEXTURE_SIZE` of at least this, so a pyramid
+/// stops once a level fits within it
+pub const SMALLEST_GUARANTEED_TEXTURE_SIZE: u32 = 2048;
+
+/// How each channel is reduced when halving an image for the next mip level
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Downsample {
+	/// For continuous data, e.g. temperature or elevation
+	Average,
+	/// For categorical data, where a blend of two values is meaningless, e.g.
+	/// country identities. Ties go to the first value in row-major order.
+	Mode,
+	/// For sparse features that shouldn't disappear at lower resolutions,
+	/// e.g. borders
+	Max,
+}
+
+impl Downsample {
+	fn reduce(self, block: &[u8]) -> u8 {
+		match self {
+			Downsample::Average => {
+				let sum: u32 = block.iter().map(|&v| v as u32).sum();
+				let count = block.len() as u32;
+				((sum + count / 2) / count) as u8
+			}
+			Downsample::Mode => {
+				// Blocks are tiny, so counting in place beats a histogram
+				let count = |value: u8| block.iter().filter(|&&v| v == value).count();
+				let mut best = (block[0], count(block[0]));
+				for &value in &block[1..] {
+					let value_count = count(value);
+					if value_count > best.1 {
+						best = (value, value_count);
+					}
+				}
+				best.0
+			}
+			Downsample::Max => block.iter().copied().max().unwrap_or_default(),
+		}
+	}
+}
+
+/// Halves `image` in each dimension (rounding down, but never below 1).
+/// `downsample` has one entry per channel, or a single entry used for every
+/// channel.
+pub fn downsample<P: Pixel<Subpixel = u8> + Sync>(
+	image: &ImageBuffer<P, Vec<u8>>,
+	downsample: &[Downsample],
+) -> ImageBuffer<P, Vec<u8>> {
+	let num_channels = P::CHANNEL_COUNT as usize;
+	assert!(
+		downsample.len() == 1 || downsample.len() == num_channels,
+		"Expected 1 or {num_channels} downsampling methods, got {}",
+		downsample.len()
+	);
+	let method = |channel: usize| downsample[channel.min(downsample.len() - 1)];
+
+	let (width, height) = image.dimensions();
+	let (output_width, output_height) = ((width / 2).max(1), (height / 2).max(1));
+
+	let mut output_buffer =
+		vec![0u8; output_width as usize * output_height as usize * num_channels];
+	output_buffer.par_chunks_mut(output_width as usize * num_channels).enumerate().for_each(
+		|(y, output_row)| {
+			let source_rows = source_span(y as u32, output_height, height);
+			let mut block = Vec::with_capacity(9);
+
+			for x in 0..output_width {
+				let source_columns = source_span(x, output_width, width);
+				for channel in 0..num_channels {
+					block.clear();
+					for source_y in source_rows.clone() {
+						for source_x in source_columns.clone() {
+							block.push(image.get_pixel(source_x, source_y).channels()[channel]);
+						}
+					}
+					output_row[x as usize * num_channels + channel] =
+						method(channel).reduce(&block);
+				}
+			}
+		},
+	);
+
+	ImageBuffer::from_raw(output_width, output_height, output_buffer)
+		.expect("Failed to create downsampled image!")
+}
+
+/// The source pixels covered by output pixel `index`, when `source_size`
+/// pixels are reduced to `output_size`
+fn source_span(index: u32, output_size: u32, source_size: u32) -> Range<u32> {
+	let start = index as u64 * source_size as u64 / output_size as u64;
+	let end = (index as u64 + 1) * source_size as u64 / output_size as u64;
+	start as u32..end as u32
+}
+
+/// Saves `image` as level 0 of a mip pyramid at `{root}/{level}/{name}.png`,
+/// the layout `biggest_mipmap_level` expects, followed by each halved level
+/// until one fits `SMALLEST_GUARANTEED_TEXTURE_SIZE`
+pub fn save_pyramid<P: Pixel<Subpixel = u8> + PixelWithColorType + Sync>(
+	image: &ImageBuffer<P, Vec<u8>>,
+	root: &Path,
+	name: &str,
+	downsample: &[Downsample],
+) -> ImageResult<Vec<PathBuf>> {
+	save_pyramid_from_level(0, image, root, name, downsample)
+}
+
+/// Like `save_pyramid`, for when `image` is already `first_level`, e.g. if
+/// level 0 is too big to hold in memory and was written as tiles
+pub fn save_pyramid_from_level<P: Pixel<Subpixel = u8> + PixelWithColorType + Sync>(
+	first_level: usize,
+	image: &ImageBuffer<P, Vec<u8>>,
+	root: &Path,
+	name: &str,
+	downsample_methods: &[Downsample],
+) -> ImageResult<Vec<PathBuf>> {
+	let mut saved = Vec::new();
+	let mut level = first_level;
+	let mut level_image = image.clone();
+
+	loop {
+		let level_root = root.join(level.to_string());
+		fs::create_dir_all(&level_root)?;
+
+		let output_name = level_root.join(format!("{name}.png"));
+		level_image.save(&output_name)?;
+		println!("Saved level {level}: {:?}", output_name);
+		saved.push(output_name);
+
+		let (width, height) = level_image.dimensions();
+		if width.max(height) <= SMALLEST_GUARANTEED_TEXTURE_SIZE {
+			return Ok(saved);
+		}
+
+		level_image = downsample(&level_image, downsample_methods);
+		level += 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use image::{GrayImage, LumaA};
+
+	use super::*;
+
+	#[test]
+	fn average_rounds_to_nearest() {
+		let image = GrayImage::from_raw(2, 2, vec![0, 1, 1, 1]).unwrap();
+		assert_eq!(downsample(&image, &[Downsample::Average]).into_raw(), vec![1]);
+	}
+
+	#[test]
+	fn mode_keeps_most_common_value() {
+		let image = GrayImage::from_raw(4, 2, vec![7, 3, 5, 5, 3, 3, 5, 9]).unwrap();
+		assert_eq!(downsample(&image, &[Downsample::Mode]).into_raw(), vec![3, 5]);
+	}
+
+	#[test]
+	fn mode_ties_go_to_first_value() {
+		let image = GrayImage::from_raw(2, 2, vec![4, 2, 2, 4]).unwrap();
+		assert_eq!(downsample(&image, &[Downsample::Mode]).into_raw(), vec![4]);
+	}
+
+	#[test]
+	fn max_keeps_thin_features() {
+		let image = GrayImage::from_raw(4, 2, vec![0, 0, 0, 255, 0, 0, 0, 0]).unwrap();
+		assert_eq!(downsample(&image, &[Downsample::Max]).into_raw(), vec![0, 255]);
+	}
+
+	#[test]
+	fn methods_apply_per_channel() {
+		let image = ImageBuffer::<LumaA<u8>, _>::from_raw(
+			2,
+			2,
+			vec![255, 1, 0, 2, 0, 2, 0, 3], // Border, identity
+		)
+		.unwrap();
+		let downsampled = downsample(&image, &[Downsample::Max, Downsample::Mode]);
+		assert_eq!(downsampled.into_raw(), vec![255, 2]);
+	}
+
+	#[test]
+	fn odd_sizes_cover_every_source_pixel() {
+		let image = GrayImage::from_raw(3, 1, vec![0, 0, 9]).unwrap();
+		let downsampled = downsample(&image, &[Downsample::Max]);
+		assert_eq!(downsampled.dimensions(), (1, 1));
+		assert_eq!(downsampled.into_raw(), vec![9]);
+	}
+
+	#[test]
+	fn pyramid_stops_at_guaranteed_texture_size() {
+		let temp = tempfile::tempdir().unwrap();
+		let root = temp.path();
+		let image = GrayImage::new(SMALLEST_GUARANTEED_TEXTURE_SIZE * 4, 2);
+
+		let saved = save_pyramid(&image, root, "full", &[Downsample::Average]).unwrap();
+
+		let expected: Vec<PathBuf> =
+			(0..=2).map(|level| root.join(format!("{level}/full.png"))).collect();
+		assert_eq!(saved, expected);
+		assert!(expected.iter().all(|path| path.exists()));
+	}
+}
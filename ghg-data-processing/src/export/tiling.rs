@@ -0,0 +1,146 @@
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use ghg_data_core::tiles::{TileLevel, TileSet};
+use image::imageops::crop_imm;
+use image::{ImageBuffer, ImageResult, Pixel, PixelWithColorType};
+use rayon::prelude::*;
+
+use crate::export::pyramid::{downsample, Downsample};
+
+/// Small enough that the viewer only fetches roughly what's on screen
+pub const TILE_SIZE: u32 = 512;
+
+/// Saves the tiles covered by `image` at `{root}/{level}/{x}/{y}.png`.
+/// `image` can be a block of the level, starting at tile `(first_column,
+/// first_row)`, for when a whole level is too big to hold in memory; its width
+/// and height must be multiples of `tile_size`, unless it's at the level's
+/// right or bottom edge.
+pub fn save_tile_block<P: Pixel<Subpixel = u8> + PixelWithColorType + Sync + 'static>(
+	image: &ImageBuffer<P, Vec<u8>>,
+	root: &Path,
+	level: usize,
+	(first_column, first_row): (u32, u32),
+	tile_size: u32,
+) -> ImageResult<()> {
+	let block = TileLevel::new(level, image.width(), image.height(), tile_size);
+
+	(0..block.columns)
+		.flat_map(|x| (0..block.rows).map(move |y| (x, y)))
+		.collect::<Vec<_>>()
+		.into_par_iter()
+		.try_for_each(|(x, y)| {
+			let (left, top, width, height) = block.tile_bounds(x, y, tile_size);
+			let output_name = root.join(TileSet::tile_path(level, first_column + x, first_row + y));
+			fs::create_dir_all(output_name.parent().unwrap())?;
+			crop_imm(image, left, top, width, height).to_image().save(output_name)
+		})
+}
+
+/// Saves all of `image` as tiles of `level`
+pub fn save_tiles<P: Pixel<Subpixel = u8> + PixelWithColorType + Sync + 'static>(
+	image: &ImageBuffer<P, Vec<u8>>,
+	root: &Path,
+	level: usize,
+	tile_size: u32,
+) -> ImageResult<TileLevel> {
+	save_tile_block(image, root, level, (0, 0), tile_size)?;
+	println!("Saved level {level} tiles: {:?}", root.join(level.to_string()));
+	Ok(TileLevel::new(level, image.width(), image.height(), tile_size))
+}
+
+/// Saves `image` as tiles of `first_level`, then each halved level until one
+/// fits in a single tile. Returns the levels saved, for the descriptor.
+pub fn save_tiled_pyramid_from_level<
+	P: Pixel<Subpixel = u8> + PixelWithColorType + Sync + 'static,
+>(
+	first_level: usize,
+	image: &ImageBuffer<P, Vec<u8>>,
+	root: &Path,
+	downsample_methods: &[Downsample],
+	tile_size: u32,
+) -> ImageResult<Vec<TileLevel>> {
+	let mut levels = vec![save_tiles(image, root, first_level, tile_size)?];
+	let mut level_image = image.clone();
+
+	while level_image.width().max(level_image.height()) > tile_size {
+		level_image = downsample(&level_image, downsample_methods);
+		levels.push(save_tiles(&level_image, root, first_level + levels.len(), tile_size)?);
+	}
+
+	Ok(levels)
+}
+
+/// Tiles `image` from level 0 down, and saves the descriptor
+pub fn save_tiled_pyramid<P: Pixel<Subpixel = u8> + PixelWithColorType + Sync + 'static>(
+	image: &ImageBuffer<P, Vec<u8>>,
+	root: &Path,
+	downsample_methods: &[Downsample],
+) -> ImageResult<TileSet> {
+	let levels = save_tiled_pyramid_from_level(0, image, root, downsample_methods, TILE_SIZE)?;
+	let tile_set = TileSet { tile_size: TILE_SIZE, channels: P::CHANNEL_COUNT, levels };
+	save_tile_set(&tile_set, root)?;
+	Ok(tile_set)
+}
+
+/// Writes the descriptor to `{root}/tiles.json`
+pub fn save_tile_set(tile_set: &TileSet, root: &Path) -> std::io::Result<()> {
+	let descriptor_name = root.join(TileSet::FILE_NAME);
+	let mut descriptor_file = File::create(&descriptor_name)?;
+	let descriptor = serde_json::to_string(tile_set).expect("Failed to serialize tile set");
+	write!(descriptor_file, "{}", descriptor)?;
+
+	println!("Saved tile set: {:?}", descriptor_name);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use image::{GrayImage, Luma};
+
+	use super::*;
+
+	#[test]
+	fn tiles_cover_the_image() {
+		let temp = tempfile::tempdir().unwrap();
+		let root = temp.path();
+		let image = GrayImage::from_fn(5, 3, |x, y| Luma([(y * 5 + x) as u8]));
+
+		let level = save_tiles(&image, root, 0, 2).unwrap();
+		assert_eq!((level.columns, level.rows), (3, 2));
+
+		let corner = image::open(root.join("0/2/1.png")).unwrap().to_luma8();
+		assert_eq!(corner.into_raw(), vec![14]);
+		let first = image::open(root.join("0/0/0.png")).unwrap().to_luma8();
+		assert_eq!(first.into_raw(), vec![0, 1, 5, 6]);
+	}
+
+	#[test]
+	fn blocks_continue_the_tile_numbering() {
+		let temp = tempfile::tempdir().unwrap();
+		let root = temp.path();
+		let block = GrayImage::new(4, 2);
+
+		save_tile_block(&block, root, 0, (2, 3), 2).unwrap();
+		assert!(root.join("0/2/3.png").exists());
+		assert!(root.join("0/3/3.png").exists());
+	}
+
+	#[test]
+	fn pyramid_ends_with_a_single_tile() {
+		let temp = tempfile::tempdir().unwrap();
+		let root = temp.path();
+		let image = GrayImage::new(TILE_SIZE * 4, TILE_SIZE * 2);
+
+		let tile_set = save_tiled_pyramid(&image, root, &[Downsample::Average]).unwrap();
+
+		let sizes: Vec<(u32, u32)> =
+			tile_set.levels.iter().map(|level| (level.columns, level.rows)).collect();
+		assert_eq!(sizes, vec![(4, 2), (2, 1), (1, 1)]);
+
+		let descriptor = fs::read_to_string(root.join(TileSet::FILE_NAME)).unwrap();
+		assert_eq!(serde_json::from_str::<TileSet>(&descriptor).unwrap(), tile_set);
+	}
+}
@@ -85,6 +85,48 @@ impl Shp<f64> {
 	}
 }
 
+/// *.geojson files. A single Feature or Geometry is treated as a collection of
+/// one.
+#[cfg(feature = "read_geojson")]
+pub struct GeoJson<T: DataType> {
+	pub(crate) contents: geojson::FeatureCollection,
+	phantom: PhantomData<T>,
+}
+
+#[cfg(feature = "read_geojson")]
+impl<T: DataType> DataFile<T, ()> for GeoJson<T> {
+	fn extension() -> &'static OsStr { OsStr::new("geojson") }
+
+	fn open(path: &Path, _metadata: ()) -> Result<Self, String>
+	where
+		Self: Sized,
+	{
+		let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+		Self::parse(text.as_str())
+	}
+}
+
+#[cfg(feature = "read_geojson")]
+impl<T: DataType> GeoJson<T> {
+	pub fn parse(text: &str) -> Result<Self, String> {
+		let contents = match text.parse::<geojson::GeoJson>().map_err(|e| e.to_string())? {
+			geojson::GeoJson::FeatureCollection(collection) => collection,
+			geojson::GeoJson::Feature(feature) => geojson::FeatureCollection {
+				bbox: None,
+				features: vec![feature],
+				foreign_members: None,
+			},
+			geojson::GeoJson::Geometry(geometry) => {
+				geojson::FeatureCollection::from_iter([geojson::Feature {
+					geometry: Some(geometry),
+					..Default::default()
+				}])
+			}
+		};
+		Ok(Self { contents, phantom: PhantomData })
+	}
+}
+
 #[cfg(feature = "read_netcdf")]
 pub mod cdf {
 	use super::*;
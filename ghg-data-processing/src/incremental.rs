@@ -0,0 +1,279 @@
+//! Records what each export was built from, so running an export again only
+//! rebuilds the outputs whose inputs or parameters changed
+
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{fmt, fs, io};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const BUFFER_SIZE: usize = 1 << 20;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputRecord {
+	pub path: PathBuf,
+	pub size: u64,
+	/// Only used to avoid hashing unchanged files again; a file that's touched
+	/// but not changed still counts as up to date
+	pub modified: SystemTime,
+	/// Lowercase hex
+	pub sha256: String,
+}
+
+/// What a job's outputs were built from, saved next to them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BuildRecord {
+	pub inputs: Vec<InputRecord>,
+	pub parameters: serde_json::Value,
+}
+
+impl BuildRecord {
+	/// The record at `path`, if there is one that can be read
+	pub fn load(path: &Path) -> io::Result<Option<Self>> {
+		match fs::read(path) {
+			Ok(contents) => Ok(serde_json::from_slice(&contents).ok()),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Writes to a temporary file first, so an interrupted save never leaves
+	/// a half-written record
+	fn save(&self, path: &Path) -> io::Result<()> {
+		let temporary_path = path.with_extension("build.tmp");
+		let contents = serde_json::to_vec_pretty(self).expect("Failed to serialize build record");
+		fs::write(&temporary_path, contents)?;
+		fs::rename(temporary_path, path)
+	}
+
+	fn matches(&self, other: &Self) -> bool {
+		self.parameters == other.parameters
+			&& self.inputs.len() == other.inputs.len()
+			&& self
+				.inputs
+				.iter()
+				.zip(&other.inputs)
+				.all(|(a, b)| a.path == b.path && a.sha256 == b.sha256)
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JobOutcome {
+	Skipped,
+	Rebuilt,
+}
+
+impl Display for JobOutcome {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			JobOutcome::Skipped => write!(f, "skipped"),
+			JobOutcome::Rebuilt => write!(f, "rebuilt"),
+		}
+	}
+}
+
+/// One unit of export work: a set of outputs built from a set of input files
+/// with some parameters. Its `BuildRecord` is only written once the outputs
+/// are, so an interrupted build is redone on the next run.
+pub struct Job {
+	record_path: PathBuf,
+	outputs: Vec<PathBuf>,
+	record: BuildRecord,
+	previous: Option<BuildRecord>,
+}
+
+impl Job {
+	/// Hashes `inputs`, reusing the hashes in the previous record at
+	/// `record_path` for files whose size and modification time are unchanged.
+	/// `parameters` should cover everything else that affects the outputs,
+	/// including a version to bump when the export code changes.
+	pub fn new(
+		record_path: PathBuf,
+		outputs: Vec<PathBuf>,
+		inputs: &[PathBuf],
+		parameters: &impl Serialize,
+	) -> io::Result<Self> {
+		let previous = BuildRecord::load(&record_path)?;
+		let inputs = inputs
+			.iter()
+			.map(|path| input_record(path, previous.as_ref()))
+			.collect::<io::Result<_>>()?;
+		let parameters = serde_json::to_value(parameters)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+		Ok(Self { record_path, outputs, record: BuildRecord { inputs, parameters }, previous })
+	}
+
+	/// Whether every output exists and was built from the same inputs and
+	/// parameters
+	pub fn is_up_to_date(&self) -> bool {
+		self.previous.as_ref().is_some_and(|previous| previous.matches(&self.record))
+			&& self.outputs.iter().all(|output| output.exists())
+	}
+
+	/// Runs `build` unless the outputs are up to date, then records what they
+	/// were built from
+	pub fn run(self, build: impl FnOnce()) -> io::Result<JobOutcome> {
+		let outcome = if self.is_up_to_date() {
+			JobOutcome::Skipped
+		} else {
+			build();
+			JobOutcome::Rebuilt
+		};
+
+		// Also refreshes the modification times of touched inputs, so they
+		// aren't hashed again next time
+		if self.previous.as_ref() != Some(&self.record) {
+			self.record.save(&self.record_path)?;
+		}
+		Ok(outcome)
+	}
+}
+
+fn input_record(path: &Path, previous: Option<&BuildRecord>) -> io::Result<InputRecord> {
+	let file_metadata = fs::metadata(path)?;
+	let size = file_metadata.len();
+	let modified = file_metadata.modified()?;
+
+	let unchanged = previous
+		.and_then(|previous| previous.inputs.iter().find(|input| input.path == path))
+		.filter(|input| input.size == size && input.modified == modified);
+	let sha256 = match unchanged {
+		Some(input) => input.sha256.clone(),
+		None => sha256_file(path)?,
+	};
+
+	Ok(InputRecord { path: path.to_owned(), size, modified, sha256 })
+}
+
+/// Lowercase hex
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+	let mut file = File::open(path)?;
+	let mut hasher = Sha256::new();
+	let mut buffer = vec![0u8; BUFFER_SIZE];
+	loop {
+		let read = file.read(&mut buffer)?;
+		if read == 0 {
+			break;
+		}
+		hasher.update(&buffer[..read]);
+	}
+	Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Which jobs of a run were rebuilt, to print at the end
+#[derive(Clone, Debug, Default)]
+pub struct BuildReport {
+	pub rebuilt: Vec<String>,
+	pub skipped: usize,
+}
+
+impl BuildReport {
+	pub fn add(&mut self, name: impl Into<String>, outcome: JobOutcome) {
+		match outcome {
+			JobOutcome::Skipped => self.skipped += 1,
+			JobOutcome::Rebuilt => self.rebuilt.push(name.into()),
+		}
+	}
+}
+
+impl Display for BuildReport {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let total = self.rebuilt.len() + self.skipped;
+		if self.rebuilt.is_empty() {
+			return write!(f, "All {total} outputs were up to date");
+		}
+
+		write!(f, "Rebuilt {} of {total} outputs:", self.rebuilt.len())?;
+		for name in &self.rebuilt {
+			write!(f, "\n  {name}")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct Fixture {
+		directory: tempfile::TempDir,
+		input: PathBuf,
+		output: PathBuf,
+	}
+
+	impl Fixture {
+		fn new() -> Self {
+			let directory = tempfile::tempdir().unwrap();
+			let input = directory.path().join("input.nc4");
+			fs::write(&input, b"first").unwrap();
+			let output = directory.path().join("output.png");
+			Self { directory, input, output }
+		}
+
+		fn run(&self, parameters: &[&str]) -> JobOutcome {
+			let job = Job::new(
+				self.directory.path().join("output.build"),
+				vec![self.output.clone()],
+				std::slice::from_ref(&self.input),
+				&parameters,
+			)
+			.unwrap();
+			job.run(|| fs::write(&self.output, b"built").unwrap()).unwrap()
+		}
+	}
+
+	#[test]
+	fn skips_unchanged_jobs() {
+		let fixture = Fixture::new();
+
+		assert_eq!(fixture.run(&["T2M"]), JobOutcome::Rebuilt);
+		assert_eq!(fixture.run(&["T2M"]), JobOutcome::Skipped);
+
+		// Rewriting the same contents isn't a change
+		fs::write(&fixture.input, b"first").unwrap();
+		assert_eq!(fixture.run(&["T2M"]), JobOutcome::Skipped);
+	}
+
+	#[test]
+	fn rebuilds_changed_inputs() {
+		let fixture = Fixture::new();
+		fixture.run(&["T2M"]);
+
+		fs::write(&fixture.input, b"second").unwrap();
+		assert_eq!(fixture.run(&["T2M"]), JobOutcome::Rebuilt);
+		assert_eq!(fixture.run(&["T2M"]), JobOutcome::Skipped);
+	}
+
+	#[test]
+	fn rebuilds_changed_parameters() {
+		let fixture = Fixture::new();
+		fixture.run(&["T2M"]);
+
+		assert_eq!(fixture.run(&["T2M", "PS"]), JobOutcome::Rebuilt);
+	}
+
+	#[test]
+	fn rebuilds_missing_outputs() {
+		let fixture = Fixture::new();
+		fixture.run(&["T2M"]);
+
+		fs::remove_file(&fixture.output).unwrap();
+		assert_eq!(fixture.run(&["T2M"]), JobOutcome::Rebuilt);
+		assert!(fixture.output.exists());
+	}
+
+	#[test]
+	fn reports_rebuilt_outputs() {
+		let mut report = BuildReport::default();
+		report.add("1980.01.04", JobOutcome::Skipped);
+		assert_eq!(report.to_string(), "All 1 outputs were up to date");
+
+		report.add("1980.05.08", JobOutcome::Rebuilt);
+		assert_eq!(report.to_string(), "Rebuilt 1 of 2 outputs:\n  1980.05.08");
+	}
+}
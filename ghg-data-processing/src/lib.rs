@@ -2,6 +2,10 @@
 
 #[macro_use]
 pub mod save_result;
+pub mod download;
 pub mod export;
 pub mod file_type;
+pub mod incremental;
+pub mod progress;
 pub mod read_data;
+pub mod validate;
@@ -0,0 +1,67 @@
+//! Progress of a batch of jobs that finish in any order, logged a line per job
+//! so it reads the same from a terminal or a log file
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub struct Progress {
+	total: usize,
+	finished: AtomicUsize,
+	started: Instant,
+}
+
+impl Progress {
+	pub fn new(total: usize) -> Self {
+		Self { total, finished: AtomicUsize::new(0), started: Instant::now() }
+	}
+
+	/// Logs that the job `name` finished, with `status`. Safe to call from any
+	/// worker.
+	pub fn finish(&self, name: &str, status: impl Display) {
+		let finished = self.finished.fetch_add(1, Ordering::Relaxed) + 1;
+		println!("{}", progress_line(finished, self.total, self.started.elapsed(), name, status));
+	}
+}
+
+fn progress_line(
+	finished: usize,
+	total: usize,
+	elapsed: Duration,
+	name: &str,
+	status: impl Display,
+) -> String {
+	let width = total.to_string().len();
+	let remaining = elapsed.mul_f64((total - finished) as f64 / finished as f64);
+	format!(
+		"[{finished:>width$}/{total}] {name} {status}, {} elapsed, ~{} left",
+		format_duration(elapsed),
+		format_duration(remaining)
+	)
+}
+
+fn format_duration(duration: Duration) -> String {
+	let seconds = duration.as_secs();
+	match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+		(0, 0, s) => format!("{s}s"),
+		(0, m, s) => format!("{m}m {s:02}s"),
+		(h, m, _) => format!("{h}h {m:02}m"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn formats_lines() {
+		assert_eq!(
+			progress_line(3, 126, Duration::from_secs(90), "1980.09.12", "rebuilt"),
+			"[  3/126] 1980.09.12 rebuilt, 1m 30s elapsed, ~1h 01m left"
+		);
+		assert_eq!(
+			progress_line(126, 126, Duration::from_secs(5), "2021.09.12", "skipped"),
+			"[126/126] 2021.09.12 skipped, 5s elapsed, ~0s left"
+		);
+	}
+}
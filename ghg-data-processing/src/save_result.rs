@@ -1,11 +1,30 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use ghg_data_core::color_map::ColorScale;
+
+/// Saves `channels` as a mip pyramid of images, at
+/// `{directory}/{level}/{stem}.png` for an `$output_name` of
+/// `{directory}/{stem}.png`, and their metadata at
+/// `{directory}/{stem}.metadata`
 #[macro_export]
 macro_rules! save_channels {
     ($output_name:expr, $channels:expr) => {
-        $channels.to_image()
-            .save($output_name.clone())
-            .expect("Failed to save data as image!");
+        let image = $channels.to_image();
+        let output_root = $output_name.parent().expect("Output must be within a directory");
+        let image_name = $output_name
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .expect("Output must have a file name");
 
-        println!("Saved image: {:?}", $output_name);
+        $crate::export::pyramid::save_pyramid(
+            &image,
+            output_root,
+            image_name,
+            &[$crate::export::pyramid::Downsample::Average],
+        )
+        .expect("Failed to save data as image!");
 
         let metadata_name = $output_name.with_extension("metadata");
         let mut metadata_file = File::create(metadata_name.clone()).expect("Failed to create metadata file");
@@ -24,3 +43,40 @@ macro_rules! save_channels {
 }
 
 pub use save_channels;
+
+/// Saves how a dataset's values are colored, at
+/// `{directory}/color_scale.json`, where the viewer looks for it alongside the
+/// dataset's metadata
+pub fn save_color_scale(directory: &Path, color_scale: &ColorScale) -> std::io::Result<()> {
+	color_scale.color_map.validate().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+	let contents =
+		serde_json::to_string_pretty(color_scale).expect("Failed to serialize color scale");
+	fs::write(directory.join(ColorScale::FILE_NAME), contents)
+}
+
+#[cfg(test)]
+mod tests {
+	use ghg_data_core::color_map::{ColorMap, ColorStop};
+
+	use super::*;
+
+	#[test]
+	fn saves_color_scales_the_viewer_reads() {
+		let temp = tempfile::tempdir().unwrap();
+		let root = temp.path();
+
+		let color_scale = ColorScale {
+			color_map: ColorMap::Custom(vec![
+				ColorStop { position: 0.0, color: [0, 0, 255] },
+				ColorStop { position: 1.0, color: [255, 0, 0] },
+			]),
+			midpoint: Some(273.15),
+		};
+		save_color_scale(root, &color_scale).unwrap();
+		let saved = fs::read(root.join("color_scale.json")).unwrap();
+		assert_eq!(serde_json::from_slice::<ColorScale>(&saved).unwrap(), color_scale);
+
+		let no_stops = ColorScale { color_map: ColorMap::Custom(Vec::new()), midpoint: None };
+		assert!(save_color_scale(root, &no_stops).is_err());
+	}
+}
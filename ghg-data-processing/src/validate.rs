@@ -0,0 +1,385 @@
+//! Checks exported images against their metadata and, where it can be read,
+//! the data they were exported from
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::{fmt, fs};
+
+use ghg_data_core::metadata::{ChannelMetadata, Metadata};
+use image::{DynamicImage, GenericImageView};
+
+use crate::export::data_2d_statistics::Data2dStatistics;
+
+/// Roughly how many pixels of each output are decoded and compared with the
+/// source
+const SAMPLE_COUNT: usize = 4096;
+
+/// An output of `save_channels!`: the image at each pyramid level, and its
+/// metadata
+pub struct Output {
+	pub name: String,
+	pub levels: Vec<DynamicImage>,
+	pub metadata: Metadata,
+}
+
+impl Output {
+	/// Loads `{root}/{stem}.metadata` and every `{root}/{level}/{stem}.png`
+	pub fn load(root: &Path, stem: &str) -> Result<Self, String> {
+		let metadata_path = root.join(format!("{stem}.metadata"));
+		let metadata = fs::read(&metadata_path)
+			.map_err(|e| format!("Failed to read {metadata_path:?}: {e}"))?;
+		let metadata = serde_json::from_slice(&metadata)
+			.map_err(|e| format!("Failed to parse {metadata_path:?}: {e}"))?;
+
+		let mut levels = Vec::new();
+		loop {
+			let level_path = root.join(levels.len().to_string()).join(format!("{stem}.png"));
+			if !level_path.exists() {
+				break;
+			}
+			levels.push(
+				image::open(&level_path)
+					.map_err(|e| format!("Failed to load {level_path:?}: {e}"))?,
+			);
+		}
+		if levels.is_empty() {
+			return Err(format!("No images for {stem} in {root:?}"));
+		}
+
+		Ok(Self { name: stem.to_owned(), levels, metadata })
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+	NotEightBit,
+	ChannelCount {
+		image: usize,
+		metadata: usize,
+	},
+	SourceChannelCount {
+		metadata: usize,
+		source: usize,
+	},
+	Dimensions {
+		image: (u32, u32),
+		source: (usize, usize),
+	},
+	LevelDimensions {
+		level: usize,
+		expected: (u32, u32),
+		actual: (u32, u32),
+	},
+	/// `min` isn't less than `max`, so the value range can't be mapped onto
+	/// bytes
+	EmptyRange {
+		channel: usize,
+		min: f64,
+		max: f64,
+	},
+	NonFinite {
+		channel: usize,
+		count: usize,
+	},
+	/// Decoded values are further from the source than one quantisation step
+	QuantisationError {
+		channel: usize,
+		error: f64,
+		step: f64,
+	},
+}
+
+impl Display for Problem {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			Problem::NotEightBit => write!(f, "image isn't 8 bits per channel"),
+			Problem::ChannelCount { image, metadata } => {
+				write!(f, "image has {image} channels, but the metadata has {metadata}")
+			}
+			Problem::SourceChannelCount { metadata, source } => {
+				write!(f, "metadata has {metadata} channels, but the source has {source}")
+			}
+			Problem::Dimensions { image, source } => write!(
+				f,
+				"image is {}x{}, but the source is {}x{}",
+				image.0, image.1, source.0, source.1
+			),
+			Problem::LevelDimensions { level, expected, actual } => write!(
+				f,
+				"level {level} is {}x{}, expected {}x{}",
+				actual.0, actual.1, expected.0, expected.1
+			),
+			Problem::EmptyRange { channel, min, max } => {
+				write!(f, "channel {channel} has min {min} and max {max}")
+			}
+			Problem::NonFinite { channel, count } => {
+				write!(f, "channel {channel} has {count} NaN or infinite source values")
+			}
+			Problem::QuantisationError { channel, error, step } => {
+				write!(f, "channel {channel} decodes up to {error} from the source (step {step})")
+			}
+		}
+	}
+}
+
+/// How far decoded values are from the source, in the source's units
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QuantisationError {
+	pub max: f64,
+	pub mean: f64,
+	/// The value of one byte, `(max - min) / 255`
+	pub step: f64,
+}
+
+pub struct Validation {
+	pub name: String,
+	pub problems: Vec<Problem>,
+	/// Per channel, if the source was compared
+	pub errors: Vec<QuantisationError>,
+}
+
+impl Validation {
+	pub fn is_ok(&self) -> bool { self.problems.is_empty() }
+}
+
+impl Display for Validation {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.name, if self.is_ok() { "ok" } else { "FAILED" })?;
+		for problem in &self.problems {
+			write!(f, "\n  {problem}")?;
+		}
+		for (channel, error) in self.errors.iter().enumerate() {
+			write!(
+				f,
+				"\n  channel {channel} error: max {:.4}, mean {:.4} (step {:.4})",
+				error.max, error.mean, error.step
+			)?;
+		}
+		Ok(())
+	}
+}
+
+/// Checks `output`, and compares it with the channels it was exported from if
+/// `source` is given
+pub fn validate(output: &Output, source: Option<&[Data2dStatistics<f64>]>) -> Validation {
+	let mut validation =
+		Validation { name: output.name.clone(), problems: Vec::new(), errors: Vec::new() };
+	let problems = &mut validation.problems;
+
+	let image = &output.levels[0];
+	let channels = &output.metadata.0;
+	if image.color().bytes_per_pixel() != image.color().channel_count() {
+		problems.push(Problem::NotEightBit);
+	}
+	let image_channels = image.color().channel_count() as usize;
+	if image_channels != channels.len() {
+		problems.push(Problem::ChannelCount { image: image_channels, metadata: channels.len() });
+	}
+
+	for (channel, range) in channels.iter().enumerate() {
+		if !range.min.is_finite() || !range.max.is_finite() || range.min >= range.max {
+			problems.push(Problem::EmptyRange { channel, min: range.min, max: range.max });
+		}
+	}
+
+	let mut expected = image.dimensions();
+	for (level, level_image) in output.levels.iter().enumerate().skip(1) {
+		expected = ((expected.0 / 2).max(1), (expected.1 / 2).max(1));
+		if level_image.dimensions() != expected {
+			problems.push(Problem::LevelDimensions {
+				level,
+				expected,
+				actual: level_image.dimensions(),
+			});
+		}
+	}
+
+	let Some(source) = source else {
+		return validation;
+	};
+	if source.len() != channels.len() {
+		problems
+			.push(Problem::SourceChannelCount { metadata: channels.len(), source: source.len() });
+	}
+	for (channel, data) in source.iter().enumerate() {
+		let count = data
+			.data
+			.rows
+			.iter()
+			.flat_map(|row| &row.columns)
+			.filter(|value| !value.is_finite())
+			.count();
+		if count > 0 {
+			problems.push(Problem::NonFinite { channel, count });
+		}
+
+		let source_dimensions = (data.data.width(), data.data.height());
+		if source_dimensions != (image.width() as usize, image.height() as usize) {
+			problems
+				.push(Problem::Dimensions { image: image.dimensions(), source: source_dimensions });
+		}
+	}
+
+	// Comparing pixels only makes sense once the shapes agree
+	if !problems.is_empty() {
+		return validation;
+	}
+	for (channel, data) in source.iter().enumerate() {
+		let error = quantisation_error(image, channel, &channels[channel], data);
+		// Bytes are truncated, so values should decode to within a step below
+		if error.max > error.step * (1.0 + 1e-9) {
+			validation.problems.push(Problem::QuantisationError {
+				channel,
+				error: error.max,
+				step: error.step,
+			});
+		}
+		validation.errors.push(error);
+	}
+	validation
+}
+
+/// Decodes an even spread of pixels from `channel` of `image`, which is stored
+/// north-up while `source` rows run south to north
+fn quantisation_error(
+	image: &DynamicImage,
+	channel: usize,
+	metadata: &ChannelMetadata,
+	source: &Data2dStatistics<f64>,
+) -> QuantisationError {
+	let (width, height) = (image.width() as usize, image.height() as usize);
+	let channel_count = image.color().channel_count() as usize;
+	let bytes = image.as_bytes();
+
+	let pixel_count = width * height;
+	let stride = (pixel_count / SAMPLE_COUNT).max(1);
+	let (mut max_error, mut total_error, mut samples) = (0f64, 0.0, 0);
+	for pixel in (0..pixel_count).step_by(stride) {
+		let (x, y) = (pixel % width, pixel / width);
+		let decoded = metadata.decode(bytes[pixel * channel_count + channel]);
+		let error = (decoded - source.data.rows[height - 1 - y].columns[x]).abs();
+		max_error = max_error.max(error);
+		total_error += error;
+		samples += 1;
+	}
+
+	QuantisationError { max: max_error, mean: total_error / samples as f64, step: metadata.step() }
+}
+
+#[cfg(test)]
+mod tests {
+	use image::GrayAlphaImage;
+
+	use super::*;
+	use crate::export::data_2d_statistics::{Data2d, ToMetadata};
+	use crate::export::image::ToImage;
+
+	/// A 4-channel field varying across both axes, so a flipped image is
+	/// caught
+	fn source(name: &str) -> Data2dStatistics<f64> {
+		let (width, height) = (40, 20);
+		let mut data = Data2d::new(width, height);
+		for (y, row) in data.rows.iter_mut().enumerate() {
+			for (x, value) in row.columns.iter_mut().enumerate() {
+				*value = 250.0 + x as f64 * 0.7 + y as f64 * 1.3;
+			}
+		}
+		Data2dStatistics {
+			name: name.to_owned(),
+			data,
+			min: Some(250.0),
+			max: Some(250.0 + 39.0 * 0.7 + 19.0 * 1.3),
+		}
+	}
+
+	fn export(channels: &[Data2dStatistics<f64>; 4]) -> Output {
+		let image = DynamicImage::ImageRgba8(channels.to_image());
+		let mut levels = vec![image.clone()];
+		levels.push(image.thumbnail_exact(20, 10));
+		Output { name: "1980.01.04".to_owned(), levels, metadata: channels.to_metadata() }
+	}
+
+	#[test]
+	fn accepts_exported_channels() {
+		let channels = ["T2M", "T2M", "T2M", "T2M"].map(source);
+		let validation = validate(&export(&channels), Some(&channels));
+
+		assert!(validation.is_ok(), "{validation}");
+		assert_eq!(validation.errors.len(), 4);
+		let error = validation.errors[0];
+		assert!(error.max < error.step && error.mean > 0.0, "{error:?}");
+	}
+
+	#[test]
+	fn rejects_flipped_images() {
+		let channels = ["T2M", "T2M", "T2M", "T2M"].map(source);
+		let mut output = export(&channels);
+		output.levels[0] = output.levels[0].flipv();
+
+		let validation = validate(&output, Some(&channels));
+		assert!(matches!(validation.problems[0], Problem::QuantisationError { channel: 0, .. }));
+	}
+
+	#[test]
+	fn reports_constant_and_non_finite_channels() {
+		let mut channels = ["T2M", "T2M", "T2M", "T2M"].map(source);
+		channels[1].data.rows[3].columns[5] = f64::NAN;
+		let mut output = export(&channels);
+		output.metadata.0[2] = ChannelMetadata { min: 273.0, max: 273.0 };
+
+		let validation = validate(&output, Some(&channels));
+		assert_eq!(
+			validation.problems,
+			[
+				Problem::EmptyRange { channel: 2, min: 273.0, max: 273.0 },
+				Problem::NonFinite { channel: 1, count: 1 },
+			]
+		);
+	}
+
+	#[test]
+	fn reports_mismatched_shapes() {
+		let output = Output {
+			name: "bad".to_owned(),
+			levels: vec![
+				DynamicImage::ImageLumaA8(GrayAlphaImage::new(8, 4)),
+				DynamicImage::ImageLumaA8(GrayAlphaImage::new(3, 2)),
+			],
+			metadata: Metadata(vec![ChannelMetadata { min: 0.0, max: 1.0 }]),
+		};
+
+		let validation = validate(&output, None);
+		assert_eq!(
+			validation.problems,
+			[
+				Problem::ChannelCount { image: 2, metadata: 1 },
+				Problem::LevelDimensions { level: 1, expected: (4, 2), actual: (3, 2) },
+			]
+		);
+	}
+
+	#[test]
+	fn loads_saved_outputs() {
+		let temp = tempfile::tempdir().unwrap();
+		let root = temp.path();
+		let channels = ["T2M", "T2M", "T2M", "T2M"].map(source);
+		let image = channels.to_image();
+		crate::export::pyramid::save_pyramid(
+			&image,
+			root,
+			"1980.01.04",
+			&[crate::export::pyramid::Downsample::Average],
+		)
+		.unwrap();
+		fs::write(
+			root.join("1980.01.04.metadata"),
+			serde_json::to_string(&channels.to_metadata()).unwrap(),
+		)
+		.unwrap();
+
+		let output = Output::load(root, "1980.01.04").unwrap();
+		assert_eq!(output.levels.len(), 1);
+		assert!(validate(&output, Some(&channels)).is_ok());
+		assert!(Output::load(root, "missing").is_err());
+	}
+}
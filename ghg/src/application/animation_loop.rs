@@ -1,25 +1,53 @@
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
+use ghg_data_core::color_map::ColorScale;
 use single_thread_executor::new_executor_and_spawner;
 use wasm_bindgen_futures::spawn_local;
 use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 
 use crate::application::control::controller_frame;
+use crate::application::data::Dataset;
+use crate::application::events::ViewerEvents;
+use crate::application::layers::{LayerStack, LayerTextures};
+use crate::application::planet::PlanetTextures;
+use crate::application::probe::ProbeSources;
+use crate::application::scene_uniforms::SceneUniforms;
+#[cfg(debug_assertions)]
+use crate::application::shaders;
 // use crate::application::data::load_temp_data;
 use crate::application::shaders::{get_direct_mesh_render_shaders, get_planet_shaders};
-use crate::application::{country, data, debug_axes, debug_projection, planet};
+use crate::application::timeline::Timeline;
+use crate::application::{
+	country, data, debug_axes, debug_projection, layers, planet, probe, scene_uniforms,
+	tile_streaming, timeline,
+};
 use crate::render_core::animation::{wrap_animation_body, AnimationFn};
 use crate::render_core::animation_params::AnimationParams;
 use crate::render_core::camera::Camera;
 use crate::render_core::frame_sequencer::{FrameGate, FrameMarker, FrameSequencer};
-use crate::render_core::texture_provider::TextureProvider;
+use crate::render_core::texture_registry::TextureRegistry;
 use crate::utils::prelude::*;
 
+/// The viewer's state that can be changed from outside the animation loop
+#[derive(Clone)]
+pub struct ViewerState {
+	pub events: ViewerEvents,
+	pub camera: Rc<RefCell<Camera>>,
+	pub dataset: Rc<RefCell<Dataset>>,
+	pub timeline: Rc<RefCell<Timeline>>,
+	/// The data's color scale, if the page has chosen one over the dataset's
+	pub color_scale: Rc<RefCell<Option<ColorScale>>>,
+	pub layers: Rc<RefCell<LayerStack>>,
+	/// Where to reload the shaders from, once the page asks to
+	#[cfg(debug_assertions)]
+	pub shader_reload: Rc<RefCell<Option<String>>>,
+}
+
 pub fn get_animation_loop(
 	canvas: HtmlCanvasElement,
 	context: WebGl2RenderingContext,
-) -> Result<AnimationFn, JsValue> {
+) -> Result<(AnimationFn, ViewerState), JsValue> {
 	let (executor, spawner) = new_executor_and_spawner();
 	spawn_local(async move {
 		executor.run().await;
@@ -31,34 +59,74 @@ pub fn get_animation_loop(
 	let planet_shader = get_planet_shaders(&context)?;
 	let axes_shader = get_direct_mesh_render_shaders(&context)?;
 
-	let current_month = Rc::new(Cell::new(0));
+	let scene_uniforms = SceneUniforms::new(&context)?;
+	scene_uniforms.bind_to(&planet_shader, true)?;
+	scene_uniforms.bind_to(&axes_shader, false)?;
+
+	let events = ViewerEvents::new(canvas.clone());
+	let dataset = Rc::new(RefCell::new(Dataset::default()));
+	let timeline = Rc::new(RefCell::new(Timeline::default()));
+	let color_scale = Rc::new(RefCell::new(None));
+	let layers = Rc::new(RefCell::new(LayerStack::default()));
 	let current_cursor_location = Rc::new(Cell::new(None));
+	let current_click = Rc::new(Cell::new(None));
 
 	// let projection_locations = Rc::new(RefCell::new(vec![nglm::vec3(0.5, 0.5,
 	// 0.5), nglm::vec3(0.5, 0.0, -0.5)]));
 
-	let texture_provider = TextureProvider::default();
+	let textures = TextureRegistry::new(context.clone());
+	let layer_textures = LayerTextures::new(textures.clone());
+	let probe_sources = Rc::new(RefCell::new(ProbeSources::default()));
+
+	let planet_textures = PlanetTextures::new(&textures);
 
 	let frame_sequencer = Rc::new(FrameSequencer::<AnimationParams>::new());
 	spawner.spawn(planet::load_textures(
 		FrameGate::new(frame_sequencer.clone(), "Load Textures".to_owned()),
+		events.clone(),
 		spawner.clone(),
 		planet_shader.clone(),
 		camera.clone(),
-		texture_provider.clone(),
+		textures.clone(),
+		planet_textures.clone(),
+	));
+
+	spawner.spawn(tile_streaming::stream_color_tiles(
+		FrameGate::new(frame_sequencer.clone(), "Stream Color Tiles".to_owned()),
+		spawner.clone(),
+		planet_shader.clone(),
+		camera.clone(),
+		textures.clone(),
 	));
 
 	spawner.spawn(country::draw_borders(
 		FrameGate::new(frame_sequencer.clone(), "Draw Countries".to_owned()),
+		events.clone(),
 		planet_shader.clone(),
-		texture_provider.clone(),
+		camera.clone(),
+		layer_textures.clone(),
+		layers.clone(),
+		current_cursor_location.clone(),
+		current_click.clone(),
+		probe_sources.clone(),
 	));
 
 	spawner.spawn(data::handle_data(
 		FrameGate::new(frame_sequencer.clone(), "Handle Data".to_owned()),
+		events.clone(),
+		planet_shader.clone(),
+		dataset.clone(),
+		timeline.clone(),
+		color_scale.clone(),
+		layer_textures.clone(),
+		layers.clone(),
+		probe_sources.clone(),
+	));
+
+	spawner.spawn(layers::compose_layers(
+		FrameGate::new(frame_sequencer.clone(), "Compose Layers".to_owned()),
 		planet_shader.clone(),
-		current_month.clone(),
-		texture_provider.clone(),
+		layers.clone(),
 	));
 
 	spawner.spawn(controller_frame(
@@ -66,14 +134,22 @@ pub fn get_animation_loop(
 		canvas.clone(),
 		planet_shader.clone(),
 		camera.clone(),
-		current_month.clone(),
+		timeline.clone(),
 		current_cursor_location.clone(),
+		current_click.clone(),
+	));
+
+	spawner.spawn(scene_uniforms::write_scene_uniforms(
+		FrameGate::new(frame_sequencer.clone(), "Write Scene Uniforms".to_owned()),
+		scene_uniforms,
+		camera.clone(),
 	));
 
 	spawner.spawn(planet::draw(
 		FrameGate::new(frame_sequencer.clone(), "Draw Planet".to_owned()),
 		planet_shader.clone(),
 		camera.clone(),
+		planet_textures,
 	));
 
 	spawner.spawn(debug_axes::draw(
@@ -90,9 +166,47 @@ pub fn get_animation_loop(
 		current_cursor_location.clone(),
 	));
 
+	spawner.spawn(probe::probe_cursor(
+		FrameGate::new(frame_sequencer.clone(), "Probe Cursor".to_owned()),
+		events.clone(),
+		camera.clone(),
+		current_cursor_location.clone(),
+		timeline.clone(),
+		probe_sources.clone(),
+	));
+
+	spawner.spawn(timeline::play_timeline(
+		FrameGate::new(frame_sequencer.clone(), "Play Timeline".to_owned()),
+		events.clone(),
+		timeline.clone(),
+	));
+
+	#[cfg(debug_assertions)]
+	let shader_reload = Rc::new(RefCell::new(None));
+	#[cfg(debug_assertions)]
+	spawner.spawn(shaders::reload_shaders(
+		FrameGate::new(frame_sequencer.clone(), "Reload Shaders".to_owned()),
+		events.clone(),
+		vec![planet_shader.clone(), axes_shader.clone()],
+		shader_reload.clone(),
+	));
+
 	let frame_marker = FrameMarker::new(frame_sequencer.clone());
 
-	Ok(wrap_animation_body(move |params: AnimationParams| {
+	let animation_body = wrap_animation_body(move |params: AnimationParams| {
 		frame_marker.frame(params);
-	}))
+	});
+	Ok((
+		animation_body,
+		ViewerState {
+			events,
+			camera,
+			dataset,
+			timeline,
+			color_scale,
+			layers,
+			#[cfg(debug_assertions)]
+			shader_reload,
+		},
+	))
 }
@@ -6,6 +6,7 @@ use std::rc::Rc;
 use web_sys::HtmlCanvasElement;
 
 use crate::application::shaders::ShaderContext;
+use crate::application::timeline::Timeline;
 use crate::interaction_core::input_subscriber::{
 	FrameInputSubscriber, InputState, KeyState, MouseButton, MouseButtonState, MouseMovement,
 	Scroll, SwitchState, TouchMovement, TouchState,
@@ -28,8 +29,9 @@ impl Controller {
 		camera: Rc<RefCell<Camera>>,
 		planet_shader: ShaderContext,
 		terrain_scale: Uniform<f32>,
-		current_month: Rc<Cell<usize>>,
+		timeline: Rc<RefCell<Timeline>>,
 		current_cursor_location: Rc<Cell<Option<LogicalCursorPosition>>>,
+		current_click: Rc<Cell<Option<LogicalCursorPosition>>>,
 	) -> Self {
 		let mut input_subscriber = FrameInputSubscriber::new(canvas);
 
@@ -47,12 +49,9 @@ impl Controller {
 						"Digit4" => terrain_scale.write_unchecked(0.7 * scale_max),
 						"Digit5" => terrain_scale.write_unchecked(0.9 * scale_max),
 						"Digit6" => terrain_scale.write_unchecked(1.5 * scale_max),
-						"ArrowRight" => {
-							current_month.replace((current_month.get() + 1) % 12);
-						}
-						"ArrowLeft" => {
-							current_month.replace(((current_month.get() - 1) + 12) % 12);
-						}
+						"ArrowRight" => timeline.borrow_mut().step(1),
+						"ArrowLeft" => timeline.borrow_mut().step(-1),
+						"Space" => timeline.borrow_mut().toggle_playing(),
 						other => ghg_log!("{:?}", other),
 					},
 					_ => {}
@@ -110,11 +109,7 @@ impl Controller {
 
 		input_subscriber.subscribe_on_scroll_event(zoom::make_scroll_handler(&camera));
 
-		input_subscriber.subscribe_on_mouse_button_event(Box::new(
-			|button_states: Vec<MouseButtonState>, _current_state: InputState| {
-				ghg_log!("Mouse button changes: {:?}", button_states);
-			},
-		));
+		input_subscriber.subscribe_on_mouse_button_event(click::make_click_handler(current_click));
 
 		input_subscriber.subscribe_on_touch_state_event(Box::new(
 			|touch_state: HashMap<i32, TouchState>, _current_state: InputState| {
@@ -133,8 +128,9 @@ pub async fn controller_frame(
 	canvas: HtmlCanvasElement,
 	planet_shader: ShaderContext,
 	camera: Rc<RefCell<Camera>>,
-	current_month: Rc<Cell<usize>>,
+	timeline: Rc<RefCell<Timeline>>,
 	current_cursor_location: Rc<Cell<Option<LogicalCursorPosition>>>,
+	current_click: Rc<Cell<Option<LogicalCursorPosition>>>,
 ) {
 	planet_shader.use_shader();
 	let terrain_scale = uniform::init_f32("u_terrainScale", &planet_shader, 0.03);
@@ -144,8 +140,9 @@ pub async fn controller_frame(
 		camera,
 		planet_shader.clone(),
 		terrain_scale,
-		current_month,
+		timeline,
 		current_cursor_location,
+		current_click,
 	);
 
 	loop {
@@ -154,6 +151,56 @@ pub async fn controller_frame(
 	}
 }
 
+mod click {
+	use super::*;
+	use crate::interaction_core::input_subscriber::MouseButtonCallback;
+
+	/// How far, in device pixels, the cursor can move between pressing and
+	/// releasing the left button and still count as a click rather than a drag
+	const CLICK_TOLERANCE: i32 = 4;
+
+	/// Sets `current_click` to where the left button was clicked, without
+	/// dragging the globe around
+	pub fn make_click_handler(
+		current_click: Rc<Cell<Option<LogicalCursorPosition>>>,
+	) -> MouseButtonCallback {
+		let pressed_at = Cell::new(None);
+		Box::new(move |button_states: Vec<MouseButtonState>, current_state: InputState| {
+			let location = current_state.current_cursor_location();
+			for button_state in button_states.iter().filter(|b| b.button == MouseButton::Left) {
+				match button_state.state {
+					SwitchState::Pressed => pressed_at.set(location),
+					SwitchState::Released => {
+						if let (Some(pressed), Some(released)) = (pressed_at.take(), location) {
+							if is_click(pressed, released) {
+								current_click.set(Some(released));
+							}
+						}
+					}
+				}
+			}
+		})
+	}
+
+	fn is_click(pressed: LogicalCursorPosition, released: LogicalCursorPosition) -> bool {
+		let moved = released - pressed;
+		moved.x.abs().max(moved.y.abs()) <= CLICK_TOLERANCE
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		#[test]
+		fn drags_are_not_clicks() {
+			let pressed = nglm::I32Vec2::new(100, 100);
+			assert!(is_click(pressed, pressed));
+			assert!(is_click(pressed, nglm::I32Vec2::new(103, 96)));
+			assert!(!is_click(pressed, nglm::I32Vec2::new(100, 120)));
+		}
+	}
+}
+
 mod zoom {
 	use super::*;
 	use crate::interaction_core::input_subscriber::ScrollCallback;
@@ -1,36 +1,71 @@
+use std::cell::{Cell, RefCell};
+use std::mem::replace;
+use std::ops::Deref;
 use std::path::Path;
+use std::rc::Rc;
 
+use ghg_data_core::countries::CountryIndex;
 use image::LumaA;
+use serde_json::from_slice;
 use wasm_bindgen::JsValue;
 use web_sys::WebGl2RenderingContext;
 
+use crate::application::events::{detail_object, ViewerEvent, ViewerEvents};
 use crate::application::image_utility::biggest_mipmap_level;
+use crate::application::layers::{Layer, LayerStack, LayerTextures};
+use crate::application::probe::{cursor_on_globe, CountryLookup, ProbeSources};
 use crate::application::shaders::ShaderContext;
+use crate::application::tile_streaming::point_to_uv;
+use crate::interaction_core::user_inputs::LogicalCursorPosition;
 use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::camera::Camera;
 use crate::render_core::frame_sequencer::FrameGate;
-use crate::render_core::image::load_into_texture_with_filters;
-use crate::render_core::texture_provider::TextureProvider;
+use crate::render_core::image::{decode_png, load_into_texture_with_filters, CpuImage};
 use crate::render_core::uniform;
 use crate::request_data::fetch_bytes;
 use crate::utils::prelude::*;
 
 const COUNTRY_IMAGE_MAX_SIZE: usize = 21_600;
 
+const COUNTRY_ROOT: &str = "images/countries";
+
+/// The level kept on the CPU for looking countries up: 5400x2700, so about
+/// 15 MB once only the identities are kept
+const LOOKUP_LEVEL: usize = 2;
+
+/// The countries under the cursor and last clicked, as the byte their identity
+/// is stored as in `s_countryMap`. 0, the ocean, means none.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CountrySelection {
+	pub hovered: u8,
+	pub selected: u8,
+}
+
+impl CountrySelection {
+	/// Clicking a country selects it, and clicking it again or clicking the
+	/// ocean deselects it. Returns whether the selection changed.
+	pub fn click(&mut self, byte: u8) -> bool {
+		let selected = if byte == self.selected { 0 } else { byte };
+		replace(&mut self.selected, selected) != selected
+	}
+}
+
 async fn load_country_data(
 	shader_context: ShaderContext,
-	texture_index: u32,
+	textures: &LayerTextures,
 ) -> Result<(), JsValue> {
+	// Level 0 is only exported as tiles, there's no `full.png` for it
 	let mipmap_level =
-		biggest_mipmap_level(shader_context.context.clone(), COUNTRY_IMAGE_MAX_SIZE)?;
-	let country_root = Path::new("images/countries");
+		biggest_mipmap_level(shader_context.context.clone(), COUNTRY_IMAGE_MAX_SIZE)?.max(1);
+	let country_root = Path::new(COUNTRY_ROOT);
 	let country_map_image = country_root.join(format!("{mipmap_level}/full.png").as_str());
 
 	let texture = fetch_bytes(country_map_image.to_str().unwrap()).await?;
 	shader_context.use_shader();
 	load_into_texture_with_filters::<LumaA<u8>>(
-		shader_context.context.clone(),
+		textures.registry(),
 		&texture,
-		WebGl2RenderingContext::TEXTURE0 + texture_index,
+		textures.unit(Layer::Countries, "country_map"),
 		WebGl2RenderingContext::NEAREST, // Avoids weird boundary aliasing
 		WebGl2RenderingContext::NEAREST,
 	)?;
@@ -38,24 +73,134 @@ async fn load_country_data(
 	Ok(())
 }
 
+/// The country map's identities and their names
+async fn load_country_lookup() -> Result<CountryLookup, JsValue> {
+	let country_root = Path::new(COUNTRY_ROOT);
+
+	let index_path = country_root.join(CountryIndex::FILE_NAME);
+	let index_bytes = fetch_bytes(index_path.to_str().unwrap()).await?;
+	let index: CountryIndex = from_slice(&index_bytes).map_err(|e| e.to_string())?;
+
+	let image_path = country_root.join(format!("{LOOKUP_LEVEL}/full.png"));
+	let (pixels, dimensions) = decode_png(&fetch_bytes(image_path.to_str().unwrap()).await?)?;
+
+	// Identities are in the alpha channel
+	Ok(CountryLookup { identities: CpuImage::new(pixels, dimensions).into_channel(1), index })
+}
+
+/// The byte of the country under `cursor_location`, or 0 if there isn't one
+fn country_under_cursor(
+	cursor_location: Option<LogicalCursorPosition>,
+	camera: &Camera,
+	lookup: &CountryLookup,
+	width: i32,
+	height: i32,
+) -> u8 {
+	cursor_location
+		.and_then(|cursor_location| cursor_on_globe(cursor_location, camera, width, height))
+		.map_or(0, |point| lookup.identity_byte(point_to_uv(&point)))
+}
+
+/// The selection's identity and name, for a `ViewerEvent::Selection`
+fn selection_detail(lookup: &CountryLookup, selected: u8) -> JsValue {
+	let identity = lookup.index.decode(selected).filter(|&identity| identity != 0);
+	let name = lookup.index.name_of_byte(selected);
+	detail_object(&[
+		("identity", identity.map_or(JsValue::NULL, Into::into)),
+		("name", name.map_or(JsValue::NULL, Into::into)),
+	])
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn draw_borders(
 	gate: FrameGate<AnimationParams>,
+	events: ViewerEvents,
 	shader_context: ShaderContext,
-	mut texture_provider: TextureProvider,
+	camera: Rc<RefCell<Camera>>,
+	textures: LayerTextures,
+	layers: Rc<RefCell<LayerStack>>,
+	current_cursor_location: Rc<Cell<Option<LogicalCursorPosition>>>,
+	current_click: Rc<Cell<Option<LogicalCursorPosition>>>,
+	probe_sources: Rc<RefCell<ProbeSources>>,
 ) {
-	let texture_index = texture_provider.take();
+	let texture_index = textures.unit(Layer::Countries, "country_map").index();
 
 	shader_context.use_shader();
 
 	let _texture_uniform =
 		uniform::init_smart_i32("s_countryMap", &shader_context, texture_index as i32);
-	let load_result = load_country_data(shader_context.clone(), texture_index).await;
+	let load_result = load_country_data(shader_context.clone(), &textures).await;
 	if !load_result.is_ok() {
 		ghg_error!("Failed to load country data: {:?}", load_result);
 		return;
 	}
 
+	// Borders can still be drawn without names, but nothing can be hovered or
+	// selected
+	match load_country_lookup().await {
+		Ok(lookup) => probe_sources.borrow_mut().countries = Some(lookup),
+		Err(e) => ghg_error!("Failed to load country names: {:?}", e),
+	}
+	events.send(ViewerEvent::Loaded, &detail_object(&[("part", "countries".into())]));
+
+	shader_context.use_shader();
+	let mut selection = CountrySelection::default();
+	let mut hovered_uniform = uniform::init_smart_i32("u_hoveredCountry", &shader_context, 0);
+	let mut selected_uniform = uniform::init_smart_i32("u_selectedCountry", &shader_context, 0);
+
 	loop {
-		let _params = (&gate).await;
+		let params = (&gate).await;
+
+		let width = params.viewport.width() as i32;
+		let height = params.viewport.height() as i32;
+		let visible = layers.borrow().is_visible(Layer::Countries);
+		let sources = probe_sources.borrow();
+		let camera = camera.deref().borrow();
+
+		let click = current_click.take();
+		if let Some(lookup) = sources.countries.as_ref().filter(|_| visible) {
+			selection.hovered =
+				country_under_cursor(current_cursor_location.get(), &camera, lookup, width, height);
+
+			if let Some(click_location) = click {
+				let clicked =
+					country_under_cursor(Some(click_location), &camera, lookup, width, height);
+				if selection.click(clicked) {
+					events.send(
+						ViewerEvent::Selection,
+						&selection_detail(lookup, selection.selected),
+					);
+				}
+			}
+		} else {
+			selection.hovered = 0;
+		}
+
+		shader_context.use_shader();
+		hovered_uniform.smart_write(selection.hovered as i32);
+		selected_uniform.smart_write(selection.selected as i32);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn clicks_toggle_the_selection() {
+		let mut selection = CountrySelection::default();
+
+		assert!(selection.click(36));
+		assert_eq!(selection.selected, 36);
+		assert!(selection.click(72));
+		assert_eq!(selection.selected, 72);
+
+		// Clicking the selected country again, or the ocean, deselects it
+		assert!(selection.click(72));
+		assert_eq!(selection.selected, 0);
+		assert!(!selection.click(0));
+		assert!(selection.click(36));
+		assert!(selection.click(0));
+		assert_eq!(selection.selected, 0);
 	}
 }
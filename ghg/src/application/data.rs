@@ -1,19 +1,27 @@
-use std::cell::Cell;
-use std::future::join;
+use std::cell::RefCell;
+use std::future::{join, Future};
 use std::path::Path;
 use std::rc::Rc;
 
-use ghg_data_core::metadata::Metadata;
+use ghg_data_core::color_map::{ColorMap, ColorScale, LOOKUP_TABLE_SIZE};
+use ghg_data_core::metadata::{ChannelMetadata, Metadata};
 use image::Rgba;
+use phf::{phf_map, Map};
 use serde_json::from_slice;
 use wasm_bindgen::JsValue;
 use web_sys::WebGl2RenderingContext;
 
+use crate::application::events::{detail_object, ViewerEvent, ViewerEvents};
+use crate::application::image_utility::biggest_mipmap_level;
+use crate::application::layers::{Layer, LayerStack, LayerTextures};
+use crate::application::legend::Legend;
+use crate::application::probe::{DataImage, ProbeSources};
 use crate::application::shaders::ShaderContext;
+use crate::application::timeline::Timeline;
 use crate::render_core::animation_params::AnimationParams;
 use crate::render_core::frame_sequencer::FrameGate;
-use crate::render_core::image::load_into_texture_with_filters;
-use crate::render_core::texture_provider::TextureProvider;
+use crate::render_core::image::{decode_png, load_pixels_into_texture_with_filters, CpuImage};
+use crate::render_core::texture_provider::TextureUnit;
 use crate::render_core::uniform;
 use crate::request_data::fetch_bytes;
 use crate::utils::prelude::*;
@@ -25,7 +33,20 @@ use crate::utils::prelude::*;
 // 	pub max_uniforms: SmartUniform<nglm::Vec4>,
 // }
 
-const MONTH_NAMES: [&str; 12] = [
+const DATA_IMAGE_MAX_SIZE: usize = 576;
+
+/// Each year is split across images of one month per channel. These are
+/// injected into the planet shader as `NUM_MAPS_PER_YEAR` and
+/// `NUM_CHANNELS_IN_MAP`.
+pub const MAPS_PER_YEAR: usize = 3;
+pub const CHANNELS_PER_MAP: usize = 4;
+
+/// Units of each dataset's values, by directory. MERRA-2 `T2M` is in Kelvin.
+const DATASET_UNITS: Map<&str, &str> = phf_map! {
+	"earth_temp" => "K",
+};
+
+pub const MONTH_NAMES: [&str; 12] = [
 	"January",
 	"February",
 	"March",
@@ -40,81 +61,448 @@ const MONTH_NAMES: [&str; 12] = [
 	"December",
 ];
 
-async fn load_temp_data(
-	shader_context: ShaderContext,
+/// The data drawn on the globe: a directory of exported images under
+/// `images`, and which year of it to show
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dataset {
+	pub name: String,
+	pub year: u32,
+}
+
+impl Default for Dataset {
+	fn default() -> Self { Self { name: "earth_temp".to_owned(), year: 2021 } }
+}
+
+impl Dataset {
+	/// The images holding the year's months, `{year}.{first month}.{last
+	/// month}`
+	pub fn file_stems(&self) -> [String; MAPS_PER_YEAR] {
+		std::array::from_fn(|map| {
+			let first_month = map * CHANNELS_PER_MAP + 1;
+			let last_month = first_month + CHANNELS_PER_MAP - 1;
+			format!("{}.{first_month:02}.{last_month:02}", self.year)
+		})
+	}
+
+	/// Units of the dataset's values, or nothing if they're unknown
+	pub fn units(&self) -> &'static str {
+		DATASET_UNITS.get(self.name.as_str()).copied().unwrap_or_default()
+	}
+}
+
+/// Which map of a year holds `month`, from 0 for January, and which of its
+/// channels. The planet shader finds months the same way, from the
+/// `CHANNELS_PER_MAP` injected into it.
+pub fn month_location(month: usize) -> (usize, usize) {
+	(month / CHANNELS_PER_MAP, month % CHANNELS_PER_MAP)
+}
+
+/// The texture unit `map` of a year is loaded into
+fn map_unit(textures: &LayerTextures, map: usize) -> &TextureUnit {
+	textures.unit(Layer::Data, &format!("data_map_{map}"))
+}
+
+/// Fetches and decodes `file_stem`'s image and metadata, without replacing
+/// what's drawn
+async fn fetch_data_image(
+	context: &WebGl2RenderingContext,
+	dataset: &Dataset,
 	file_stem: &str,
-	texture_index: u32,
-) -> Result<Metadata, JsValue> {
-	let temp_root = Path::new("images/earth_temp");
+) -> Result<DataImage, JsValue> {
+	let mipmap_level = biggest_mipmap_level(context.clone(), DATA_IMAGE_MAX_SIZE)?;
+	let data_root = Path::new("images").join(&dataset.name);
 
-	let summer_temp_image = temp_root.join((file_stem.to_owned() + ".png").as_str());
-	let summer_temp_metadata = summer_temp_image.with_extension("metadata");
+	let data_image = data_root.join(format!("{mipmap_level}/{file_stem}.png").as_str());
+	let data_metadata = data_root.join(format!("{file_stem}.metadata").as_str());
 
-	let texture = fetch_bytes(summer_temp_image.to_str().unwrap()).await?;
+	let texture = fetch_bytes(data_image.to_str().unwrap()).await?;
 
-	let metadata_bytes = fetch_bytes(summer_temp_metadata.to_str().unwrap()).await?;
+	let metadata_bytes = fetch_bytes(data_metadata.to_str().unwrap()).await?;
 	let metadata: Metadata = from_slice(&metadata_bytes).map_err(|e| e.to_string())?;
 
+	let (pixels, dimensions) = decode_png(&texture)?;
+	Ok(DataImage {
+		name: file_stem.to_owned(),
+		image: CpuImage::new(pixels, dimensions),
+		metadata,
+		units: dataset.units(),
+	})
+}
+
+fn upload_data_image(
+	shader_context: &ShaderContext,
+	textures: &LayerTextures,
+	data_image: &DataImage,
+	map: usize,
+) -> Result<(), JsValue> {
 	shader_context.use_shader();
-	load_into_texture_with_filters::<Rgba<u8>>(
-		shader_context.context.clone(),
-		&texture,
-		WebGl2RenderingContext::TEXTURE0 + texture_index,
+	load_pixels_into_texture_with_filters::<Rgba<u8>>(
+		textures.registry(),
+		&data_image.image.pixels,
+		(data_image.image.width, data_image.image.height),
+		map_unit(textures, map),
 		WebGl2RenderingContext::LINEAR,
 		WebGl2RenderingContext::NEAREST,
-	)?;
+	)
+}
+
+/// The dataset's own color scale, or the default if it doesn't have one
+async fn load_color_scale(dataset: &Dataset) -> ColorScale {
+	let path = Path::new("images").join(&dataset.name).join(ColorScale::FILE_NAME);
+	let loaded = match fetch_bytes(path.to_str().unwrap()).await {
+		Ok(bytes) => from_slice::<ColorScale>(&bytes).map_err(|e| e.to_string()),
+		Err(e) => {
+			ghg_log!("No color scale for {}, using the default: {:?}", dataset.name, e);
+			return ColorScale::default();
+		}
+	};
 
-	Ok(metadata)
+	match loaded.and_then(|color_scale| color_scale.color_map.validate().map(|_| color_scale)) {
+		Ok(color_scale) => color_scale,
+		Err(e) => {
+			ghg_error!("Invalid color scale for {}, using the default: {e}", dataset.name);
+			ColorScale::default()
+		}
+	}
 }
 
-pub async fn handle_data(
-	gate: FrameGate<AnimationParams>,
-	shader_context: ShaderContext,
-	current_month: Rc<Cell<usize>>,
-	mut texture_provider: TextureProvider,
-) {
-	let map_indices: [u32; 3] =
-		[texture_provider.take(), texture_provider.take(), texture_provider.take()];
+/// Uploads `color_map`'s lookup table, a single row of texels
+fn load_color_map(
+	shader_context: &ShaderContext,
+	textures: &LayerTextures,
+	color_map: &ColorMap,
+) -> Result<(), JsValue> {
+	shader_context.use_shader();
+	load_pixels_into_texture_with_filters::<Rgba<u8>>(
+		textures.registry(),
+		&color_map.lookup_table(),
+		(LOOKUP_TABLE_SIZE as u32, 1),
+		textures.unit(Layer::Data, "data_color_map"),
+		WebGl2RenderingContext::LINEAR,
+		WebGl2RenderingContext::LINEAR,
+	)
+}
 
-	let load_all_results = join!(
-		load_temp_data(shader_context.clone(), "2021.01.04", map_indices[0]),
-		load_temp_data(shader_context.clone(), "2021.05.08", map_indices[1]),
-		load_temp_data(shader_context.clone(), "2021.09.12", map_indices[2]),
+/// Fetches every map of the dataset, so none are uploaded unless they all
+/// load
+async fn fetch_dataset(
+	context: &WebGl2RenderingContext,
+	dataset: &Dataset,
+) -> Result<[DataImage; MAPS_PER_YEAR], JsValue> {
+	let [first, second, third] = dataset.file_stems();
+	let fetch_all_results = join!(
+		fetch_data_image(context, dataset, &first),
+		fetch_data_image(context, dataset, &second),
+		fetch_data_image(context, dataset, &third),
 	)
 	.await;
 
-	if !load_all_results.0.is_ok() || !load_all_results.1.is_ok() || !load_all_results.2.is_ok() {
-		ghg_error!("Failed to load some temperature data: {:?}", load_all_results)
+	Ok([fetch_all_results.0?, fetch_all_results.1?, fetch_all_results.2?])
+}
+
+async fn load_dataset(
+	shader_context: &ShaderContext,
+	textures: &LayerTextures,
+	dataset: &Dataset,
+) -> Result<[DataImage; MAPS_PER_YEAR], LoadError> {
+	let data_images =
+		fetch_dataset(&shader_context.context, dataset).await.map_err(LoadError::Fetch)?;
+	for (map, data_image) in data_images.iter().enumerate() {
+		upload_data_image(shader_context, textures, data_image, map).map_err(LoadError::Upload)?;
 	}
+	Ok(data_images)
+}
 
-	let mins_and_maxes: [(nglm::Vec4, nglm::Vec4); 3] = [
-		load_all_results.0.ok().unwrap().clone().try_into().expect("Failed to convert metadata"),
-		load_all_results.1.ok().unwrap().clone().try_into().expect("Failed to convert metadata"),
-		load_all_results.2.ok().unwrap().clone().try_into().expect("Failed to convert metadata"),
-	];
+/// Why a dataset didn't load
+#[derive(Debug)]
+enum LoadError {
+	/// Nothing was replaced
+	Fetch(JsValue),
+	/// Some of the maps may have been replaced
+	Upload(JsValue),
+}
 
-	const NUM_CHANNELS: i32 = 4;
+/// Which dataset is loaded, and which the page has asked for
+struct DatasetSwitch {
+	requested: Rc<RefCell<Dataset>>,
+	loaded: Option<Dataset>,
+	/// Not tried again until another is chosen, if there was nothing to go back
+	/// to
+	failed: Option<Dataset>,
+}
 
-	let (mins, maxes): (Vec<nglm::Vec4>, Vec<nglm::Vec4>) = mins_and_maxes.into_iter().unzip();
+impl DatasetSwitch {
+	fn new(requested: Rc<RefCell<Dataset>>) -> Self {
+		Self { requested, loaded: None, failed: None }
+	}
 
-	let min_mat = nglm::Mat4x3::from_columns(&mins);
-	let max_mat = nglm::Mat4x3::from_columns(&maxes);
+	/// Loads the requested dataset with `load`, unless it's already loaded or
+	/// has failed to. If it fails, the previous dataset is asked for again,
+	/// unless the page has asked for another in the meantime.
+	async fn switch<T, Load: Future<Output = Result<T, LoadError>>>(
+		&mut self,
+		load: impl FnOnce(Dataset) -> Load,
+	) -> Option<(Dataset, Result<T, LoadError>)> {
+		let wanted = self.requested.borrow().clone();
+		if self.loaded.as_ref() == Some(&wanted) || self.failed.as_ref() == Some(&wanted) {
+			return None;
+		}
 
-	let _min_uniforms = uniform::init_smart_mat4x3("u_dataMinValues", &shader_context, min_mat);
-	let _max_uniforms = uniform::init_smart_mat4x3("u_dataMaxValues", &shader_context, max_mat);
+		let result = load(wanted.clone()).await;
+		match &result {
+			Ok(_) => {
+				self.loaded = Some(wanted.clone());
+				self.failed = None;
+			}
+			Err(e) => {
+				let previous = self.loaded.clone();
+				if let LoadError::Upload(_) = e {
+					// What's drawn is a mix of the two, so the previous one is loaded
+					// again
+					self.loaded = None;
+				}
 
+				// Another chosen while this one loaded is left to load next
+				if *self.requested.borrow() == wanted {
+					match previous {
+						Some(previous) => *self.requested.borrow_mut() = previous,
+						None => self.failed = Some(wanted.clone()),
+					}
+				}
+			}
+		}
+		Some((wanted, result))
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_data(
+	gate: FrameGate<AnimationParams>,
+	events: ViewerEvents,
+	shader_context: ShaderContext,
+	dataset: Rc<RefCell<Dataset>>,
+	timeline: Rc<RefCell<Timeline>>,
+	color_scale: Rc<RefCell<Option<ColorScale>>>,
+	textures: LayerTextures,
+	layers: Rc<RefCell<LayerStack>>,
+	probe_sources: Rc<RefCell<ProbeSources>>,
+) {
+	let map_indices: [u32; MAPS_PER_YEAR] =
+		std::array::from_fn(|map| map_unit(&textures, map).index());
+	let color_map_index = textures.unit(Layer::Data, "data_color_map").index();
+
+	shader_context.use_shader();
+	let mut min_uniforms = uniform::new_smart_vec4_array("u_dataMinValues", &shader_context);
+	let mut max_uniforms = uniform::new_smart_vec4_array("u_dataMaxValues", &shader_context);
 	let mut texture_uniform = uniform::new_smart_i32("s_dataMap", &shader_context);
+	let mut next_texture_uniform = uniform::new_smart_i32("s_dataNextMap", &shader_context);
 	let mut data_month_uniform = uniform::new_smart_i32("u_dataMonth", &shader_context);
+	let mut next_data_month_uniform = uniform::new_smart_i32("u_dataNextMonth", &shader_context);
+	let mut blend_uniform = uniform::new_smart_f32("u_dataBlend", &shader_context);
+	let _color_map_uniform =
+		uniform::init_smart_i32("s_dataColorMap", &shader_context, color_map_index as i32);
+	let mut has_midpoint_uniform = uniform::new_smart_i32("u_dataHasMidpoint", &shader_context);
+	let mut midpoint_uniform = uniform::new_smart_f32("u_dataMidpoint", &shader_context);
+
+	let mut dataset_switch = DatasetSwitch::new(dataset);
+	let mut dataset_color_scale = ColorScale::default();
+	let mut shown_color_scale = None;
+	// Each time step's range, and the units they're in
+	let mut channel_ranges: Vec<ChannelMetadata> = Vec::new();
+	let mut units = "";
+	let mut shown_legend = None;
 
 	loop {
+		// A new dataset is loaded between frames, while the previous one is still
+		// drawn
+		let switched = dataset_switch
+			.switch(|wanted| {
+				let (shader_context, textures) = (&shader_context, &textures);
+				async move { load_dataset(shader_context, textures, &wanted).await }
+			})
+			.await;
+		if let Some((wanted_dataset, result)) = switched {
+			let dataset_detail = [
+				("part", "data".into()),
+				("dataset", wanted_dataset.name.as_str().into()),
+				("year", wanted_dataset.year.into()),
+			];
+			match result {
+				Ok(data_images) => {
+					let (mins, maxes): (Vec<nglm::Vec4>, Vec<nglm::Vec4>) = data_images
+						.iter()
+						.map(|data| -> (nglm::Vec4, nglm::Vec4) {
+							data.metadata.clone().try_into().expect("Failed to convert metadata")
+						})
+						.unzip();
+
+					shader_context.use_shader();
+					min_uniforms.smart_write(mins);
+					max_uniforms.smart_write(maxes);
+					let step_count = data_images.iter().map(|data| data.metadata.0.len()).sum();
+					timeline.borrow_mut().set_steps(wanted_dataset.year, step_count);
+					channel_ranges =
+						data_images.iter().flat_map(|data| data.metadata.0.clone()).collect();
+					units = wanted_dataset.units();
+					probe_sources.borrow_mut().data = data_images.into();
+					dataset_color_scale = load_color_scale(&wanted_dataset).await;
+
+					events.send(ViewerEvent::Loaded, &detail_object(&dataset_detail));
+				}
+				Err(e) => {
+					ghg_error!("Failed to load data for {:?}: {:?}", wanted_dataset, e);
+					let error = match &e {
+						LoadError::Fetch(e) | LoadError::Upload(e) => format!("{e:?}"),
+					};
+					let failed_detail = [dataset_detail.as_slice(), &[("error", error.into())]];
+					events.send(ViewerEvent::Loaded, &detail_object(&failed_detail.concat()));
+				}
+			}
+		}
+
 		let _params = (&gate).await;
 
-		let current_month = current_month.get() as i32;
-		let current_map_index = current_month / NUM_CHANNELS;
+		// The page's color scale, if it's chosen one
+		let wanted_color_scale =
+			color_scale.borrow().clone().unwrap_or_else(|| dataset_color_scale.clone());
+		if shown_color_scale.as_ref() != Some(&wanted_color_scale) {
+			if let Err(e) =
+				load_color_map(&shader_context, &textures, &wanted_color_scale.color_map)
+			{
+				ghg_error!("Failed to load color map {:?}: {:?}", wanted_color_scale.color_map, e);
+			}
+			shown_color_scale = Some(wanted_color_scale);
+		}
+		let midpoint = shown_color_scale.as_ref().and_then(|color_scale| color_scale.midpoint);
+
+		// The two months can be in different maps, so each gets its own
+		let (month, next_month, blend) = timeline.borrow().blend();
+		let map_of = |month: usize| map_indices[month_location(month).0] as i32;
 
 		shader_context.use_shader();
 
-		texture_uniform.smart_write(map_indices[current_map_index as usize] as i32);
-		data_month_uniform.smart_write(current_month);
+		texture_uniform.smart_write(map_of(month));
+		next_texture_uniform.smart_write(map_of(next_month));
+		data_month_uniform.smart_write(month as i32);
+		next_data_month_uniform.smart_write(next_month as i32);
+		blend_uniform.smart_write(blend);
+		has_midpoint_uniform.smart_write(midpoint.is_some() as i32);
+		midpoint_uniform.smart_write(midpoint.unwrap_or_default() as f32);
+		let visible = layers.borrow().is_visible(Layer::Data);
+
+		// The legend follows the time step shown, rather than the blend
+		let legend = match (visible, &shown_color_scale, channel_ranges.get(month)) {
+			(true, Some(color_scale), Some(&channel)) => {
+				Some(Legend::new(color_scale, channel, units))
+			}
+			_ => None,
+		};
+		if shown_legend.as_ref() != Some(&legend) {
+			events
+				.send(ViewerEvent::Legend, &legend.as_ref().map_or(JsValue::NULL, Legend::detail));
+			shown_legend = Some(legend);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	#[test]
+	fn blends_across_maps() {
+		assert_eq!(month_location(0), (0, 0));
+		assert_eq!(month_location(3), (0, 3));
+		assert_eq!(month_location(4), (1, 0));
+		assert_eq!(month_location(11), (2, 3));
+
+		let mut timeline = Timeline::default();
+		timeline.set_steps(2021, MAPS_PER_YEAR * CHANNELS_PER_MAP);
+		timeline.seek(3).unwrap();
+		timeline.play();
+		timeline.advance(Duration::from_millis(250));
+		let (month, next_month, _) = timeline.blend();
+		assert_eq!((month_location(month), month_location(next_month)), ((0, 3), (1, 0)));
+
+		// December blends back into January, at the start of the first map
+		timeline.seek(11).unwrap();
+		timeline.advance(Duration::from_millis(250));
+		let (month, next_month, _) = timeline.blend();
+		assert_eq!((month_location(month), month_location(next_month)), ((2, 3), (0, 0)));
+	}
+
+	fn dataset(year: u32) -> Dataset { Dataset { name: "earth_temp".to_owned(), year } }
+
+	fn switch_to(
+		switch: &mut DatasetSwitch,
+		load: impl FnOnce(Dataset) -> Result<(), LoadError>,
+	) -> Option<Dataset> {
+		let switched = async_std::task::block_on(switch.switch(|wanted| async { load(wanted) }));
+		switched.map(|(dataset, _)| dataset)
+	}
+
+	#[test]
+	fn goes_back_to_the_previous_dataset_when_one_fails() {
+		let requested = Rc::new(RefCell::new(dataset(1980)));
+		let mut switch = DatasetSwitch::new(requested.clone());
+		assert_eq!(switch_to(&mut switch, |_| Ok(())), Some(dataset(1980)));
+		assert_eq!(switch_to(&mut switch, |_| panic!("Already loaded")), None);
+
+		requested.replace(dataset(1990));
+		let failed = switch_to(&mut switch, |_| Err(LoadError::Fetch(JsValue::NULL)));
+		assert_eq!(failed, Some(dataset(1990)));
+		assert_eq!(*requested.borrow(), dataset(1980));
+		assert_eq!(switch_to(&mut switch, |_| panic!("Still loaded")), None);
+
+		// A failed upload leaves a mix of the two, so the previous one is loaded again
+		requested.replace(dataset(1990));
+		switch_to(&mut switch, |_| Err(LoadError::Upload(JsValue::NULL)));
+		assert_eq!(*requested.borrow(), dataset(1980));
+		assert_eq!(switch_to(&mut switch, |_| Ok(())), Some(dataset(1980)));
+	}
+
+	#[test]
+	fn keeps_datasets_asked_for_while_another_fails() {
+		let requested = Rc::new(RefCell::new(dataset(1980)));
+		let mut switch = DatasetSwitch::new(requested.clone());
+		switch_to(&mut switch, |_| Ok(()));
+
+		requested.replace(dataset(1990));
+		switch_to(&mut switch, |_| {
+			requested.replace(dataset(2000));
+			Err(LoadError::Fetch(JsValue::NULL))
+		});
+		assert_eq!(*requested.borrow(), dataset(2000));
+		assert_eq!(switch_to(&mut switch, |_| Ok(())), Some(dataset(2000)));
+	}
+
+	#[test]
+	fn waits_for_another_dataset_when_the_first_fails() {
+		let requested = Rc::new(RefCell::new(dataset(1980)));
+		let mut switch = DatasetSwitch::new(requested.clone());
+
+		switch_to(&mut switch, |_| Err(LoadError::Fetch(JsValue::NULL)));
+		assert_eq!(*requested.borrow(), dataset(1980));
+		assert_eq!(switch_to(&mut switch, |_| panic!("Not tried again")), None);
+
+		// Unless another was asked for while it loaded
+		requested.replace(dataset(1990));
+		switch_to(&mut switch, |_| {
+			requested.replace(dataset(2000));
+			Err(LoadError::Fetch(JsValue::NULL))
+		});
+		assert_eq!(switch_to(&mut switch, |_| Ok(())), Some(dataset(2000)));
+	}
+
+	#[test]
+	fn datasets_cover_each_month() {
+		let dataset = Dataset { name: "earth_temp".to_owned(), year: 1980 };
+		assert_eq!(dataset.file_stems(), ["1980.01.04", "1980.05.08", "1980.09.12"]);
+		assert_eq!(dataset.units(), "K");
+
+		let unknown = Dataset { name: "sea_level".to_owned(), year: 1980 };
+		assert_eq!(unknown.units(), "");
 	}
 }
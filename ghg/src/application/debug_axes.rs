@@ -74,10 +74,6 @@ pub async fn draw(
 	let mut scale = uniform::new_smart_f32("u_meshScale", &shader);
 	let mut location = uniform::new_smart_vec3("u_meshTranslation", &shader);
 
-	let mut model = uniform::new_smart_mat4("u_model", &shader);
-	let mut view = uniform::new_smart_mat4("u_view", &shader);
-	let mut projection = uniform::new_smart_mat4("u_projection", &shader);
-
 	loop {
 		let params = (&gate).await;
 
@@ -86,15 +82,6 @@ pub async fn draw(
 		scale.smart_write(1.0);
 		location.smart_write(nglm::Vec3::zeros());
 
-		let width = params.viewport.width() as i32;
-		let height = params.viewport.height() as i32;
-
-		let mvp = camera.deref().borrow().get_perspective_matrices(width, height);
-
-		model.smart_write(mvp.model.clone());
-		view.smart_write(mvp.view.clone());
-		projection.smart_write(mvp.projection.clone());
-
 		draw_meshes(
 			params.viewport.context(),
 			camera.deref().borrow().deref(),
@@ -0,0 +1,84 @@
+use web_sys::{CustomEvent, CustomEventInit, HtmlCanvasElement};
+
+use crate::utils::prelude::*;
+
+/// Events the viewer sends the page, as `CustomEvent`s on its canvas. What
+/// happened is in each event's `detail`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewerEvent {
+	/// Part of the globe finished loading: `{ part }`, one of `"planet"`,
+	/// `"countries"` or `"data"`. Data also has `{ dataset, year }`, and an
+	/// `error` if it failed to load, in which case the previous dataset is
+	/// chosen again.
+	Loaded,
+	/// What's under the cursor changed: `{ x, y, coordinates, value, date,
+	/// country }`, with `x` and `y` in CSS pixels from the canvas' top left.
+	/// `detail` is `null` once the cursor leaves the globe.
+	Probe,
+	/// A country was selected or deselected: `{ identity, name }`, both `null`
+	/// once nothing is selected
+	Selection,
+	/// The timeline moved or its playback changed: `{ step, stepCount, year,
+	/// month, date, playing, stepsPerSecond, rangeStart, rangeEnd, looping }`,
+	/// with `month` from 0 for January
+	Timeline,
+	/// What the data layer's colors mean changed: `{ units, min, max, ticks,
+	/// gradient }`, with each tick `{ value, label, position }` along the color
+	/// bar from 0 to 1, and `gradient` a CSS `linear-gradient` of the colors.
+	/// `detail` is `null` while the data layer is hidden.
+	Legend,
+	/// Reloaded shaders failed to compile or link, in dev builds: `{ log }`,
+	/// with each error's file and line in the original sources. `detail` is
+	/// `null` once they all reload.
+	ShaderError,
+}
+
+impl ViewerEvent {
+	pub fn event_type(self) -> &'static str {
+		match self {
+			ViewerEvent::Loaded => "viewerload",
+			ViewerEvent::Probe => "probechange",
+			ViewerEvent::Selection => "countryselectionchange",
+			ViewerEvent::Timeline => "timelinechange",
+			ViewerEvent::Legend => "legendchange",
+			ViewerEvent::ShaderError => "shadererror",
+		}
+	}
+}
+
+/// Sends `ViewerEvent`s from the canvas they're drawn on
+#[derive(Clone)]
+pub struct ViewerEvents {
+	canvas: HtmlCanvasElement,
+}
+
+impl ViewerEvents {
+	pub fn new(canvas: HtmlCanvasElement) -> Self { Self { canvas } }
+
+	pub fn canvas(&self) -> &HtmlCanvasElement { &self.canvas }
+
+	pub fn send(&self, event: ViewerEvent, detail: &JsValue) {
+		if let Err(e) = self.dispatch(event, detail) {
+			ghg_error!("Failed to send {:?} event: {:?}", event, e);
+		}
+	}
+
+	fn dispatch(&self, event: ViewerEvent, detail: &JsValue) -> Result<(), JsValue> {
+		let init = CustomEventInit::new();
+		init.set_detail(detail);
+		let custom_event = CustomEvent::new_with_event_init_dict(event.event_type(), &init)?;
+		self.canvas.dispatch_event(&custom_event)?;
+		Ok(())
+	}
+}
+
+/// A plain object for an event's `detail`
+pub fn detail_object(fields: &[(&str, JsValue)]) -> JsValue {
+	let object = js_sys::Object::new();
+	for (name, value) in fields {
+		js_sys::Reflect::set(&object, &JsValue::from_str(name), value)
+			.expect("Failed to set a property on a new object");
+	}
+	object.into()
+}
@@ -0,0 +1,357 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::application::shaders::ShaderContext;
+use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::frame_sequencer::FrameGate;
+use crate::render_core::texture_provider::TextureUnit;
+use crate::render_core::texture_registry::TextureRegistry;
+use crate::render_core::uniform;
+use crate::utils::prelude::*;
+
+/// How many layers the planet shader can compose, which it has defined as
+/// `MAX_LAYERS`
+pub const MAX_LAYERS: usize = 4;
+
+/// What can be drawn over the terrain. Each layer's color comes from its own
+/// function in the planet shader, and its task fills in its textures and
+/// uniforms.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Layer {
+	Countries,
+	Data,
+}
+
+/// What a layer needs from the planet shader
+#[derive(Clone, Copy, Debug)]
+pub struct LayerDeclaration {
+	/// Defined in the planet shader as the layer's id
+	pub shader_constant: &'static str,
+	/// The GLSL function, taking the texture point, that gives the layer's
+	/// color
+	pub color_function: &'static str,
+	/// Named so the layer's task can ask for each one's texture unit
+	pub textures: &'static [&'static str],
+	/// Checked against the shader once it's compiled, to catch a layer whose
+	/// GLSL is missing
+	pub uniforms: &'static [&'static str],
+	pub default_style: LayerStyle,
+}
+
+impl Layer {
+	pub const ALL: [Layer; 2] = [Layer::Countries, Layer::Data];
+
+	/// The layer's `shader_constant` in the planet shader
+	fn shader_id(self) -> i32 { self as i32 }
+
+	pub fn declaration(self) -> LayerDeclaration {
+		match self {
+			Layer::Countries => LayerDeclaration {
+				shader_constant: "LAYER_COUNTRIES",
+				color_function: "getCountryColor",
+				textures: &["country_map"],
+				uniforms: &["s_countryMap", "u_hoveredCountry", "u_selectedCountry"],
+				default_style: LayerStyle {
+					visible: true,
+					opacity: 0.6,
+					blend_mode: BlendMode::Normal,
+				},
+			},
+			Layer::Data => LayerDeclaration {
+				shader_constant: "LAYER_DATA",
+				color_function: "getDataColor",
+				textures: &["data_map_0", "data_map_1", "data_map_2", "data_color_map"],
+				uniforms: &[
+					"s_dataMap",
+					"s_dataNextMap",
+					"s_dataColorMap",
+					"u_dataMonth",
+					"u_dataNextMonth",
+					"u_dataBlend",
+					"u_dataMinValues",
+					"u_dataMaxValues",
+					"u_dataHasMidpoint",
+					"u_dataMidpoint",
+				],
+				default_style: LayerStyle {
+					visible: false,
+					opacity: 0.4,
+					blend_mode: BlendMode::Normal,
+				},
+			},
+		}
+	}
+}
+
+/// What the planet shader is compiled with for composing layers: how many it
+/// can compose, each layer's id, and `LAYER_COLOR(layer, texturePoint)`,
+/// which calls the color function of the layer with that id
+pub fn shader_defines() -> Vec<(&'static str, String)> {
+	let mut defines = vec![("MAX_LAYERS", MAX_LAYERS.to_string())];
+	let mut dispatch = String::new();
+	for layer in Layer::ALL {
+		let declaration = layer.declaration();
+		defines.push((declaration.shader_constant, layer.shader_id().to_string()));
+		dispatch += &format!(
+			"layer == {} ? {}(texturePoint) : ",
+			declaration.shader_constant, declaration.color_function
+		);
+	}
+	defines.push(("LAYER_COLOR(layer, texturePoint)", format!("({dispatch}vec4(0.0))")));
+	defines
+}
+
+/// How a layer's color is combined with the layers beneath it, before it's
+/// faded in by its opacity
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+	/// The layer's color replaces what's beneath
+	Normal,
+	/// Darkens what's beneath, by the layer's color
+	Multiply,
+	/// Lightens what's beneath, by the layer's color
+	Screen,
+	Add,
+}
+
+impl BlendMode {
+	/// The mode's `BLEND_` constant in `layers.glsl`
+	fn shader_id(self) -> i32 { self as i32 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerStyle {
+	pub visible: bool,
+	pub opacity: f32,
+	pub blend_mode: BlendMode,
+}
+
+/// The layers drawn over the terrain, in the order they're composed, and how
+/// each is drawn
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerStack {
+	/// Bottom first
+	order: Vec<Layer>,
+	styles: HashMap<Layer, LayerStyle>,
+}
+
+impl Default for LayerStack {
+	fn default() -> Self {
+		Self {
+			order: Layer::ALL.to_vec(),
+			styles: Layer::ALL
+				.iter()
+				.map(|&layer| (layer, layer.declaration().default_style))
+				.collect(),
+		}
+	}
+}
+
+impl LayerStack {
+	pub fn style(&self, layer: Layer) -> LayerStyle { self.styles[&layer] }
+
+	pub fn is_visible(&self, layer: Layer) -> bool { self.style(layer).visible }
+
+	pub fn set_visible(&mut self, layer: Layer, visible: bool) {
+		self.style_mut(layer).visible = visible;
+	}
+
+	pub fn set_opacity(&mut self, layer: Layer, opacity: f32) -> Result<(), String> {
+		if !(0.0..=1.0).contains(&opacity) {
+			return Err(format!("Opacity must be from 0 to 1, not {opacity}"));
+		}
+		self.style_mut(layer).opacity = opacity;
+		Ok(())
+	}
+
+	pub fn set_blend_mode(&mut self, layer: Layer, blend_mode: BlendMode) {
+		self.style_mut(layer).blend_mode = blend_mode;
+	}
+
+	/// Where `layer` is composed, from 0 at the bottom
+	pub fn position(&self, layer: Layer) -> usize {
+		self.order.iter().position(|&other| other == layer).expect("Every layer is in the stack")
+	}
+
+	/// Moves `layer` to `position`, from 0 at the bottom, shifting the layers
+	/// from there up
+	pub fn set_position(&mut self, layer: Layer, position: usize) -> Result<(), String> {
+		if position >= self.order.len() {
+			return Err(format!("Position {position} is outside the {} layers", self.order.len()));
+		}
+		let current = self.position(layer);
+		self.order.remove(current);
+		self.order.insert(position, layer);
+		Ok(())
+	}
+
+	/// The visible layers, bottom first
+	pub fn drawn(&self) -> impl Iterator<Item = (Layer, LayerStyle)> + '_ {
+		self.order
+			.iter()
+			.map(|&layer| (layer, self.style(layer)))
+			.filter(|(_, style)| style.visible && style.opacity > 0.0)
+	}
+
+	fn style_mut(&mut self, layer: Layer) -> &mut LayerStyle {
+		self.styles.get_mut(&layer).expect("Every layer has a style")
+	}
+}
+
+/// The texture units of every layer's textures, taken once so layers don't
+/// each have to
+#[derive(Clone)]
+pub struct LayerTextures {
+	registry: TextureRegistry,
+	units: Rc<HashMap<(Layer, String), TextureUnit>>,
+}
+
+impl LayerTextures {
+	pub fn new(registry: TextureRegistry) -> Self {
+		let units = Layer::ALL
+			.iter()
+			.flat_map(|&layer| {
+				layer.declaration().textures.iter().map(move |&name| (layer, name.to_owned()))
+			})
+			.map(|texture| (texture, registry.take()))
+			.collect();
+		Self { registry, units: Rc::new(units) }
+	}
+
+	/// What the layers load their textures with
+	pub fn registry(&self) -> &TextureRegistry { &self.registry }
+
+	/// The texture unit of one of `layer`'s declared textures
+	pub fn unit(&self, layer: Layer, texture: &str) -> &TextureUnit {
+		self.units
+			.get(&(layer, texture.to_owned()))
+			.unwrap_or_else(|| panic!("{layer:?} doesn't declare a texture {texture}"))
+	}
+}
+
+/// Logs any uniform a layer declares that the shader doesn't have, which means
+/// its GLSL is missing or doesn't use it
+fn check_declarations(shader_context: &ShaderContext) {
+	for layer in Layer::ALL {
+		for &name in layer.declaration().uniforms {
+			if shader_context.context.get_uniform_location(&shader_context.program, name).is_none()
+			{
+				ghg_error!(
+					"{layer:?} declares uniform {name}, which the planet shader doesn't have"
+				);
+			}
+		}
+	}
+}
+
+/// Tells the planet shader which layers to compose each frame, in what order
+/// and how
+pub async fn compose_layers(
+	gate: FrameGate<AnimationParams>,
+	shader_context: ShaderContext,
+	layers: Rc<RefCell<LayerStack>>,
+) {
+	check_declarations(&shader_context);
+
+	shader_context.use_shader();
+	let mut count_uniform = uniform::new_smart_i32("u_layerCount", &shader_context);
+	let mut layers_uniform = uniform::new_smart_ivec4("u_layers", &shader_context);
+	let mut opacities_uniform = uniform::new_smart_vec4("u_layerOpacities", &shader_context);
+	let mut blend_modes_uniform = uniform::new_smart_ivec4("u_layerBlendModes", &shader_context);
+
+	loop {
+		let _params = (&gate).await;
+
+		let mut ids = nglm::IVec4::zeros();
+		let mut opacities = nglm::Vec4::zeros();
+		let mut blend_modes = nglm::IVec4::zeros();
+		let drawn: Vec<_> = layers.borrow().drawn().take(MAX_LAYERS).collect();
+		for (slot, (layer, style)) in drawn.iter().enumerate() {
+			ids[slot] = layer.shader_id();
+			opacities[slot] = style.opacity;
+			blend_modes[slot] = style.blend_mode.shader_id();
+		}
+
+		shader_context.use_shader();
+		count_uniform.smart_write(drawn.len() as i32);
+		layers_uniform.smart_write(ids);
+		opacities_uniform.smart_write(opacities);
+		blend_modes_uniform.smart_write(blend_modes);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::render_core::shader;
+
+	#[test]
+	fn draws_visible_layers_in_order() {
+		let mut stack = LayerStack::default();
+		let drawn = |stack: &LayerStack| stack.drawn().map(|(layer, _)| layer).collect::<Vec<_>>();
+		assert_eq!(drawn(&stack), [Layer::Countries]);
+
+		stack.set_visible(Layer::Data, true);
+		assert_eq!(drawn(&stack), [Layer::Countries, Layer::Data]);
+
+		stack.set_position(Layer::Data, 0).unwrap();
+		assert_eq!(drawn(&stack), [Layer::Data, Layer::Countries]);
+		assert_eq!(stack.position(Layer::Countries), 1);
+		assert!(stack.set_position(Layer::Data, 2).is_err());
+
+		// Fully transparent layers are skipped
+		stack.set_opacity(Layer::Countries, 0.0).unwrap();
+		assert_eq!(drawn(&stack), [Layer::Data]);
+		assert!(stack.set_opacity(Layer::Countries, 1.5).is_err());
+
+		stack.set_blend_mode(Layer::Data, BlendMode::Multiply);
+		assert_eq!(
+			stack.style(Layer::Data),
+			LayerStyle { visible: true, opacity: 0.4, blend_mode: BlendMode::Multiply }
+		);
+	}
+
+	#[test]
+	fn every_layer_fits_in_the_shader() {
+		assert!(Layer::ALL.len() <= MAX_LAYERS);
+		for (index, layer) in Layer::ALL.into_iter().enumerate() {
+			assert_eq!(layer.shader_id(), index as i32);
+		}
+	}
+
+	#[test]
+	fn defines_every_layer_for_the_shader() {
+		let defines = shader_defines();
+		let define = |name: &str| {
+			defines.iter().find(|(defined, _)| *defined == name).map(|(_, value)| value.as_str())
+		};
+		assert_eq!(define("MAX_LAYERS"), Some("4"));
+		assert_eq!(define("LAYER_COUNTRIES"), Some("0"));
+		assert_eq!(define("LAYER_DATA"), Some("1"));
+		assert_eq!(
+			define("LAYER_COLOR(layer, texturePoint)"),
+			Some(
+				"(layer == LAYER_COUNTRIES ? getCountryColor(texturePoint) : layer == LAYER_DATA \
+				 ? getDataColor(texturePoint) : vec4(0.0))"
+			)
+		);
+	}
+
+	#[test]
+	fn every_layer_has_its_color_function() {
+		let defines = shader_defines();
+		let planet = shader::preprocess_shader(
+			"application/shaders/planet.frag",
+			&defines,
+			&shader::load_shader,
+		)
+		.unwrap();
+		for layer in Layer::ALL {
+			let function = format!("vec4 {}(vec2 ", layer.declaration().color_function);
+			assert!(planet.source.contains(&function), "{layer:?} has no {function}");
+		}
+	}
+}
@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+
+use ghg_data_core::color_map::ColorScale;
+use ghg_data_core::metadata::ChannelMetadata;
+use itertools::Itertools;
+
+use crate::application::events::detail_object;
+use crate::utils::prelude::*;
+
+/// About how many ticks a legend has. Ticks are kept to round values, so there
+/// can be a few more or fewer.
+const TARGET_TICK_COUNT: usize = 5;
+
+/// What the data layer's colors mean, for the page to draw a color bar with
+#[derive(Clone, Debug, PartialEq)]
+pub struct Legend {
+	pub units: &'static str,
+	/// The values at either end of the color bar
+	pub min: f64,
+	pub max: f64,
+	pub ticks: Vec<Tick>,
+	/// The color map as a CSS `linear-gradient`, from `min` on the left to
+	/// `max` on the right
+	pub gradient: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tick {
+	pub value: f64,
+	pub label: String,
+	/// Along the color bar, from 0 at `min` to 1 at `max`
+	pub position: f32,
+}
+
+impl Legend {
+	/// The legend for a time step of `channel`'s range, colored with
+	/// `color_scale`
+	pub fn new(color_scale: &ColorScale, channel: ChannelMetadata, units: &'static str) -> Self {
+		// The colors span the widened range around a midpoint
+		let (min, max) = match color_scale.midpoint {
+			Some(midpoint) => {
+				let half_range = (midpoint - channel.min).max(channel.max - midpoint);
+				(midpoint - half_range, midpoint + half_range)
+			}
+			None => (channel.min, channel.max),
+		};
+
+		let (values, step) = nice_ticks(min, max, TARGET_TICK_COUNT);
+		let ticks = values
+			.into_iter()
+			.map(|value| Tick {
+				value,
+				label: tick_label(value, step),
+				position: color_scale.position(value, channel.min, channel.max),
+			})
+			.collect();
+
+		let stops = color_scale
+			.color_map
+			.stops()
+			.iter()
+			.map(|stop| {
+				let [r, g, b] = stop.color;
+				format!("rgb({r}, {g}, {b}) {}%", stop.position * 100.0)
+			})
+			.join(", ");
+
+		Self { units, min, max, ticks, gradient: format!("linear-gradient(to right, {stops})") }
+	}
+
+	/// The `detail` of a `ViewerEvent::Legend`
+	pub fn detail(&self) -> JsValue {
+		let ticks: js_sys::Array = self
+			.ticks
+			.iter()
+			.map(|tick| {
+				detail_object(&[
+					("value", tick.value.into()),
+					("label", tick.label.as_str().into()),
+					("position", tick.position.into()),
+				])
+			})
+			.collect();
+
+		detail_object(&[
+			("units", self.units.into()),
+			("min", self.min.into()),
+			("max", self.max.into()),
+			("ticks", ticks.into()),
+			("gradient", self.gradient.as_str().into()),
+		])
+	}
+}
+
+/// Round values within `min..=max`, about `target_count` of them, and the step
+/// between them. Steps are 1, 2 or 5 times a power of ten.
+fn nice_ticks(min: f64, max: f64, target_count: usize) -> (Vec<f64>, f64) {
+	if max.partial_cmp(&min) != Some(Ordering::Greater) {
+		return (vec![min], 0.0);
+	}
+
+	let rough_step = (max - min) / target_count as f64;
+	let magnitude = 10f64.powf(rough_step.log10().floor());
+	let step = [1.0, 2.0, 5.0, 10.0]
+		.into_iter()
+		.map(|multiple| multiple * magnitude)
+		.find(|&step| step >= rough_step)
+		.unwrap_or(10.0 * magnitude);
+
+	// Multiplied out, so errors don't build up from tick to tick
+	let (first, last) = ((min / step).ceil() as i64, (max / step).floor() as i64);
+	((first..=last).map(|index| index as f64 * step).collect(), step)
+}
+
+/// `value` with as many decimal places as `step` needs
+fn tick_label(value: f64, step: f64) -> String {
+	let decimals = if step > 0.0 { (-step.log10().floor()).max(0.0) as usize } else { 1 };
+	format!("{value:.decimals$}")
+}
+
+#[cfg(test)]
+mod tests {
+	use ghg_data_core::color_map::ColorMap;
+
+	use super::*;
+
+	#[test]
+	fn ticks_are_round_values() {
+		assert_eq!(nice_ticks(200.0, 327.5, 5), (vec![200.0, 250.0, 300.0], 50.0));
+		assert_eq!(nice_ticks(-0.35, 0.5, 5).1, 0.2);
+		assert_eq!(nice_ticks(3.0, 3.0, 5), (vec![3.0], 0.0));
+
+		let labels: Vec<_> =
+			nice_ticks(-0.35, 0.5, 5).0.into_iter().map(|value| tick_label(value, 0.2)).collect();
+		assert_eq!(labels, ["-0.2", "0.0", "0.2", "0.4"]);
+	}
+
+	#[test]
+	fn legends_cover_the_colored_range() {
+		let channel = ChannelMetadata { min: -2.0, max: 6.0 };
+
+		let linear = Legend::new(&ColorScale::default(), channel, "K");
+		assert_eq!((linear.min, linear.max), (-2.0, 6.0));
+		assert_eq!(linear.ticks[0], Tick { value: -2.0, label: "-2".to_owned(), position: 0.0 });
+		assert!(linear.gradient.starts_with("linear-gradient(to right, rgb(68, 1, 84) 0%,"));
+
+		// Widened to be even around the midpoint, which is in the middle of the bar
+		let anomaly = ColorScale { color_map: ColorMap::RdBu, midpoint: Some(0.0) };
+		let legend = Legend::new(&anomaly, channel, "K");
+		assert_eq!((legend.min, legend.max), (-6.0, 6.0));
+		let zero = legend.ticks.iter().find(|tick| tick.value == 0.0).unwrap();
+		assert_eq!(zero.position, 0.5);
+	}
+}
@@ -1,32 +1,26 @@
-use crate::application::shaders::ShaderContext;
-use crate::render_core::uniform;
-use crate::render_core::uniform::SmartUniform;
+use crate::render_core::uniform::uniform_block;
 
-pub struct LightParameters {
-	pub ambient_strength: SmartUniform<f32>,
-	pub specular_strength: SmartUniform<f32>,
-	pub ambient_color: SmartUniform<nglm::Vec3>,
-	pub light_color: SmartUniform<nglm::Vec3>,
-	pub light_position: SmartUniform<nglm::Vec3>,
-	pub camera_position: SmartUniform<nglm::Vec3>,
+uniform_block! {
+	#[block("Lighting", binding = 1)]
+	/// The scene's light, as `lighting.glsl` declares it
+	pub struct LightingBlock {
+		pub ambient_color: nglm::Vec3,
+		pub ambient_strength: f32,
+		pub light_color: nglm::Vec3,
+		pub specular_strength: f32,
+		pub light_position: nglm::Vec3,
+	}
 }
 
-impl LightParameters {
-	pub fn new(shader_context: &ShaderContext) -> Self {
-		let ambient_strength = uniform::init_smart_f32("u_ambientStrength", &shader_context, 0.3);
-		let specular_strength = uniform::init_smart_f32("u_specularStrength", &shader_context, 0.5);
-		let ambient_color =
-			uniform::init_smart_vec3("u_ambientColor", &shader_context, nglm::vec3(0.8, 0.8, 1.0));
-		let light_color =
-			uniform::init_smart_vec3("u_lightColor", &shader_context, nglm::vec3(1.0, 1.0, 1.0));
-
+impl LightingBlock {
+	/// The scene's usual light, shining from `light_position`
+	pub fn from_position(light_position: nglm::Vec3) -> Self {
 		Self {
-			ambient_strength,
-			specular_strength,
-			ambient_color,
-			light_color,
-			light_position: uniform::new_smart_vec3("u_lightPosition", &shader_context),
-			camera_position: uniform::new_smart_vec3("u_cameraPosition", &shader_context),
+			ambient_color: nglm::vec3(0.8, 0.8, 1.0),
+			ambient_strength: 0.3,
+			light_color: nglm::vec3(1.0, 1.0, 1.0),
+			specular_strength: 0.5,
+			light_position,
 		}
 	}
 }
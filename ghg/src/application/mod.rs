@@ -4,9 +4,16 @@ pub mod country;
 pub mod data;
 mod debug_axes;
 mod debug_projection;
+pub mod events;
 mod image_utility;
+pub mod layers;
+pub mod legend;
 pub mod lighting;
 pub mod planet;
+pub mod probe;
+mod scene_uniforms;
 pub mod shaders;
 pub mod sphere;
+pub mod tile_streaming;
+pub mod timeline;
 pub mod vertex;
@@ -7,10 +7,9 @@ use std::time::Duration;
 use image::{Luma, Rgb};
 use single_thread_executor::Spawner;
 use wasm_bindgen::JsValue;
-use web_sys::WebGl2RenderingContext;
 
+use crate::application::events::{detail_object, ViewerEvent, ViewerEvents};
 use crate::application::image_utility::biggest_mipmap_level;
-use crate::application::lighting::LightParameters;
 use crate::application::shaders::ShaderContext;
 use crate::application::sphere::generate_sphere;
 use crate::application::vertex::BasicMesh;
@@ -21,7 +20,8 @@ use crate::render_core::image::load_into_texture;
 use crate::render_core::mesh::{
 	add_mesh, clear_frame, draw_meshes, DrawBuffers, DrawMode, MeshMode,
 };
-use crate::render_core::texture_provider::TextureProvider;
+use crate::render_core::texture_provider::TextureUnit;
+use crate::render_core::texture_registry::TextureRegistry;
 use crate::render_core::uniform;
 use crate::request_data::fetch_bytes;
 #[allow(unused_imports)]
@@ -29,61 +29,65 @@ use crate::utils::prelude::*;
 
 const IMAGE_MAX_SIZE: usize = 21_600;
 
+pub const PLANET_SUBDIVISIONS: u32 = 10;
+pub const PLANET_POINTS_PER_SUBDIVISION: u32 = 10;
+
+/// The units the planet's own textures are loaded into. The planet is drawn
+/// with them for as long as the viewer is, so `draw` holds onto them.
+#[derive(Clone)]
+pub struct PlanetTextures {
+	pub color: Rc<TextureUnit>,
+	pub terrain: Rc<TextureUnit>,
+}
+
+impl PlanetTextures {
+	pub fn new(textures: &TextureRegistry) -> Self {
+		Self { color: Rc::new(textures.take()), terrain: Rc::new(textures.take()) }
+	}
+}
+
 async fn load_planet_terrain(
-	context: WebGl2RenderingContext,
-	texture_index: u32,
+	textures: &TextureRegistry,
+	unit: &TextureUnit,
 	mipmap_level: usize,
 ) -> Result<(), JsValue> {
 	let texture =
 		fetch_bytes(format!("images/earth_height/{mipmap_level}/full.png").as_str()).await?;
-	load_into_texture::<Luma<u8>>(
-		context,
-		&texture,
-		WebGl2RenderingContext::TEXTURE0 + texture_index,
-	)
+	load_into_texture::<Luma<u8>>(textures, &texture, unit)
 }
 
 async fn load_planet_color(
-	context: WebGl2RenderingContext,
-	texture_index: u32,
+	textures: &TextureRegistry,
+	unit: &TextureUnit,
 	mipmap_level: usize,
 ) -> Result<(), JsValue> {
 	let texture =
 		fetch_bytes(format!("images/earth_color/{mipmap_level}/full.png").as_str()).await?;
-	load_into_texture::<Rgb<u8>>(
-		context,
-		&texture,
-		WebGl2RenderingContext::TEXTURE0 + texture_index,
-	)?;
+	load_into_texture::<Rgb<u8>>(textures, &texture, unit)?;
 	Ok(())
 }
 
 async fn load_all_textures(
-	context: WebGl2RenderingContext,
+	events: ViewerEvents,
+	textures: TextureRegistry,
 	done: Rc<Cell<bool>>,
-	terrain_index: u32,
-	color_index: u32,
+	terrain_unit: Rc<TextureUnit>,
+	color_unit: Rc<TextureUnit>,
 ) {
-	let texture_mipmap_level = biggest_mipmap_level(context.clone(), IMAGE_MAX_SIZE)
+	let texture_mipmap_level = biggest_mipmap_level(textures.context().clone(), IMAGE_MAX_SIZE)
 		.expect("Failed to get max texture size");
 
 	let (color_result, terrain_result) = join!(
-		load_planet_color(context.clone(), color_index, texture_mipmap_level),
-		load_planet_terrain(context.clone(), terrain_index, texture_mipmap_level)
+		load_planet_color(&textures, &color_unit, texture_mipmap_level),
+		load_planet_terrain(&textures, &terrain_unit, texture_mipmap_level)
 	)
 	.await;
 
 	assert!(color_result.is_ok(), "Color load failed");
 	assert!(terrain_result.is_ok(), "Terrain load failed");
 
-	remove_overlay();
 	done.replace(true);
-}
-
-// TODO: Move to module
-#[wasm_bindgen(module = "/www/overlay.js")]
-extern "C" {
-	fn remove_overlay();
+	events.send(ViewerEvent::Loaded, &detail_object(&[("part", "planet".into())]));
 }
 
 fn generate_drawable_sphere(
@@ -102,25 +106,25 @@ fn generate_drawable_sphere(
 
 pub async fn load_textures(
 	gate: FrameGate<AnimationParams>,
+	events: ViewerEvents,
 	spawner: Spawner,
 	shader: ShaderContext,
 	camera: Rc<RefCell<Camera>>,
-	mut texture_provider: TextureProvider,
+	textures: TextureRegistry,
+	units: PlanetTextures,
 ) {
 	let textures_loaded = Rc::new(Cell::new(false));
 
-	let color_index = texture_provider.take();
-	let terrain_index = texture_provider.take();
-
 	shader.use_shader();
-	uniform::init_i32("s_textureMap", &shader, terrain_index as i32);
-	uniform::init_i32("s_colorMap", &shader, color_index as i32);
+	uniform::init_i32("s_textureMap", &shader, units.terrain.index() as i32);
+	uniform::init_i32("s_colorMap", &shader, units.color.index() as i32);
 
 	spawner.spawn(load_all_textures(
-		shader.context,
+		events,
+		textures,
 		textures_loaded.clone(),
-		terrain_index,
-		color_index,
+		units.terrain,
+		units.color,
 	));
 
 	let mut initial_spin = 3.0f32;
@@ -155,6 +159,7 @@ pub async fn draw(
 	gate: FrameGate<AnimationParams>,
 	shader: ShaderContext,
 	camera: Rc<RefCell<Camera>>,
+	_units: PlanetTextures,
 ) {
 	let mut frustum_test_camera =
 		Camera::new(&nglm::vec3(1.1, 0.0, 0.0), &nglm::vec3(0.0, 0.0, 0.0));
@@ -162,14 +167,11 @@ pub async fn draw(
 
 	shader.use_shader();
 
-	let planet_meshes_and_buffers = generate_drawable_sphere(10, 10, shader.clone());
-
-	let mut lighting = LightParameters::new(&shader);
-
-	// TODO: Need to make a uniform that can bind into multiple shader programs
-	let mut planet_model = uniform::new_smart_mat4("u_model", &shader);
-	let mut planet_view = uniform::new_smart_mat4("u_view", &shader);
-	let mut planet_projection = uniform::new_smart_mat4("u_projection", &shader);
+	let planet_meshes_and_buffers = generate_drawable_sphere(
+		PLANET_SUBDIVISIONS,
+		PLANET_POINTS_PER_SUBDIVISION,
+		shader.clone(),
+	);
 
 	loop {
 		let params = (&gate).await;
@@ -186,20 +188,8 @@ pub async fn draw(
 
 		clear_frame(params.viewport.context());
 
-		let camera_position = camera.deref().borrow().position();
-		lighting.camera_position.smart_write(camera_position.clone());
-		lighting.light_position.smart_write(camera_position.clone()); // - nglm::vec3(0.0, 0.5, 0.3)
-
-		let width = params.viewport.width() as i32;
-		let height = params.viewport.height() as i32;
-
-		let mvp = camera.deref().borrow().get_perspective_matrices(width, height);
-
+		// The camera and lighting come from the scene's uniform blocks
 		if DEBUG_FRUSTUM {
-			planet_model.smart_write(mvp.model.clone());
-			planet_view.smart_write(mvp.view.clone());
-			planet_projection.smart_write(mvp.projection.clone());
-
 			draw_meshes(
 				params.viewport.context(),
 				&frustum_test_camera,
@@ -207,10 +197,6 @@ pub async fn draw(
 				DrawMode::Surface,
 			);
 		} else {
-			planet_model.smart_write(mvp.model.clone());
-			planet_view.smart_write(mvp.view.clone());
-			planet_projection.smart_write(mvp.projection.clone());
-
 			draw_meshes(
 				params.viewport.context(),
 				camera.deref().borrow().deref(),
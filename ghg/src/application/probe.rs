@@ -0,0 +1,313 @@
+use std::cell::{Cell, RefCell};
+use std::ops::Deref;
+use std::rc::Rc;
+
+use ghg_data_core::countries::CountryIndex;
+use ghg_data_core::metadata::Metadata;
+
+use crate::application::data::{month_location, MONTH_NAMES};
+use crate::application::events::{detail_object, ViewerEvent, ViewerEvents};
+use crate::application::tile_streaming::point_to_uv;
+use crate::application::timeline::Timeline;
+use crate::interaction_core::user_inputs::LogicalCursorPosition;
+use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::camera::{Camera, MvpMatrices};
+use crate::render_core::canvas::window;
+use crate::render_core::frame_sequencer::FrameGate;
+use crate::render_core::image::CpuImage;
+#[allow(unused_imports)]
+use crate::utils::prelude::*;
+
+/// A data texture, kept on the CPU as well
+pub struct DataImage {
+	/// The image's file stem, `{year}.{first month}.{last month}`
+	pub name: String,
+	pub image: CpuImage,
+	pub metadata: Metadata,
+	pub units: &'static str,
+}
+
+/// The country map's identities, kept on the CPU as well
+pub struct CountryLookup {
+	/// Just the identity channel
+	pub identities: CpuImage,
+	pub index: CountryIndex,
+}
+
+impl CountryLookup {
+	/// The stored identity byte at `uv`, 0 for the ocean
+	pub fn identity_byte(&self, uv: nglm::Vec2) -> u8 { self.identities.texel(uv)[0] }
+}
+
+/// CPU-side copies of what's drawn on the globe, filled in as they load
+#[derive(Default)]
+pub struct ProbeSources {
+	/// One per data texture, in the order the timeline steps through their
+	/// channels
+	pub data: Vec<DataImage>,
+	pub countries: Option<CountryLookup>,
+}
+
+/// What's at a point on the globe, ready to show
+#[derive(Clone, Debug, PartialEq)]
+pub struct Probe {
+	pub coordinates: String,
+	pub value: String,
+	pub date: String,
+	pub country: String,
+}
+
+impl Probe {
+	/// The `detail` of a `ViewerEvent::Probe`, at `cursor_location`
+	fn detail(&self, cursor_location: LogicalCursorPosition) -> JsValue {
+		let dpr = window().device_pixel_ratio();
+		detail_object(&[
+			("x", (cursor_location.x as f64 / dpr).into()),
+			("y", (cursor_location.y as f64 / dpr).into()),
+			("coordinates", self.coordinates.as_str().into()),
+			("value", self.value.as_str().into()),
+			("date", self.date.as_str().into()),
+			("country", self.country.as_str().into()),
+		])
+	}
+}
+
+impl ProbeSources {
+	/// Everything known about `point` on the unit sphere, in the data of
+	/// `month`. Anything that hasn't loaded is left empty.
+	pub fn probe(&self, point: &nglm::Vec3, month: usize) -> Probe {
+		let uv = point_to_uv(point);
+
+		let (value, date) = match self.data_channel(month) {
+			Some((data, channel)) => {
+				let byte = data.image.texel(uv)[channel];
+				let value = data.metadata.0[channel].decode(byte);
+				let year = data.name.split('.').next().unwrap_or_default();
+				(format!("{value:.1} {}", data.units), format!("{} {year}", MONTH_NAMES[month]))
+			}
+			None => (String::new(), String::new()),
+		};
+
+		let country = self
+			.countries
+			.as_ref()
+			.and_then(|countries| countries.index.name_of_byte(countries.identity_byte(uv)))
+			.unwrap_or_default()
+			.to_owned();
+
+		Probe { coordinates: coordinates_label(uv), value, date, country }
+	}
+
+	/// The data image holding `month`, and its channel for it
+	fn data_channel(&self, month: usize) -> Option<(&DataImage, usize)> {
+		let (map, channel) = month_location(month);
+		let data = self.data.get(map)?;
+		(channel < data.metadata.0.len()).then_some((data, channel))
+	}
+}
+
+/// Latitude and longitude in degrees, for a point in texture space. Textures
+/// are equirectangular with north at v = 0.
+pub fn uv_to_latitude_longitude(uv: nglm::Vec2) -> (f32, f32) {
+	(90.0 - 180.0 * uv.y, 360.0 * uv.x - 180.0)
+}
+
+/// The point on the unit sphere at a latitude and longitude in degrees, the
+/// inverse of `point_to_uv` and `uv_to_latitude_longitude`
+pub fn latitude_longitude_to_point(latitude: f32, longitude: f32) -> nglm::Vec3 {
+	let (latitude, longitude) = (latitude.to_radians(), longitude.to_radians());
+	// North is -y, since the projection flips y
+	nglm::vec3(latitude.cos() * longitude.sin(), -latitude.sin(), latitude.cos() * longitude.cos())
+}
+
+fn coordinates_label(uv: nglm::Vec2) -> String {
+	let (latitude, longitude) = uv_to_latitude_longitude(uv);
+	let north_south = if latitude < 0.0 { 'S' } else { 'N' };
+	let east_west = if longitude < 0.0 { 'W' } else { 'E' };
+	format!("{:.1}°{north_south}, {:.1}°{east_west}", latitude.abs(), longitude.abs())
+}
+
+/// The direction, in world space, from the camera through `cursor_location`
+pub fn cursor_ray(
+	cursor_location: LogicalCursorPosition,
+	mvp: &MvpMatrices,
+	screen_width: f32,
+	screen_height: f32,
+) -> nglm::Vec3 {
+	let normalized_device_coords = nglm::vec2(
+		(2.0 * cursor_location.x as f32) / screen_width - 1.0,
+		1.0 - (2.0 * cursor_location.y as f32) / screen_height,
+	);
+	let homogeneous_clip_coords =
+		nglm::vec4(normalized_device_coords.x, normalized_device_coords.y, -1.0, 1.0);
+
+	let mut ray_eye = mvp.projection.try_inverse().expect("Failed to invert projection matrix")
+		* homogeneous_clip_coords;
+	ray_eye = nglm::vec4(ray_eye.x, ray_eye.y, -1.0, 0.0);
+
+	(mvp.view.try_inverse().expect("Failed to invert the view matrix") * ray_eye).xyz().normalize()
+}
+
+/// Where `ray` from the camera first meets the sphere, if it does
+pub fn sphere_intersection(
+	camera: &Camera,
+	ray: nglm::Vec3,
+	sphere_center: nglm::Vec3,
+	sphere_radius: f32,
+) -> Option<nglm::Vec3> {
+	let ray = ray.normalize();
+	let camera_pos = camera.position();
+
+	let difference = camera_pos - sphere_center;
+	let a = ray.dot(&ray);
+	let b = ray.dot(&difference);
+	let c = difference.dot(&difference) - sphere_radius * sphere_radius;
+	let delta = b * b - a * c;
+
+	if delta < 0.0 {
+		return None;
+	}
+
+	let sqrt_delta = delta.sqrt();
+	let t_min = (-b - sqrt_delta) / a;
+	let t_max = (-b + sqrt_delta) / a;
+
+	if t_max < 0.0 {
+		return None;
+	}
+
+	let t = if t_min >= 0.0 { t_min } else { t_max };
+	Some(camera_pos + (t * ray))
+}
+
+/// The point on the globe under the cursor, if it's over the globe
+pub fn cursor_on_globe(
+	cursor_location: LogicalCursorPosition,
+	camera: &Camera,
+	width: i32,
+	height: i32,
+) -> Option<nglm::Vec3> {
+	let mvp = camera.get_perspective_matrices(width, height);
+	let ray = cursor_ray(cursor_location, &mvp, width as f32, height as f32);
+	sphere_intersection(camera, ray, nglm::Vec3::zeros(), 1.0)
+}
+
+/// Sends a `ViewerEvent::Probe` whenever what's under the cursor changes
+pub async fn probe_cursor(
+	gate: FrameGate<AnimationParams>,
+	events: ViewerEvents,
+	camera: Rc<RefCell<Camera>>,
+	current_cursor_location: Rc<Cell<Option<LogicalCursorPosition>>>,
+	timeline: Rc<RefCell<Timeline>>,
+	sources: Rc<RefCell<ProbeSources>>,
+) {
+	let mut shown: Option<(LogicalCursorPosition, Probe)> = None;
+
+	loop {
+		let params = (&gate).await;
+
+		let width = params.viewport.width() as i32;
+		let height = params.viewport.height() as i32;
+		let probe = current_cursor_location.get().and_then(|cursor_location| {
+			let point =
+				cursor_on_globe(cursor_location, camera.deref().borrow().deref(), width, height)?;
+			Some((
+				cursor_location,
+				sources.borrow().probe(&point, timeline.borrow().current_step()),
+			))
+		});
+
+		if probe != shown {
+			let detail = match &probe {
+				Some((cursor_location, probe)) => probe.detail(*cursor_location),
+				None => JsValue::NULL,
+			};
+			events.send(ViewerEvent::Probe, &detail);
+			shown = probe;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use ghg_data_core::metadata::ChannelMetadata;
+
+	use super::*;
+
+	#[test]
+	fn center_of_screen_looks_at_target() {
+		let camera = Camera::new(&nglm::vec3(0.0, 0.0, 3.0), &nglm::Vec3::zeros());
+
+		let point = cursor_on_globe(nglm::I32Vec2::new(400, 300), &camera, 800, 600).unwrap();
+		assert!((point - nglm::vec3(0.0, 0.0, 1.0)).magnitude() < 1e-4, "{point:?}");
+
+		assert_eq!(cursor_on_globe(nglm::I32Vec2::new(0, 0), &camera, 800, 600), None);
+	}
+
+	#[test]
+	fn latitude_and_longitude_from_uv() {
+		assert_eq!(uv_to_latitude_longitude(nglm::vec2(0.5, 0.5)), (0.0, 0.0));
+		assert_eq!(uv_to_latitude_longitude(nglm::vec2(0.0, 0.0)), (90.0, -180.0));
+		assert_eq!(coordinates_label(nglm::vec2(0.75, 0.75)), "45.0°S, 90.0°E");
+	}
+
+	#[test]
+	fn latitude_and_longitude_to_point() {
+		for (latitude, longitude) in [(0.0, 0.0), (45.0, 90.0), (-30.0, -120.0), (60.0, 179.0)] {
+			let point = latitude_longitude_to_point(latitude, longitude);
+			let (round_latitude, round_longitude) = uv_to_latitude_longitude(point_to_uv(&point));
+			assert!((round_latitude - latitude).abs() < 1e-3, "{latitude} != {round_latitude}");
+			assert!((round_longitude - longitude).abs() < 1e-3, "{longitude} != {round_longitude}");
+		}
+	}
+
+	#[test]
+	fn probes_data_and_countries() {
+		// Four months per image, in a 2x1 image whose east half is Eastland
+		let metadata = Metadata(vec![
+			ChannelMetadata { min: 200.0, max: 300.0 },
+			ChannelMetadata { min: 0.0, max: 1.0 },
+			ChannelMetadata { min: 0.0, max: 1.0 },
+			ChannelMetadata { min: 0.0, max: 2.55 },
+		]);
+		let data = |name: &str| DataImage {
+			name: name.to_owned(),
+			image: CpuImage::new(vec![0, 0, 0, 0, 255, 0, 0, 100], (2, 1)),
+			metadata: metadata.clone(),
+			units: "K",
+		};
+		let index =
+			CountryIndex { max_identity: 1, names: BTreeMap::from([(1, "Eastland".into())]) };
+		let sources = ProbeSources {
+			data: vec![data("2021.01.04"), data("2021.05.08")],
+			countries: Some(CountryLookup {
+				identities: CpuImage::new(vec![0, 255], (2, 1)),
+				index,
+			}),
+		};
+
+		// u = 0.75, east of the prime meridian
+		let east = nglm::vec3(1.0, 0.0, 0.0);
+		assert_eq!(
+			sources.probe(&east, 3),
+			Probe {
+				coordinates: "0.0°N, 90.0°E".to_owned(),
+				value: "1.0 K".to_owned(),
+				date: "April 2021".to_owned(),
+				country: "Eastland".to_owned(),
+			}
+		);
+
+		// May is the first month of the second image
+		let may = sources.probe(&east, 4);
+		assert_eq!((may.value.as_str(), may.date.as_str()), ("300.0 K", "May 2021"));
+
+		let west = sources.probe(&nglm::vec3(-1.0, 0.0, 0.0), 0);
+		assert_eq!((west.value.as_str(), west.country.as_str()), ("200.0 K", ""));
+
+		// Months past the loaded images are left empty
+		assert_eq!(sources.probe(&east, 8).value, "");
+	}
+}
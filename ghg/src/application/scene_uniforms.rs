@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use web_sys::WebGl2RenderingContext;
+
+use crate::application::lighting::LightingBlock;
+use crate::application::shaders::ShaderContext;
+use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::camera::Camera;
+use crate::render_core::frame_sequencer::FrameGate;
+use crate::render_core::uniform::{uniform_block, UniformBuffer};
+
+uniform_block! {
+	#[block("Camera", binding = 0)]
+	/// The camera, as `camera.glsl` declares it
+	pub struct CameraBlock {
+		pub model: nglm::Mat4,
+		pub view: nglm::Mat4,
+		pub projection: nglm::Mat4,
+		pub position: nglm::Vec3,
+	}
+}
+
+/// The uniform blocks every program shares, so they're written once per frame
+/// rather than once per program
+pub struct SceneUniforms {
+	camera: UniformBuffer<CameraBlock>,
+	lighting: UniformBuffer<LightingBlock>,
+}
+
+impl SceneUniforms {
+	pub fn new(context: &WebGl2RenderingContext) -> Result<Self, String> {
+		Ok(Self { camera: UniformBuffer::new(context)?, lighting: UniformBuffer::new(context)? })
+	}
+
+	/// Has `shader_context`'s program read the camera, and the lighting too if
+	/// it's `lit`
+	pub fn bind_to(&self, shader_context: &ShaderContext, lit: bool) -> Result<(), String> {
+		self.camera.bind_to(shader_context)?;
+		if lit {
+			self.lighting.bind_to(shader_context)?;
+		}
+		Ok(())
+	}
+}
+
+/// Writes the camera and lighting for the frame's draws
+pub async fn write_scene_uniforms(
+	gate: FrameGate<AnimationParams>,
+	mut scene_uniforms: SceneUniforms,
+	camera: Rc<RefCell<Camera>>,
+) {
+	loop {
+		let params = (&gate).await;
+
+		let width = params.viewport.width() as i32;
+		let height = params.viewport.height() as i32;
+		let camera = camera.borrow();
+		let mvp = camera.get_perspective_matrices(width, height);
+
+		scene_uniforms.camera.bind();
+		scene_uniforms.camera.smart_write(CameraBlock {
+			model: mvp.model,
+			view: mvp.view,
+			projection: mvp.projection,
+			position: camera.position(),
+		});
+
+		// Lit from the camera, so the side facing it is never dark
+		scene_uniforms.lighting.bind();
+		scene_uniforms.lighting.smart_write(LightingBlock::from_position(camera.position()));
+	}
+}
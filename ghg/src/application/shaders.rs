@@ -1,67 +1,218 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cell::{Cell, RefCell, RefMut};
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
 
-use lazy_static::lazy_static;
 use web_sys::{WebGl2RenderingContext, WebGlProgram};
 
+#[cfg(debug_assertions)]
+use crate::application::events::{detail_object, ViewerEvent, ViewerEvents};
+use crate::application::{data, layers};
+#[cfg(debug_assertions)]
+use crate::render_core::animation_params::AnimationParams;
+#[cfg(debug_assertions)]
+use crate::render_core::frame_sequencer::FrameGate;
 use crate::render_core::shader;
+#[cfg(debug_assertions)]
+use crate::render_core::shader::PreprocessedShader;
+use crate::render_core::uniform::UniformValueTracker;
+#[cfg(debug_assertions)]
+use crate::utils::prelude::*;
 
-lazy_static! {
-	static ref PROGRAM_ID_COUNTER: AtomicUsize = Default::default();
+/// Where a program's shaders are, relative to this crate's `src` directory
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramSources {
+	pub name: &'static str,
+	pub vertex: &'static str,
+	pub fragment: &'static str,
+	/// Constants from Rust, defined in both shaders
+	pub defines: fn() -> Vec<(&'static str, String)>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+const PLANET_SOURCES: ProgramSources = ProgramSources {
+	name: "planet",
+	vertex: "application/shaders/planet.vert",
+	fragment: "application/shaders/planet.frag",
+	defines: || {
+		let mut defines = vec![
+			("NUM_MAPS_PER_YEAR", data::MAPS_PER_YEAR.to_string()),
+			("NUM_CHANNELS_IN_MAP", data::CHANNELS_PER_MAP.to_string()),
+		];
+		defines.extend(layers::shader_defines());
+		defines
+	},
+};
+
+const DIRECT_MESH_SOURCES: ProgramSources = ProgramSources {
+	name: "direct mesh",
+	vertex: "application/shaders/direct_mesh.vert",
+	fragment: "application/shaders/passthrough.frag",
+	defines: Vec::new,
+};
+
+#[derive(Clone)]
 pub struct ShaderContext {
 	pub context: WebGl2RenderingContext,
 	pub program: WebGlProgram,
-	program_id: usize,
+	sources: ProgramSources,
+	uniform_values: Rc<RefCell<UniformValueTracker>>,
+	/// Counts how many times the program has been relinked, since each time
+	/// invalidates its uniform locations
+	generation: Rc<Cell<u32>>,
+	/// Each uniform block's name, and the binding point it reads from
+	uniform_blocks: Rc<RefCell<Vec<(&'static str, u32)>>>,
 }
 
 impl ShaderContext {
-	fn new(context: &WebGl2RenderingContext, program: &WebGlProgram) -> Self {
-		Self {
+	fn compile(context: &WebGl2RenderingContext, sources: ProgramSources) -> Result<Self, String> {
+		let defines = (sources.defines)();
+		let vert_shader = shader::preprocess_and_compile_shader(
+			context,
+			WebGl2RenderingContext::VERTEX_SHADER,
+			sources.vertex,
+			&defines,
+		)?;
+
+		let frag_shader = shader::preprocess_and_compile_shader(
+			context,
+			WebGl2RenderingContext::FRAGMENT_SHADER,
+			sources.fragment,
+			&defines,
+		)?;
+
+		let program = shader::link_program(context, &vert_shader, &frag_shader)?;
+		Ok(Self {
 			context: context.clone(),
-			program: program.clone(),
-			program_id: PROGRAM_ID_COUNTER.fetch_add(1, Ordering::Relaxed),
-		}
+			program,
+			sources,
+			uniform_values: Default::default(),
+			generation: Default::default(),
+			uniform_blocks: Default::default(),
+		})
 	}
 
 	pub fn use_shader(&self) { self.context.use_program(Some(&self.program)); }
 
-	pub fn id(&self) -> usize { self.program_id }
+	/// What was last written to this program's uniforms
+	pub fn uniform_values(&self) -> RefMut<'_, UniformValueTracker> {
+		self.uniform_values.borrow_mut()
+	}
+
+	/// How many times the program has been relinked
+	pub fn generation(&self) -> u32 { self.generation.get() }
+
+	/// Has the program read the uniform block `name` from `binding`
+	pub fn bind_uniform_block(&self, name: &'static str, binding: u32) -> Result<(), String> {
+		self.apply_uniform_block(name, binding)?;
+		self.uniform_blocks.borrow_mut().push((name, binding));
+		Ok(())
+	}
+
+	fn apply_uniform_block(&self, name: &str, binding: u32) -> Result<(), String> {
+		let index = self.context.get_uniform_block_index(&self.program, name);
+		if index == WebGl2RenderingContext::INVALID_INDEX {
+			return Err(format!("The {} shader has no uniform block {name}", self.sources.name));
+		}
+		self.context.uniform_block_binding(&self.program, index, binding);
+		Ok(())
+	}
+
+	/// Relinks the program from new shaders, then restores its uniforms. If
+	/// they don't compile or link, the program is left as it was.
+	#[cfg(debug_assertions)]
+	pub fn relink(
+		&self,
+		vertex: &PreprocessedShader,
+		fragment: &PreprocessedShader,
+	) -> Result<(), String> {
+		let vert_shader =
+			shader::compile_shader(&self.context, WebGl2RenderingContext::VERTEX_SHADER, vertex)?;
+		let frag_shader = shader::compile_shader(
+			&self.context,
+			WebGl2RenderingContext::FRAGMENT_SHADER,
+			fragment,
+		)
+		.inspect_err(|_| self.context.delete_shader(Some(&vert_shader)))?;
+		shader::relink_program(&self.context, &self.program, vert_shader, frag_shader)?;
+
+		self.generation.set(self.generation.get() + 1);
+		self.use_shader();
+		for &(name, binding) in self.uniform_blocks.borrow().iter() {
+			self.apply_uniform_block(name, binding)?;
+		}
+		self.uniform_values().rewrite_all(self);
+		Ok(())
+	}
+
+	/// Fetches the program's shaders from under `base_url` and relinks it from
+	/// them, so shaders can be changed without rebuilding
+	#[cfg(debug_assertions)]
+	pub async fn reload(&self, base_url: &str) -> Result<(), String> {
+		let paths = [self.sources.vertex, self.sources.fragment];
+		let sources = shader::fetch_shader_sources(base_url, &paths).await?;
+		let load = |path: &str| {
+			sources.get(path).map(String::as_str).ok_or_else(|| format!("{path} wasn't fetched"))
+		};
+		let defines = (self.sources.defines)();
+		let vertex = shader::preprocess_shader(self.sources.vertex, &defines, &load)?;
+		let fragment = shader::preprocess_shader(self.sources.fragment, &defines, &load)?;
+		self.relink(&vertex, &fragment)
+	}
+}
+
+impl Debug for ShaderContext {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ShaderContext").field("program", &self.program).finish_non_exhaustive()
+	}
+}
+
+impl PartialEq for ShaderContext {
+	fn eq(&self, other: &Self) -> bool {
+		self.context == other.context && self.program == other.program
+	}
 }
 
+impl Eq for ShaderContext {}
+
 pub fn get_planet_shaders(context: &WebGl2RenderingContext) -> Result<ShaderContext, String> {
-	let vert_shader = shader::preprocess_and_compile_shader(
-		&context,
-		WebGl2RenderingContext::VERTEX_SHADER,
-		include_str!("shaders/planet.vert"),
-	)?;
-
-	let frag_shader = shader::preprocess_and_compile_shader(
-		&context,
-		WebGl2RenderingContext::FRAGMENT_SHADER,
-		include_str!("shaders/planet.frag"),
-	)?;
-
-	let program = shader::link_program(&context, &vert_shader, &frag_shader)?;
-	Ok(ShaderContext::new(&context, &program))
+	ShaderContext::compile(context, PLANET_SOURCES)
 }
 
 pub fn get_direct_mesh_render_shaders(
 	context: &WebGl2RenderingContext,
 ) -> Result<ShaderContext, String> {
-	let vert_shader = shader::preprocess_and_compile_shader(
-		&context,
-		WebGl2RenderingContext::VERTEX_SHADER,
-		include_str!("shaders/direct_mesh.vert"),
-	)?;
-
-	let frag_shader = shader::preprocess_and_compile_shader(
-		&context,
-		WebGl2RenderingContext::FRAGMENT_SHADER,
-		include_str!("shaders/passthrough.frag"),
-	)?;
-
-	let program = shader::link_program(&context, &vert_shader, &frag_shader)?;
-	Ok(ShaderContext::new(&context, &program))
+	ShaderContext::compile(context, DIRECT_MESH_SOURCES)
+}
+
+/// Reloads every program's shaders from under the URL the page asks for, and
+/// tells it which failed to compile or link. Those keep their last good
+/// program.
+#[cfg(debug_assertions)]
+pub async fn reload_shaders(
+	gate: FrameGate<AnimationParams>,
+	events: ViewerEvents,
+	shaders: Vec<ShaderContext>,
+	reload_request: Rc<RefCell<Option<String>>>,
+) {
+	loop {
+		let base_url = reload_request.borrow_mut().take();
+		if let Some(base_url) = base_url {
+			let mut errors = Vec::new();
+			for shader in &shaders {
+				if let Err(e) = shader.reload(&base_url).await {
+					ghg_error!("Failed to reload the {} shader: {e}", shader.sources.name);
+					errors.push(format!("{} shader:\n{e}", shader.sources.name));
+				}
+			}
+
+			let detail = if errors.is_empty() {
+				ghg_log!("Reloaded shaders from {base_url}");
+				JsValue::NULL
+			} else {
+				detail_object(&[("log", errors.join("\n").into())])
+			};
+			events.send(ViewerEvent::ShaderError, &detail);
+		}
+
+		let _params = (&gate).await;
+	}
 }
@@ -0,0 +1,8 @@
+// The camera, shared by every program through a uniform buffer. Matches `CameraBlock`.
+
+layout(std140) uniform Camera {
+    highp mat4 u_model;
+    highp mat4 u_view;
+    highp mat4 u_projection;
+    highp vec3 u_cameraPosition;
+};
@@ -0,0 +1,23 @@
+// Where value falls along a color map, as ColorScale::position does. With a midpoint, the range is widened to be even
+// either side of it.
+float colorMapPosition(float value, float minValue, float maxValue, bool hasMidpoint, float midpoint) {
+    if (hasMidpoint) {
+        float halfRange = max(midpoint - minValue, maxValue - midpoint);
+        if (halfRange <= 0.0) {
+            return 0.5;
+        }
+        return clamp(0.5 + 0.5 * (value - midpoint) / halfRange, 0.0, 1.0);
+    }
+    if (maxValue <= minValue) {
+        return 0.0;
+    }
+    return clamp((value - minValue) / (maxValue - minValue), 0.0, 1.0);
+}
+
+// The color at position in a color map's lookup table, a single row of texels from 0 to 1
+vec3 colorMapColor(sampler2D colorMap, float position) {
+    // Between the first and last texels' centers, so each end gets exactly its color
+    float size = float(textureSize(colorMap, 0).x);
+    float u = (position * (size - 1.0) + 0.5) / size;
+    return texture(colorMap, vec2(u, 0.5)).rgb;
+}
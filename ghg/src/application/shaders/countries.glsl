@@ -0,0 +1,20 @@
+// The countries layer: each country filled with a hue from its identity, transparent over the ocean
+
+uniform sampler2D s_countryMap;
+// Countries as the byte their identity is stored as in s_countryMap's alpha, 0 for none
+uniform int u_hoveredCountry;
+uniform int u_selectedCountry;
+
+vec4 getCountryColor(vec2 texturePoint) {
+    vec4 countryColor = texture(s_countryMap, texturePoint);
+    if (countryColor.a == 0.0) {
+        return vec4(0.0);
+    }
+
+    int country = int(round(countryColor.a * 255.0));
+    float lightness = country == u_selectedCountry ? 0.85
+        : country == u_hoveredCountry ? 0.7
+        : 0.5;
+    vec3 color = hsl2rgb(vec3(countryColor.a, 1.0, lightness));
+    return vec4(color, 1.0);
+}
@@ -0,0 +1,42 @@
+// The data layer: a month of data, colored by a color map
+
+// NUM_MAPS_PER_YEAR and NUM_CHANNELS_IN_MAP are defined from data.rs
+// Two adjacent months are blended, so playback is continuous. Each has the map holding it bound.
+uniform int u_dataMonth;
+uniform int u_dataNextMonth;
+uniform float u_dataBlend; // 0 for just u_dataMonth, 1 for just u_dataNextMonth
+uniform sampler2D s_dataMap;
+uniform sampler2D s_dataNextMap;
+// Each map's channel ranges. TODO: float for year- or data-length min/max
+uniform vec4 u_dataMinValues[NUM_MAPS_PER_YEAR];
+uniform vec4 u_dataMaxValues[NUM_MAPS_PER_YEAR];
+// The color map's lookup table, and the value at its middle for diverging maps
+uniform sampler2D s_dataColorMap;
+uniform int u_dataHasMidpoint;
+uniform float u_dataMidpoint;
+
+// A month's value at texturePoint, then the min and max of its channel
+vec3 getMonthValue(sampler2D dataMap, int month, vec2 texturePoint) {
+    int mapIndex = month / NUM_CHANNELS_IN_MAP;
+    int channelInMap = month % NUM_CHANNELS_IN_MAP;
+
+    vec4 minValues = u_dataMinValues[mapIndex];
+    vec4 maxValues = u_dataMaxValues[mapIndex];
+    vec4 dataRealValue = channelValues(dataMap, texturePoint, minValues, maxValues);
+
+    return vec3(
+        channelIndex(dataRealValue, channelInMap),
+        channelIndex(minValues, channelInMap),
+        channelIndex(maxValues, channelInMap)
+    );
+}
+
+vec4 getDataColor(vec2 texturePoint) {
+    // The ranges are blended too, so the colors match each month's exactly at either end
+    vec3 month = getMonthValue(s_dataMap, u_dataMonth, texturePoint);
+    vec3 nextMonth = getMonthValue(s_dataNextMap, u_dataNextMonth, texturePoint);
+    vec3 blended = mix(month, nextMonth, u_dataBlend);
+
+    float position = colorMapPosition(blended.x, blended.y, blended.z, u_dataHasMidpoint != 0, u_dataMidpoint);
+    return vec4(colorMapColor(s_dataColorMap, position), 1.0);
+}
@@ -4,9 +4,7 @@ layout (location = 0) in vec3 position;
 layout (location = 1) in vec3 normal;
 layout (location = 2) in vec4 color;
 
-uniform mat4 u_model;
-uniform mat4 u_view;
-uniform mat4 u_projection;
+#include <application/shaders/camera.glsl>
 
 uniform vec3 u_meshTranslation;
 uniform float u_meshScale;
@@ -0,0 +1,46 @@
+// Composes the layers over the terrain, as the LayerStack in layers.rs describes them. Each layer's color comes from
+// its own get...Color function, included before this.
+
+// MAX_LAYERS, each layer's LAYER_ id and LAYER_COLOR, which calls the color function of a layer by its id, are
+// defined from the layers' declarations in layers.rs
+
+// As BlendMode::shader_id gives them
+const int BLEND_NORMAL = 0;
+const int BLEND_MULTIPLY = 1;
+const int BLEND_SCREEN = 2;
+const int BLEND_ADD = 3;
+
+// The visible layers, bottom first, and how each is drawn
+uniform int u_layerCount;
+uniform ivec4 u_layers;
+uniform vec4 u_layerOpacities;
+uniform ivec4 u_layerBlendModes;
+
+vec4 getLayerColor(int layer, vec2 texturePoint) {
+    return LAYER_COLOR(layer, texturePoint);
+}
+
+vec3 blendColors(vec3 base, vec3 color, int blendMode) {
+    if (blendMode == BLEND_MULTIPLY) {
+        return base * color;
+    } else if (blendMode == BLEND_SCREEN) {
+        return 1.0 - (1.0 - base) * (1.0 - color);
+    } else if (blendMode == BLEND_ADD) {
+        return min(base + color, 1.0);
+    }
+    return color;
+}
+
+// Each visible layer over base in turn. Where a layer is transparent, what's beneath shows through.
+vec3 composeLayers(vec3 base, vec2 texturePoint) {
+    vec3 composed = base;
+    for (int slot = 0; slot < MAX_LAYERS; slot++) {
+        if (slot >= u_layerCount) {
+            break;
+        }
+        vec4 color = getLayerColor(u_layers[slot], texturePoint);
+        vec3 blended = blendColors(composed, color.rgb, u_layerBlendModes[slot]);
+        composed = mix(composed, blended, u_layerOpacities[slot] * color.a);
+    }
+    return composed;
+}
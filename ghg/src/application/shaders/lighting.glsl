@@ -0,0 +1,9 @@
+// The scene's light, shared by every program through a uniform buffer. Matches `LightingBlock`.
+
+layout(std140) uniform Lighting {
+    highp vec3 u_ambientColor;
+    highp float u_ambientStrength;
+    highp vec3 u_lightColor;
+    highp float u_specularStrength;
+    highp vec3 u_lightPosition;
+};
@@ -1,11 +1,22 @@
 #version 300 es
 
-precision mediump float;
+// Full-resolution tiles are addressed to the texel, which mediump can't do
+precision highp float;
+precision highp int;
+precision mediump sampler2DArray;
+precision highp isampler2D;
 
 #include <application/shaders/channels.glsl>
 #include <application/shaders/color.glsl>
+#include <application/shaders/colormap.glsl>
 #include <application/shaders/pointmapping.glsl>
 #include <application/shaders/math.glsl>
+#include <application/shaders/tiles.glsl>
+#include <application/shaders/countries.glsl>
+#include <application/shaders/data.glsl>
+#include <application/shaders/layers.glsl>
+#include <application/shaders/camera.glsl>
+#include <application/shaders/lighting.glsl>
 
 in vec3 fragPosition;
 in vec3 fragNormal;
@@ -14,28 +25,15 @@ in vec4 fragColor;
 uniform sampler2D s_textureMap;
 uniform sampler2D s_colorMap;
 
-out vec4 outColor;
-
-// Lighting parameters
-uniform float u_ambientStrength;
-uniform vec3 u_ambientColor;
-
-uniform vec3 u_lightPosition;
-uniform vec3 u_lightColor;
-
-uniform vec3 u_cameraPosition;
-uniform float u_specularStrength;
-
-// Country parameters
-uniform sampler2D s_countryMap;
+// Streamed color tiles, used instead of s_colorMap once the coarsest level is loaded
+uniform int u_colorTilesReady;
+uniform sampler2DArray s_colorTiles;
+uniform isampler2D s_colorTilePages;
+uniform ivec2 u_colorTileLevel0Size;
+uniform int u_colorTileSize;
+uniform int u_colorTileLevelCount;
 
-// Data parameters
-const int NUM_MAPS_PER_YEAR = 3;
-const int NUM_CHANNELS_IN_MAP = 4;
-uniform int u_dataMonth;
-uniform sampler2D s_dataMap;
-uniform mat3x4 u_dataMinValues; // TOOD: float for year- or data-length min/max
-uniform mat3x4 u_dataMaxValues;
+out vec4 outColor;
 
 vec3 getAmbientLight() {
     return u_ambientStrength * u_ambientColor;
@@ -64,46 +62,16 @@ vec4 getTerrainColor() {
     vec2 fragSamplePosition = pointToUv(normalize(fragPosition));
 
     float terrainValue = texture(s_textureMap, fragSamplePosition).r;
-    vec4 mappedColor = texture(s_colorMap, fragSamplePosition);
+    vec4 mappedColor = u_colorTilesReady != 0
+        ? sampleTiles(s_colorTiles, s_colorTilePages, u_colorTileLevel0Size, u_colorTileSize,
+                      u_colorTileLevelCount, fragSamplePosition)
+        : texture(s_colorMap, fragSamplePosition);
     return mix(fragColor, mappedColor, 0.99);
 
     // Grayscale based on depth:
 //    return mix(fragColor, vec4(terrainValue, terrainValue, terrainValue, 1.0), 0.93);
 }
 
-vec4 getCountryColor() {
-    vec2 texturePoint = pointToUv(normalize(fragPosition));
-    vec4 countryColor = texture(s_countryMap, texturePoint);
-    if (countryColor.a == 0.0) {
-        return vec4(0.0);
-    } else {
-        vec3 color = hsl2rgb(vec3(countryColor.a, 1.0, 0.5));
-        return vec4(color, 1.0);
-    }
-}
-
-vec4 getDataColor() {
-    int mapIndex = u_dataMonth / NUM_MAPS_PER_YEAR;
-    int channelInMap = u_dataMonth % NUM_CHANNELS_IN_MAP;
-
-    vec4 minValues = u_dataMinValues[mapIndex];
-    vec4 maxValues = u_dataMaxValues[mapIndex];
-
-    vec2 texturePoint = pointToUv(normalize(fragPosition));
-    vec4 dataRealValue = channelValues(s_dataMap, texturePoint, minValues, maxValues);
-
-    vec4 dataRange = maxValues - minValues;
-
-    vec4 dataProportion = (dataRealValue - minValues) / dataRange;
-    float truncateColorSpace = 0.9;
-    vec4 truncatedProportion = (vec4(1.0) - dataProportion) * truncateColorSpace;
-
-    float channelValue = channelIndex(truncatedProportion, channelInMap);
-
-    vec3 withinColorSpace = hsl2rgb(vec3(channelValue, 1.0, 0.5));
-    return vec4(withinColorSpace, 1.0);
-}
-
 void main() {
     vec3 lightDir = normalize(u_lightPosition - fragPosition);
     vec3 norm = normalize(fragNormal);
@@ -113,12 +81,8 @@ void main() {
     + getSpecularLight(lightDir, norm);
 
     vec4 terrainColor = getTerrainColor();
-    vec4 countryColor = getCountryColor();
-    vec4 dataColor = getDataColor();
-
-//    vec4 surfaceColor = mix(fragColor, countryColor, 0.8);
-    vec4 surfaceColor = mix(terrainColor, countryColor, 0.6);
-//    surfaceColor = mix(surfaceColor, dataColor, 0.4);
+    vec2 texturePoint = pointToUv(normalize(fragPosition));
+    vec4 surfaceColor = vec4(composeLayers(terrainColor.rgb, texturePoint), terrainColor.a);
 
     outColor = surfaceColor * vec4(totalLightColor, 1.0);
 }
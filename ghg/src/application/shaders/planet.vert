@@ -11,9 +11,7 @@ uniform sampler2D s_colorMap;
 
 uniform float u_terrainScale;
 
-uniform mat4 u_model;
-uniform mat4 u_view;
-uniform mat4 u_projection;
+#include <application/shaders/camera.glsl>
 
 out vec3 fragPosition;
 out vec3 fragNormal;
@@ -0,0 +1,34 @@
+// Samples a tile pyramid streamed by tile_streaming.rs. The page table holds
+// the layer of each resident tile (or -1), with one row per tile row and the
+// levels stacked from 0 down. Levels halve in size, rounding down.
+const int MAX_TILE_LEVELS = 16;
+
+vec4 sampleTiles(sampler2DArray tiles, isampler2D pages, ivec2 level0Size, int tileSize, int levelCount,
+                 vec2 uv) {
+    int rowOffset = 0;
+    ivec2 levelSize = level0Size;
+
+    // The finest resident level wins
+    for (int level = 0; level < MAX_TILE_LEVELS; ++level) {
+        if (level >= levelCount) {
+            break;
+        }
+
+        vec2 texel = uv * vec2(levelSize);
+        ivec2 tile = min(ivec2(texel), levelSize - 1) / tileSize;
+        int layer = texelFetch(pages, ivec2(tile.x, rowOffset + tile.y), 0).r;
+
+        if (layer >= 0) {
+            // Edge tiles can be partial; keep filtering inside the part that was written
+            ivec2 tileStart = tile * tileSize;
+            vec2 tileExtent = vec2(min(ivec2(tileSize), levelSize - tileStart));
+            vec2 inTile = clamp(texel - vec2(tileStart), vec2(0.5), tileExtent - 0.5);
+            return texture(tiles, vec3(inTile / float(tileSize), float(layer)));
+        }
+
+        rowOffset += (levelSize.y + tileSize - 1) / tileSize;
+        levelSize = max(levelSize / 2, ivec2(1));
+    }
+
+    return vec4(0.0);
+}
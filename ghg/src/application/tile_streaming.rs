@@ -0,0 +1,386 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashSet};
+use std::f32::consts::{PI, TAU};
+use std::ops::Deref;
+use std::rc::Rc;
+
+use ghg_data_core::tiles::TileSet;
+use image::Rgb;
+use single_thread_executor::Spawner;
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+use crate::application::planet::{PLANET_POINTS_PER_SUBDIVISION, PLANET_SUBDIVISIONS};
+use crate::application::shaders::ShaderContext;
+use crate::application::sphere::generate_sphere;
+use crate::application::vertex::BasicMesh;
+use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::camera::Camera;
+use crate::render_core::frame_sequencer::FrameGate;
+use crate::render_core::image::decode_png;
+use crate::render_core::mesh::ToMesh;
+use crate::render_core::texture_registry::TextureRegistry;
+use crate::render_core::tile_cache::{TileCache, TileKey};
+use crate::render_core::tile_texture::TileTexture;
+use crate::render_core::uniform;
+use crate::request_data::fetch_bytes;
+#[allow(unused_imports)]
+use crate::utils::prelude::*;
+
+const COLOR_TILES_ROOT: &str = "images/earth_color";
+
+type ArrivedTiles = Rc<RefCell<Vec<(TileKey, Result<Vec<u8>, JsValue>)>>>;
+
+/// GPU memory the color tiles may use. Roughly 128 RGB tiles of 512x512.
+const TILE_MEMORY_BUDGET: usize = 96 * 1024 * 1024;
+
+/// Tile requests allowed at once, so a big camera move doesn't queue up
+/// hundreds of fetches that are stale by the time they arrive
+const MAX_REQUESTS_IN_FLIGHT: usize = 6;
+
+/// Decoding and uploading a tile is slow enough to drop frames if too many
+/// arrive at once
+const MAX_UPLOADS_PER_FRAME: usize = 2;
+
+/// A region of texture space, as sampled by `pointToUv` in the shaders
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+	pub min: nglm::Vec2,
+	pub max: nglm::Vec2,
+}
+
+/// Same mapping as `pointToUv` in `pointmapping.glsl`
+pub fn point_to_uv(point: &nglm::Vec3) -> nglm::Vec2 {
+	let point = point.normalize();
+	let u = (0.5 + point.x.atan2(point.z) / TAU).clamp(0.0, 1.0);
+	let v = (0.5 + point.y.asin() / PI).clamp(0.0, 1.0);
+	nglm::vec2(u, v)
+}
+
+/// The texture space covered by a sphere patch. Patches across the seam at
+/// u = 0 are split in two, and patches touching a pole cover every u.
+pub fn patch_uv_rects(positions: impl Iterator<Item = nglm::Vec3>) -> Vec<UvRect> {
+	const POLE_RADIUS: f32 = 1e-4;
+
+	let mut touches_pole = false;
+	let uvs: Vec<nglm::Vec2> = positions
+		.inspect(|p| touches_pole |= p.x.hypot(p.z) < POLE_RADIUS * p.magnitude())
+		.map(|p| point_to_uv(&p))
+		.collect();
+	if uvs.is_empty() {
+		return vec![];
+	}
+
+	let v_min = uvs.iter().map(|uv| uv.y).fold(f32::INFINITY, f32::min);
+	let v_max = uvs.iter().map(|uv| uv.y).fold(f32::NEG_INFINITY, f32::max);
+	let u_range = |us: &mut dyn Iterator<Item = f32>| {
+		us.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), u| (min.min(u), max.max(u)))
+	};
+	let rect = |u_min: f32, u_max: f32| UvRect {
+		min: nglm::vec2(u_min, v_min),
+		max: nglm::vec2(u_max, v_max),
+	};
+
+	if touches_pole {
+		return vec![rect(0.0, 1.0)];
+	}
+
+	let (u_min, u_max) = u_range(&mut uvs.iter().map(|uv| uv.x));
+	if u_max - u_min <= 0.5 {
+		return vec![rect(u_min, u_max)];
+	}
+
+	// No patch is anywhere near half the globe wide, so it must wrap
+	let (_, west_max) = u_range(&mut uvs.iter().map(|uv| uv.x).filter(|&u| u < 0.5));
+	let (east_min, _) = u_range(&mut uvs.iter().map(|uv| uv.x).filter(|&u| u >= 0.5));
+	vec![rect(0.0, west_max), rect(east_min, 1.0)]
+}
+
+/// The coarsest level with at least one texel per screen pixel, for a surface
+/// `distance` from a camera with a vertical field of view of `fov_radians`
+pub fn level_for_distance(
+	tile_set: &TileSet,
+	distance: f32,
+	viewport_height: f32,
+	fov_radians: f32,
+) -> usize {
+	// On a unit sphere, a radian of arc is one unit of distance
+	let pixels_per_radian = viewport_height / (2.0 * distance * (fov_radians / 2.0).tan());
+
+	tile_set
+		.levels
+		.iter()
+		.rev()
+		.find(|level| level.width as f32 / TAU >= pixels_per_radian)
+		.or(tile_set.levels.first())
+		.map_or(0, |level| level.level)
+}
+
+/// The tiles of `level` covering `rect`. Tile rows follow texture v, the same
+/// as the full-size textures.
+pub fn tiles_in_rect(tile_set: &TileSet, level: usize, rect: &UvRect) -> Vec<TileKey> {
+	let Some(tile_level) = tile_set.level(level) else {
+		return vec![];
+	};
+
+	let to_tile = |coordinate: f32, size: u32| {
+		let texel = ((coordinate * size as f32) as u32).min(size - 1);
+		texel / tile_set.tile_size
+	};
+	let columns = to_tile(rect.min.x, tile_level.width)..=to_tile(rect.max.x, tile_level.width);
+	let rows = to_tile(rect.min.y, tile_level.height)..=to_tile(rect.max.y, tile_level.height);
+
+	columns.flat_map(|x| rows.clone().map(move |y| TileKey::new(level, x, y))).collect()
+}
+
+/// A sphere patch, as drawn by `planet::draw`, with the texture space it covers
+struct Patch {
+	mesh: BasicMesh,
+	uv_rects: Vec<UvRect>,
+}
+
+fn generate_patches() -> Vec<Patch> {
+	generate_sphere(PLANET_SUBDIVISIONS, PLANET_POINTS_PER_SUBDIVISION)
+		.into_iter()
+		.map(|mesh| {
+			let uv_rects = patch_uv_rects(mesh.vertices().iter().map(|v| v.get_position()));
+			Patch { mesh, uv_rects }
+		})
+		.collect()
+}
+
+/// Every tile needed for the current view, coarsest first, so there's always
+/// something to fall back on while the finer ones load
+fn needed_tiles(
+	cache: &TileCache,
+	patches: &[Patch],
+	camera: &Camera,
+	viewport_height: f32,
+) -> BTreeSet<(Reverse<usize>, TileKey)> {
+	let tile_set = cache.tile_set();
+	let coarsest_level = cache.coarsest_level();
+
+	let fallback = tile_set.level(coarsest_level).into_iter().flat_map(|level| {
+		(0..level.columns)
+			.flat_map(move |x| (0..level.rows).map(move |y| TileKey::new(coarsest_level, x, y)))
+	});
+
+	let visible = patches.iter().filter(|patch| patch.mesh.is_visible(camera)).flat_map(|patch| {
+		let center = patch.mesh.get_center().unwrap_or_default();
+		let distance = camera.position().metric_distance(&center);
+		let level = level_for_distance(tile_set, distance, viewport_height, camera.fov_radians());
+		patch.uv_rects.iter().flat_map(move |rect| tiles_in_rect(tile_set, level, rect))
+	});
+
+	fallback.chain(visible).map(|key| (Reverse(key.level), key)).collect()
+}
+
+/// Streams the color map in tiles at the level of detail the camera needs,
+/// falling back to the single texture `planet::load_textures` loads if there
+/// are no tiles
+pub async fn stream_color_tiles(
+	gate: FrameGate<AnimationParams>,
+	spawner: Spawner,
+	shader: ShaderContext,
+	camera: Rc<RefCell<Camera>>,
+	textures: TextureRegistry,
+) {
+	let tiles_unit = textures.take();
+	let pages_unit = textures.take();
+
+	// Every sampler needs its own unit before anything is drawn, even unused
+	shader.use_shader();
+	uniform::init_i32("s_colorTiles", &shader, tiles_unit.index() as i32);
+	uniform::init_i32("s_colorTilePages", &shader, pages_unit.index() as i32);
+	let mut tiles_ready = uniform::init_smart_i32("u_colorTilesReady", &shader, 0);
+
+	let tile_set = match load_tile_set(COLOR_TILES_ROOT).await {
+		Ok(tile_set) => tile_set,
+		Err(error) => {
+			ghg_log!("No color tiles, using the full texture: {:?}", error);
+			return;
+		}
+	};
+
+	let tile_size = tile_set.tile_size;
+	let layers = max_layers(textures.context(), &tile_set);
+	ghg_log!("Streaming color tiles into {layers} layers");
+
+	let texture =
+		match TileTexture::<Rgb<u8>>::new(&textures, &tiles_unit, &pages_unit, tile_size, layers) {
+			Ok(texture) => texture,
+			Err(error) => {
+				ghg_error!("Failed to create color tile texture: {:?}", error);
+				return;
+			}
+		};
+
+	shader.use_shader();
+	let level_0 = tile_set.levels[0];
+	uniform::init_ivec2(
+		"u_colorTileLevel0Size",
+		&shader,
+		nglm::vec2(level_0.width as i32, level_0.height as i32),
+	);
+	uniform::init_i32("u_colorTileSize", &shader, tile_size as i32);
+	uniform::init_i32("u_colorTileLevelCount", &shader, tile_set.levels.len() as i32);
+
+	let mut cache = TileCache::new(tile_set, layers);
+	let patches = generate_patches();
+	let arrived: ArrivedTiles = Default::default();
+	let mut in_flight = HashSet::new();
+	let mut failed = HashSet::new();
+
+	loop {
+		let params = (&gate).await;
+
+		let uploads: Vec<_> = {
+			let mut arrived = arrived.borrow_mut();
+			let count = arrived.len().min(MAX_UPLOADS_PER_FRAME);
+			arrived.drain(..count).collect()
+		};
+		for (key, result) in uploads {
+			in_flight.remove(&key);
+			if let Err(error) =
+				result.and_then(|bytes| upload_tile(&mut cache, &texture, key, &bytes))
+			{
+				ghg_error!("Failed to load tile {:?}: {:?}", key, error);
+				failed.insert(key);
+			}
+		}
+
+		if cache.take_page_table_changed() {
+			let (width, height, entries) = cache.page_table();
+			if let Err(error) = texture.upload_page_table(width, height, entries) {
+				ghg_error!("Failed to upload tile page table: {:?}", error);
+			}
+			shader.use_shader();
+			tiles_ready.smart_write(cache.has_fallback() as i32);
+		}
+
+		cache.next_frame();
+		let needed = needed_tiles(
+			&cache,
+			&patches,
+			camera.deref().borrow().deref(),
+			params.viewport.height(),
+		);
+		for (_, key) in needed {
+			if cache.touch(&key) || in_flight.contains(&key) || failed.contains(&key) {
+				continue;
+			}
+			if in_flight.len() >= MAX_REQUESTS_IN_FLIGHT {
+				break;
+			}
+
+			in_flight.insert(key);
+			let arrived = arrived.clone();
+			spawner.spawn(async move {
+				let url =
+					format!("{COLOR_TILES_ROOT}/{}", TileSet::tile_path(key.level, key.x, key.y));
+				let result = fetch_bytes(url.as_str()).await;
+				arrived.borrow_mut().push((key, result));
+			});
+		}
+	}
+}
+
+async fn load_tile_set(root: &str) -> Result<TileSet, JsValue> {
+	let descriptor = fetch_bytes(format!("{root}/{}", TileSet::FILE_NAME).as_str()).await?;
+	serde_json::from_slice(&descriptor).map_err(|e| e.to_string().into())
+}
+
+/// As many layers as fit `TILE_MEMORY_BUDGET`, and the GPU allows
+fn max_layers(context: &WebGl2RenderingContext, tile_set: &TileSet) -> u32 {
+	let tile_bytes = tile_set.tile_size as usize * tile_set.tile_size as usize;
+	let budget_layers = (TILE_MEMORY_BUDGET / (tile_bytes * tile_set.channels as usize)) as u32;
+
+	let gpu_layers = context
+		.get_parameter(WebGl2RenderingContext::MAX_ARRAY_TEXTURE_LAYERS)
+		.ok()
+		.and_then(|layers| layers.as_f64())
+		.map_or(256, |layers| layers as u32);
+
+	budget_layers.min(gpu_layers)
+}
+
+fn upload_tile(
+	cache: &mut TileCache,
+	texture: &TileTexture<Rgb<u8>>,
+	key: TileKey,
+	png_bytes: &[u8],
+) -> Result<(), JsValue> {
+	let (pixels, (width, height)) = decode_png(png_bytes)?;
+	match cache.insert(key) {
+		Some(layer) => texture.upload_tile(layer, &pixels, width, height),
+		// Everything resident is in view; it'll be asked for again if it still is
+		None => Ok(()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ghg_data_core::tiles::TileLevel;
+
+	use super::*;
+
+	fn tile_set() -> TileSet {
+		TileSet {
+			tile_size: 512,
+			channels: 3,
+			levels: vec![
+				TileLevel::new(0, 4096, 2048, 512),
+				TileLevel::new(1, 2048, 1024, 512),
+				TileLevel::new(2, 1024, 512, 512),
+				TileLevel::new(3, 512, 256, 512),
+			],
+		}
+	}
+
+	#[test]
+	fn closer_needs_finer_levels() {
+		let tile_set = tile_set();
+		let fov = nglm::quarter_pi::<f32>();
+
+		assert_eq!(level_for_distance(&tile_set, 100.0, 800.0, fov), 3);
+		assert_eq!(level_for_distance(&tile_set, 8.0, 800.0, fov), 2);
+		assert_eq!(level_for_distance(&tile_set, 5.0, 800.0, fov), 1);
+		assert_eq!(level_for_distance(&tile_set, 2.0, 800.0, fov), 0);
+		// Closer than level 0 can keep up with
+		assert_eq!(level_for_distance(&tile_set, 0.01, 800.0, fov), 0);
+	}
+
+	#[test]
+	fn patch_within_one_tile() {
+		let positions = [nglm::vec3(0.1, 0.0, 1.0), nglm::vec3(0.2, 0.1, 1.0)];
+		let rects = patch_uv_rects(positions.into_iter());
+		assert_eq!(rects.len(), 1);
+
+		let tiles = tiles_in_rect(&tile_set(), 3, &rects[0]);
+		assert_eq!(tiles, vec![TileKey::new(3, 0, 0)]);
+	}
+
+	#[test]
+	fn patch_across_seam_is_split() {
+		let positions = [nglm::vec3(0.1, 0.0, -1.0), nglm::vec3(-0.1, 0.0, -1.0)];
+		let rects = patch_uv_rects(positions.into_iter());
+
+		assert_eq!(rects.len(), 2);
+		assert_eq!(rects[0].min.x, 0.0);
+		assert_eq!(rects[1].max.x, 1.0);
+		assert!(rects[0].max.x < 0.1 && rects[1].min.x > 0.9);
+	}
+
+	#[test]
+	fn patch_at_pole_covers_every_column() {
+		let positions = [nglm::vec3(0.0, 1.0, 0.0), nglm::vec3(0.1, 0.99, 0.1)];
+		let rects = patch_uv_rects(positions.into_iter());
+		assert_eq!(rects.len(), 1);
+
+		let tiles = tiles_in_rect(&tile_set(), 0, &rects[0]);
+		let columns: BTreeSet<u32> = tiles.iter().map(|key| key.x).collect();
+		assert_eq!(columns.len(), 8);
+		assert!(tiles.iter().all(|key| key.y == 3));
+	}
+}
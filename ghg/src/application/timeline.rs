@@ -0,0 +1,355 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::application::data::MONTH_NAMES;
+use crate::application::events::{detail_object, ViewerEvent, ViewerEvents};
+use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::frame_sequencer::FrameGate;
+use crate::utils::prelude::*;
+
+const DEFAULT_STEPS_PER_SECOND: f32 = 2.0;
+
+/// Which time step of the data is shown, and how it plays. Time steps are
+/// months, counted from January of the dataset's first year, and there are as
+/// many as the loaded data has channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timeline {
+	first_year: u32,
+	step_count: usize,
+	current: usize,
+	playing: bool,
+	steps_per_second: f32,
+	/// Playback stays within these steps, inclusive. The whole timeline if
+	/// unset.
+	range: Option<(usize, usize)>,
+	looping: bool,
+	/// How far playback is towards the next step, in steps
+	progress: f32,
+}
+
+impl Default for Timeline {
+	fn default() -> Self {
+		Self {
+			first_year: 0,
+			step_count: 0,
+			current: 0,
+			playing: false,
+			steps_per_second: DEFAULT_STEPS_PER_SECOND,
+			range: None,
+			looping: true,
+			progress: 0.0,
+		}
+	}
+}
+
+/// What the host page is told about the timeline, whenever it changes
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelineState {
+	pub step: usize,
+	pub step_count: usize,
+	pub year: u32,
+	pub month: usize,
+	pub playing: bool,
+	pub steps_per_second: f32,
+	pub range: (usize, usize),
+	pub looping: bool,
+}
+
+impl TimelineState {
+	/// The `detail` of a `ViewerEvent::Timeline`
+	fn detail(&self) -> JsValue {
+		detail_object(&[
+			("step", self.step.into()),
+			("stepCount", self.step_count.into()),
+			("year", self.year.into()),
+			("month", self.month.into()),
+			("date", format!("{} {}", MONTH_NAMES[self.month], self.year).into()),
+			("playing", self.playing.into()),
+			("stepsPerSecond", self.steps_per_second.into()),
+			("rangeStart", self.range.0.into()),
+			("rangeEnd", self.range.1.into()),
+			("looping", self.looping.into()),
+		])
+	}
+}
+
+impl Timeline {
+	/// Takes the time steps from newly loaded data, keeping the current step
+	/// and range where they still fit
+	pub fn set_steps(&mut self, first_year: u32, step_count: usize) {
+		self.first_year = first_year;
+		self.step_count = step_count;
+		self.current = self.current.min(step_count.saturating_sub(1));
+		self.range = self.range.filter(|&(_, last)| last < step_count);
+		self.progress = 0.0;
+	}
+
+	pub fn current_step(&self) -> usize { self.current }
+
+	pub fn step_count(&self) -> usize { self.step_count }
+
+	pub fn state(&self) -> TimelineState {
+		let (year, month) = self.date_of(self.current);
+		TimelineState {
+			step: self.current,
+			step_count: self.step_count,
+			year,
+			month,
+			playing: self.playing,
+			steps_per_second: self.steps_per_second,
+			range: self.range(),
+			looping: self.looping,
+		}
+	}
+
+	/// The year and month, from 0 for January, of `step`
+	pub fn date_of(&self, step: usize) -> (u32, usize) {
+		let months = MONTH_NAMES.len();
+		(self.first_year + (step / months) as u32, step % months)
+	}
+
+	/// Starts playing from the current step, or from the start of the range if
+	/// the current step is outside it
+	pub fn play(&mut self) {
+		if self.step_count == 0 {
+			return;
+		}
+		let (first, last) = self.range();
+		if !(first..=last).contains(&self.current) {
+			self.current = first;
+		}
+		self.playing = true;
+	}
+
+	pub fn pause(&mut self) {
+		self.playing = false;
+		self.progress = 0.0;
+	}
+
+	pub fn toggle_playing(&mut self) {
+		if self.playing {
+			self.pause();
+		} else {
+			self.play();
+		}
+	}
+
+	pub fn set_speed(&mut self, steps_per_second: f32) -> Result<(), String> {
+		if !steps_per_second.is_finite() || steps_per_second <= 0.0 {
+			return Err(format!(
+				"Speed must be a positive number of steps per second, not {steps_per_second}"
+			));
+		}
+		self.steps_per_second = steps_per_second;
+		Ok(())
+	}
+
+	/// Keeps playback between `first` and `last`, inclusive
+	pub fn set_range(&mut self, first: usize, last: usize) -> Result<(), String> {
+		if first > last || last >= self.step_count {
+			return Err(format!(
+				"Range {first}-{last} is outside the {} time steps",
+				self.step_count
+			));
+		}
+		self.range = Some((first, last));
+		Ok(())
+	}
+
+	pub fn clear_range(&mut self) { self.range = None; }
+
+	/// Whether playback starts over at the end of the range, rather than
+	/// stopping
+	pub fn set_looping(&mut self, looping: bool) { self.looping = looping; }
+
+	/// Moves `steps` forwards or backwards, wrapping around the whole timeline
+	pub fn step(&mut self, steps: i32) {
+		if self.step_count == 0 {
+			return;
+		}
+		self.current =
+			(self.current as i64 + steps as i64).rem_euclid(self.step_count as i64) as usize;
+		self.progress = 0.0;
+	}
+
+	pub fn seek(&mut self, step: usize) -> Result<(), String> {
+		if step >= self.step_count {
+			return Err(format!("Time step {step} is out of range, there are {}", self.step_count));
+		}
+		self.current = step;
+		self.progress = 0.0;
+		Ok(())
+	}
+
+	/// Seeks to a year and month, from 0 for January
+	pub fn seek_to_date(&mut self, year: u32, month: usize) -> Result<(), String> {
+		let months = MONTH_NAMES.len();
+		if year < self.first_year || month >= months {
+			return Err(format!("There's no data for month {month} of {year}"));
+		}
+		self.seek((year - self.first_year) as usize * months + month)
+	}
+
+	/// Moves playback on by `delta_time`
+	pub fn advance(&mut self, delta_time: Duration) {
+		if !self.playing {
+			return;
+		}
+
+		let (first, last) = self.range();
+		self.progress += delta_time.as_secs_f32() * self.steps_per_second;
+		while self.progress >= 1.0 {
+			self.progress -= 1.0;
+			if self.current < last {
+				self.current += 1;
+			} else if self.looping {
+				self.current = first;
+			} else {
+				self.pause();
+			}
+		}
+	}
+
+	/// The step shown, the step playback is moving towards, and how far it is
+	/// between them, so playback can be drawn continuously
+	pub fn blend(&self) -> (usize, usize, f32) {
+		if !self.playing {
+			return (self.current, self.current, 0.0);
+		}
+
+		let (first, last) = self.range();
+		let next = if self.current < last {
+			self.current + 1
+		} else if self.looping {
+			first
+		} else {
+			self.current
+		};
+		(self.current, next, self.progress)
+	}
+
+	fn range(&self) -> (usize, usize) {
+		self.range.unwrap_or((0, self.step_count.saturating_sub(1)))
+	}
+}
+
+/// Plays the timeline, and tells the host page about it whenever it changes
+pub async fn play_timeline(
+	gate: FrameGate<AnimationParams>,
+	events: ViewerEvents,
+	timeline: Rc<RefCell<Timeline>>,
+) {
+	let mut reported = None;
+
+	loop {
+		let params = (&gate).await;
+
+		timeline.borrow_mut().advance(params.delta_time);
+
+		let state = timeline.borrow().state();
+		if reported.as_ref() != Some(&state) {
+			events.send(ViewerEvent::Timeline, &state.detail());
+			reported = Some(state);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn timeline() -> Timeline {
+		let mut timeline = Timeline::default();
+		timeline.set_steps(2021, 12);
+		timeline
+	}
+
+	#[test]
+	fn plays_at_speed() {
+		let mut timeline = timeline();
+		timeline.advance(Duration::from_secs(1));
+		assert_eq!(timeline.current_step(), 0);
+
+		timeline.play();
+		timeline.advance(Duration::from_millis(250));
+		assert_eq!(timeline.current_step(), 0);
+		timeline.advance(Duration::from_millis(250));
+		assert_eq!(timeline.current_step(), 1);
+
+		timeline.set_speed(4.0).unwrap();
+		timeline.advance(Duration::from_secs(1));
+		assert_eq!(timeline.current_step(), 5);
+		assert!(timeline.set_speed(0.0).is_err());
+	}
+
+	#[test]
+	fn loops_over_the_range() {
+		let mut timeline = timeline();
+		timeline.set_range(3, 5).unwrap();
+		timeline.play();
+		assert_eq!(timeline.current_step(), 3);
+
+		timeline.advance(Duration::from_secs(1));
+		assert_eq!(timeline.current_step(), 5);
+		timeline.advance(Duration::from_millis(500));
+		assert_eq!(timeline.current_step(), 3);
+
+		timeline.set_looping(false);
+		timeline.advance(Duration::from_secs(2));
+		assert_eq!(timeline.current_step(), 5);
+		assert!(!timeline.state().playing);
+
+		assert!(timeline.set_range(5, 12).is_err());
+	}
+
+	#[test]
+	fn blends_towards_the_next_step() {
+		let mut timeline = timeline();
+		timeline.seek(3).unwrap();
+		assert_eq!(timeline.blend(), (3, 3, 0.0));
+
+		timeline.play();
+		timeline.advance(Duration::from_millis(250));
+		assert_eq!(timeline.blend(), (3, 4, 0.5));
+
+		// Across the end of the range, back to its start
+		timeline.set_range(2, 4).unwrap();
+		timeline.advance(Duration::from_millis(500));
+		assert_eq!(timeline.blend(), (4, 2, 0.5));
+
+		timeline.set_looping(false);
+		assert_eq!(timeline.blend(), (4, 4, 0.5));
+	}
+
+	#[test]
+	fn steps_and_seeks() {
+		let mut timeline = timeline();
+		timeline.step(-1);
+		assert_eq!(timeline.current_step(), 11);
+		timeline.step(2);
+		assert_eq!(timeline.current_step(), 1);
+
+		timeline.seek_to_date(2021, 3).unwrap();
+		assert_eq!(timeline.state().month, 3);
+		assert!(timeline.seek_to_date(2022, 0).is_err());
+		assert!(timeline.seek(12).is_err());
+
+		// Fewer steps once different data loads
+		timeline.seek(11).unwrap();
+		timeline.set_steps(1980, 4);
+		assert_eq!(
+			timeline.state(),
+			TimelineState {
+				step: 3,
+				step_count: 4,
+				year: 1980,
+				month: 3,
+				playing: false,
+				steps_per_second: DEFAULT_STEPS_PER_SECOND,
+				range: (0, 3),
+				looping: true,
+			}
+		);
+	}
+}
@@ -67,6 +67,8 @@ impl BasicMesh {
 
 	pub fn push_index(&mut self, index: u32) { self.indices.push(index); }
 
+	pub fn vertices(&self) -> &[Vertex] { &self.vertices }
+
 	pub fn set_visible_fn(&mut self, is_visible_fn: fn(&Self, &Camera) -> bool) {
 		self.is_visible_fn = is_visible_fn;
 	}
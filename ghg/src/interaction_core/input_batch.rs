@@ -330,8 +330,9 @@ fn add_mouse_move_handler(
 ) -> MouseEventHandler {
 	let mouse_move_event_handler = Closure::wrap(Box::new(move |e: MouseEvent| {
 		let dpr: f64 = window().device_pixel_ratio();
-		let x = (e.client_x() as f64 * dpr).round() as i32;
-		let y = (e.client_y() as f64 * dpr).round() as i32;
+		// Relative to the canvas, wherever it is on the page
+		let x = (e.offset_x() as f64 * dpr).round() as i32;
+		let y = (e.offset_y() as f64 * dpr).round() as i32;
 		let new_state = UserInput::<KeyCode>::CursorPosition(nglm::vec2(x, y));
 
 		current_state.replace_with(move |previous| previous.incorporate(new_state));
@@ -6,8 +6,8 @@ use std::time::Duration;
 use wasm_bindgen::JsCast;
 
 use crate::render_core::animation_params::AnimationParams;
+use crate::render_core::viewport::Viewport;
 use crate::utils::prelude::*;
-use crate::Viewport;
 
 pub type AnimationFn = Box<dyn FnMut(AnimationParams)>;
 
@@ -4,15 +4,10 @@ use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 
 use crate::utils;
 
-pub fn get_webgl2_canvas() -> Option<(HtmlCanvasElement, WebGl2RenderingContext)> {
-	let document = web_sys::window().unwrap().document().unwrap();
-	let canvas = document.get_element_by_id("render_canvas").unwrap();
-	let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>().ok()?;
-
-	let context: WebGl2RenderingContext =
-		canvas.get_context("webgl2").ok()?.unwrap().dyn_into::<WebGl2RenderingContext>().ok()?;
-
-	Some((canvas, context))
+/// A WebGL2 context for drawing on `canvas`, if the browser supports it and the
+/// canvas doesn't already have a different kind of context
+pub fn get_webgl2_context(canvas: &HtmlCanvasElement) -> Option<WebGl2RenderingContext> {
+	canvas.get_context("webgl2").ok()??.dyn_into::<WebGl2RenderingContext>().ok()
 }
 
 pub fn update_canvas_size(canvas: &HtmlCanvasElement) -> (u32, u32) {
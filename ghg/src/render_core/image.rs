@@ -5,6 +5,9 @@ use image::{
 use wasm_bindgen::JsValue;
 use web_sys::WebGl2RenderingContext;
 
+use crate::render_core::texture_provider::TextureUnit;
+use crate::render_core::texture_registry::TextureRegistry;
+
 /// This feels like it probably duplicates something that can be done in the
 /// image library already.
 pub trait LoadableImageType {
@@ -95,35 +98,42 @@ impl LoadableImageType for Rgba<u8> {
 }
 
 pub fn load_into_texture<T: LoadableImageType>(
-	context: WebGl2RenderingContext,
+	textures: &TextureRegistry,
 	png_bytes: &[u8],
-	texture_number: u32,
+	unit: &TextureUnit,
 ) -> Result<(), JsValue> {
 	load_into_texture_with_filters::<T>(
-		context,
+		textures,
 		png_bytes,
-		texture_number,
+		unit,
 		WebGl2RenderingContext::LINEAR,
 		WebGl2RenderingContext::LINEAR,
 	)
 }
 
 pub fn load_into_texture_with_filters<T: LoadableImageType>(
-	context: WebGl2RenderingContext,
+	textures: &TextureRegistry,
 	png_bytes: &[u8],
-	texture_number: u32,
+	unit: &TextureUnit,
 	min_filter: u32,
 	mag_filter: u32,
 ) -> Result<(), JsValue> {
-	let decoder = png::Decoder::new(png_bytes);
-	let mut reader = decoder.read_info().map_err(|s| s.to_string())?;
-	let mut buf = vec![0; reader.output_buffer_size()];
-
-	let info = reader.next_frame(&mut buf).map_err(|s| s.to_string())?;
-	let bytes = &buf[..info.buffer_size()];
-
-	let dimensions = (info.width, info.height);
+	let (bytes, dimensions) = decode_png(png_bytes)?;
+	load_pixels_into_texture_with_filters::<T>(
+		textures, &bytes, dimensions, unit, min_filter, mag_filter,
+	)
+}
 
+/// Like `load_into_texture_with_filters`, for pixels that are already decoded.
+/// Whatever texture `unit` had before is deleted.
+pub fn load_pixels_into_texture_with_filters<T: LoadableImageType>(
+	textures: &TextureRegistry,
+	bytes: &[u8],
+	dimensions: (u32, u32),
+	unit: &TextureUnit,
+	min_filter: u32,
+	mag_filter: u32,
+) -> Result<(), JsValue> {
 	// TODO: Probably slower, but worth profiling:
 	// let dyn_img = image::load_from_memory_with_format(png_bytes,
 	// ImageFormat::Png)     .map_err(|e| e.to_string())?;
@@ -133,10 +143,8 @@ pub fn load_into_texture_with_filters<T: LoadableImageType>(
 	// .ok_or(format!("Image was not stored with type {name}"));
 	// let dimensions = concrete_image.dimensions();
 
-	let texture = context.create_texture().ok_or("no texture")?;
-
-	context.active_texture(texture_number);
-	context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
+	textures.create(unit, WebGl2RenderingContext::TEXTURE_2D)?;
+	let context = textures.context();
 
 	context.tex_parameteri(
 		WebGl2RenderingContext::TEXTURE_2D,
@@ -175,3 +183,67 @@ pub fn load_into_texture_with_filters<T: LoadableImageType>(
 
 	Ok(())
 }
+
+/// Decodes a PNG into its raw pixels and `(width, height)`
+pub fn decode_png(png_bytes: &[u8]) -> Result<(Vec<u8>, (u32, u32)), JsValue> {
+	let decoder = png::Decoder::new(png_bytes);
+	let mut reader = decoder.read_info().map_err(|s| s.to_string())?;
+	let mut buf = vec![0; reader.output_buffer_size()];
+
+	let info = reader.next_frame(&mut buf).map_err(|s| s.to_string())?;
+	buf.truncate(info.buffer_size());
+
+	Ok((buf, (info.width, info.height)))
+}
+
+/// A CPU-side copy of a texture's pixels, for looking up what's drawn at a
+/// point without reading back from the GPU
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuImage {
+	pub width: u32,
+	pub height: u32,
+	pub channels: usize,
+	pub pixels: Vec<u8>,
+}
+
+impl CpuImage {
+	pub fn new(pixels: Vec<u8>, (width, height): (u32, u32)) -> Self {
+		let channels = pixels.len() / (width as usize * height as usize);
+		Self { width, height, channels, pixels }
+	}
+
+	/// Keeps only `channel`, to save memory when that's all that's needed
+	pub fn into_channel(self, channel: usize) -> Self {
+		let pixels = self.pixels.iter().skip(channel).step_by(self.channels).copied().collect();
+		Self { width: self.width, height: self.height, channels: 1, pixels }
+	}
+
+	/// The texel `texture(sampler, uv)` would give with `NEAREST` filtering,
+	/// where v follows the image rows
+	pub fn texel(&self, uv: nglm::Vec2) -> &[u8] {
+		let to_texel = |coordinate: f32, size: u32| {
+			((coordinate.clamp(0.0, 1.0) * size as f32) as usize).min(size as usize - 1)
+		};
+		let (x, y) = (to_texel(uv.x, self.width), to_texel(uv.y, self.height));
+		let start = (y * self.width as usize + x) * self.channels;
+		&self.pixels[start..start + self.channels]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn texels_follow_rows() {
+		// 2x2 LumaA, with each texel's alpha its index
+		let image = CpuImage::new(vec![9, 0, 9, 1, 9, 2, 9, 3], (2, 2));
+		assert_eq!(image.channels, 2);
+		assert_eq!(image.texel(nglm::vec2(0.0, 0.0)), [9, 0]);
+		assert_eq!(image.texel(nglm::vec2(0.75, 0.25)), [9, 1]);
+		assert_eq!(image.texel(nglm::vec2(0.25, 1.0)), [9, 2]);
+
+		let alpha = image.into_channel(1);
+		assert_eq!(alpha.texel(nglm::vec2(1.0, 1.0)), [3]);
+	}
+}
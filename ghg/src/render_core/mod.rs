@@ -8,5 +8,8 @@ pub mod mesh;
 /// This module provides the key ingredients to rendering in a WebGL2 context.
 pub mod shader;
 pub mod texture_provider;
+pub mod texture_registry;
+pub mod tile_cache;
+pub mod tile_texture;
 pub mod uniform;
 pub mod viewport;
@@ -1,41 +1,54 @@
-use std::ops::Index;
+#[cfg(debug_assertions)]
+use std::collections::HashMap;
+use std::collections::HashSet;
 
 use phf::{phf_map, Map};
-use regex;
+use regex::Regex;
 use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader};
 
+#[cfg(debug_assertions)]
+use crate::request_data::fetch_bytes;
+
+/// Preprocesses and compiles the shader baked in at `shader_path`, with
+/// `defines` injected
+///
 /// Note: `shader_path` must be relative to this crate's `src` directory
 pub fn preprocess_and_compile_shader(
 	context: &WebGl2RenderingContext,
 	shader_type: u32,
-	shader_source: &str,
+	shader_path: &str,
+	defines: &[(&str, String)],
 ) -> Result<WebGlShader, String> {
-	let preprocessed = preprocess_shader(shader_source);
-	compile_shader(context, shader_type, preprocessed.as_str())
+	let preprocessed = preprocess_shader(shader_path, defines, &load_shader)?;
+	compile_shader(context, shader_type, &preprocessed)
 }
 
-fn compile_shader(
+/// Compiles a preprocessed shader. If it fails, the info log points at the
+/// files and lines the errors came from.
+pub fn compile_shader(
 	context: &WebGl2RenderingContext,
 	shader_type: u32,
-	shader_source: &str,
+	shader: &PreprocessedShader,
 ) -> Result<WebGlShader, String> {
-	let shader = context
+	let compiled = context
 		.create_shader(shader_type)
 		.ok_or_else(|| String::from("Unable to create shader object"))?;
 
-	context.shader_source(&shader, shader_source);
-	context.compile_shader(&shader);
+	context.shader_source(&compiled, &shader.source);
+	context.compile_shader(&compiled);
 
 	if context
-		.get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
+		.get_shader_parameter(&compiled, WebGl2RenderingContext::COMPILE_STATUS)
 		.as_bool()
 		.unwrap_or(false)
 	{
-		Ok(shader)
+		Ok(compiled)
 	} else {
-		Err(context
-			.get_shader_info_log(&shader)
-			.unwrap_or_else(|| String::from("Unknown error creating shader")))
+		let info_log = context
+			.get_shader_info_log(&compiled)
+			.unwrap_or_else(|| String::from("Unknown error creating shader"));
+		context.delete_shader(Some(&compiled));
+		Err(shader.map_info_log(&info_log))
 	}
 }
 
@@ -51,22 +64,66 @@ pub fn link_program(
 	context.attach_shader(&program, frag_shader);
 	context.link_program(&program);
 
-	if context
-		.get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
-		.as_bool()
-		.unwrap_or(false)
-	{
+	if is_linked(context, &program) {
 		Ok(program)
 	} else {
-		Err(context
+		let info_log = context
 			.get_program_info_log(&program)
-			.unwrap_or_else(|| String::from("Unknown error creating program object")))
+			.unwrap_or_else(|| String::from("Unknown error creating program object"));
+		context.delete_program(Some(&program));
+		Err(info_log)
 	}
 }
 
-const INCLUDE_STRING_MATCH: &str = r#"#include <([a-zA-Z0-9\.\-\_/]+)>"#;
-#[allow(dead_code)]
-const LINE_COMMENT_MATCH: &str = r#"//"#;
+/// Links `program` again from new shaders, which replace and delete its old
+/// ones. The shaders are linked on their own first, so `program` is left as
+/// it was if they don't link.
+#[cfg(debug_assertions)]
+pub fn relink_program(
+	context: &WebGl2RenderingContext,
+	program: &WebGlProgram,
+	vert_shader: WebGlShader,
+	frag_shader: WebGlShader,
+) -> Result<(), String> {
+	match link_program(context, &vert_shader, &frag_shader) {
+		Ok(trial) => context.delete_program(Some(&trial)),
+		Err(info_log) => {
+			context.delete_shader(Some(&vert_shader));
+			context.delete_shader(Some(&frag_shader));
+			return Err(info_log);
+		}
+	}
+
+	if let Some(old_shaders) = context.get_attached_shaders(program) {
+		for old_shader in old_shaders.iter() {
+			let old_shader = WebGlShader::from(old_shader);
+			context.detach_shader(program, &old_shader);
+			context.delete_shader(Some(&old_shader));
+		}
+	}
+	context.attach_shader(program, &vert_shader);
+	context.attach_shader(program, &frag_shader);
+	context.link_program(program);
+
+	if is_linked(context, program) {
+		Ok(())
+	} else {
+		Err(context
+			.get_program_info_log(program)
+			.unwrap_or_else(|| String::from("Unknown error relinking program object")))
+	}
+}
+
+fn is_linked(context: &WebGl2RenderingContext, program: &WebGlProgram) -> bool {
+	context
+		.get_program_parameter(program, WebGl2RenderingContext::LINK_STATUS)
+		.as_bool()
+		.unwrap_or(false)
+}
+
+const INCLUDE_STRING_MATCH: &str = r#"^\s*#include <([a-zA-Z0-9\.\-\_/]+)>"#;
+/// A line of an info log, e.g. `ERROR: 0:12: 'x' : undeclared identifier`
+const INFO_LOG_LINE_MATCH: &str = r#"(?m)^(ERROR|WARNING): \d+:(\d+):"#;
 
 macro_rules! include_strs {
 	($($relative_to_src:expr),+ $(,)?) => {
@@ -77,72 +134,194 @@ macro_rules! include_strs {
 }
 
 const PREPROCESSABLE_SHADERS: Map<&str, &str> = include_strs![
+	"application/shaders/camera.glsl",
 	"application/shaders/channels.glsl",
 	"application/shaders/color.glsl",
+	"application/shaders/colormap.glsl",
+	"application/shaders/countries.glsl",
+	"application/shaders/data.glsl",
+	"application/shaders/data_sphere.vert",
+	"application/shaders/direct_mesh.vert",
+	"application/shaders/layers.glsl",
+	"application/shaders/lighting.glsl",
+	"application/shaders/passthrough.frag",
+	"application/shaders/planet.frag",
+	"application/shaders/planet.vert",
 	"application/shaders/pointmapping.glsl",
 	"application/shaders/math.glsl",
+	"application/shaders/tiles.glsl",
 ];
 
-fn load_shader(source_path: &str) -> &str {
+/// The baked in source of `source_path`
+pub fn load_shader(source_path: &str) -> Result<&'static str, String> {
 	PREPROCESSABLE_SHADERS
 		.get(source_path)
-		.expect(format!("Shader {} was not listed for preprocessing", source_path).as_str())
-}
-
-fn preprocess_shader(shader_source: &str) -> String {
-	let with_includes = fill_includes(shader_source);
-	with_includes
-}
-
-fn fill_includes(shader_source: &str) -> String {
-	let mut filled_source = shader_source.to_owned();
-	let include_str_regex = regex::Regex::new(INCLUDE_STRING_MATCH).unwrap();
-	loop {
-		if let Some(include_line) = include_str_regex.find(filled_source.as_str()) {
-			if !is_match_commented(include_line, filled_source.as_str()) {
-				let file_name = {
-					let captures = include_str_regex.captures(include_line.as_str()).expect(
-						format!(
-							"Parsing error for include {}-{}: {}",
-							include_line.start(),
-							include_line.end(),
-							include_line.as_str()
-						)
-						.as_str(),
-					);
-					assert_eq!(captures.len(), 2);
-					captures.index(1).to_owned()
-				};
-				println!("{include_line:?}, {file_name}");
-
-				let include_file_contents = load_shader(file_name.as_str());
-				let processed_include = preprocess_shader(include_file_contents);
-				filled_source.replace_range(
-					include_line.start()..include_line.end(),
-					processed_include.as_str(),
-				);
-			}
-		} else {
-			break;
+		.copied()
+		.ok_or_else(|| format!("Shader {source_path} was not listed for preprocessing"))
+}
+
+/// Where a line of a preprocessed shader came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+	pub path: String,
+	/// From 1
+	pub line: usize,
+}
+
+/// Where each line of a preprocessed shader came from, so errors in it can be
+/// traced back to the files that were filled in
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+	locations: Vec<SourceLocation>,
+}
+
+impl SourceMap {
+	/// Where `line` of the preprocessed shader, from 1, came from
+	pub fn location(&self, line: usize) -> Option<&SourceLocation> {
+		line.checked_sub(1).and_then(|index| self.locations.get(index))
+	}
+
+	/// Points the errors in a compiler's info log at the files and lines they
+	/// came from, rather than at the preprocessed source
+	pub fn map_info_log(&self, info_log: &str) -> String {
+		let line_regex = Regex::new(INFO_LOG_LINE_MATCH).unwrap();
+		line_regex
+			.replace_all(info_log, |captures: &regex::Captures| {
+				let location = captures[2].parse().ok().and_then(|line| self.location(line));
+				match location {
+					Some(SourceLocation { path, line }) => {
+						format!("{}: {path}:{line}:", &captures[1])
+					}
+					None => captures[0].to_owned(),
+				}
+			})
+			.into_owned()
+	}
+}
+
+/// A shader with its defines and includes filled in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreprocessedShader {
+	pub source: String,
+	pub source_map: SourceMap,
+}
+
+impl PreprocessedShader {
+	pub fn map_info_log(&self, info_log: &str) -> String { self.source_map.map_info_log(info_log) }
+}
+
+/// What injected defines are mapped to, since they don't come from a file
+pub const DEFINES_PATH: &str = "<defines>";
+
+/// Fills in the shader at `shader_path`, with every source coming from
+/// `load`. Each `(name, value)` of `defines` is defined right after
+/// `#version`, and each file is only included the first time.
+pub fn preprocess_shader<'a>(
+	shader_path: &str,
+	defines: &[(&str, String)],
+	load: &impl Fn(&str) -> Result<&'a str, String>,
+) -> Result<PreprocessedShader, String> {
+	let mut lines = Vec::new();
+	let mut included = HashSet::from([shader_path.to_owned()]);
+	fill_includes(shader_path, load, &mut included, &mut lines)?;
+
+	// `#version` has to come before anything else
+	let after_version = match lines.first() {
+		Some((line, _)) if line.trim_start().starts_with("#version") => 1,
+		_ => 0,
+	};
+	let define_lines = defines.iter().enumerate().map(|(index, (name, value))| {
+		let location = SourceLocation { path: DEFINES_PATH.to_owned(), line: index + 1 };
+		(format!("#define {name} {value}"), location)
+	});
+	lines.splice(after_version..after_version, define_lines);
+
+	let (source_lines, locations): (Vec<String>, _) = lines.into_iter().unzip();
+	let source = source_lines.into_iter().map(|line| line + "\n").collect();
+	Ok(PreprocessedShader { source, source_map: SourceMap { locations } })
+}
+
+/// Appends the lines of the shader at `shader_path`, filling in its includes
+/// that aren't already `included`
+fn fill_includes<'a>(
+	shader_path: &str,
+	load: &impl Fn(&str) -> Result<&'a str, String>,
+	included: &mut HashSet<String>,
+	lines: &mut Vec<(String, SourceLocation)>,
+) -> Result<(), String> {
+	let include_regex = Regex::new(INCLUDE_STRING_MATCH).unwrap();
+	for (index, line) in load(shader_path)?.lines().enumerate() {
+		let location = SourceLocation { path: shader_path.to_owned(), line: index + 1 };
+		// Commented out includes don't match, since they don't start the line
+		let Some(captures) = include_regex.captures(line) else {
+			lines.push((line.to_owned(), location));
+			continue;
+		};
+
+		let include_path = &captures[1];
+		if included.insert(include_path.to_owned()) {
+			fill_includes(include_path, load, included, lines)
+				.map_err(|e| format!("{e}\n\tincluded from {}:{}", location.path, location.line))?;
 		}
 	}
+	Ok(())
+}
 
-	filled_source
+/// The paths a shader source includes
+#[cfg(any(debug_assertions, test))]
+fn includes_of(shader_source: &str) -> impl Iterator<Item = String> + '_ {
+	let include_regex = Regex::new(INCLUDE_STRING_MATCH).unwrap();
+	shader_source
+		.lines()
+		.filter_map(move |line| include_regex.captures(line).map(|captures| captures[1].to_owned()))
 }
 
-fn is_match_commented(_regex_match: regex::Match, _full_source: &str) -> bool {
-	// TODO
-	false
+/// Fetches the shaders at `shader_paths` and everything they include, from
+/// under `base_url`, so they can be changed without rebuilding
+#[cfg(debug_assertions)]
+pub async fn fetch_shader_sources(
+	base_url: &str,
+	shader_paths: &[&str],
+) -> Result<HashMap<String, String>, String> {
+	let mut sources = HashMap::new();
+	let mut pending: Vec<String> = shader_paths.iter().map(|&path| path.to_owned()).collect();
+	while let Some(path) = pending.pop() {
+		if sources.contains_key(&path) {
+			continue;
+		}
+		let bytes =
+			fetch_bytes(&format!("{base_url}{path}")).await.map_err(|e| format!("{e:?}"))?;
+		let source = String::from_utf8(bytes).map_err(|e| format!("{path} isn't UTF-8: {e}"))?;
+		pending.extend(includes_of(&source));
+		sources.insert(path, source);
+	}
+	Ok(sources)
 }
 
 #[cfg(test)]
 mod tests {
+	use std::collections::HashMap;
+
 	use super::*;
 
+	fn preprocess(
+		sources: &[(&'static str, &'static str)],
+		defines: &[(&str, String)],
+	) -> Result<PreprocessedShader, String> {
+		let sources: HashMap<_, _> = sources.iter().copied().collect();
+		let load =
+			|path: &str| sources.get(path).copied().ok_or_else(|| format!("No shader {path}"));
+		preprocess_shader("main.frag", defines, &load)
+	}
+
+	fn location(path: &str, line: usize) -> SourceLocation {
+		SourceLocation { path: path.to_owned(), line }
+	}
+
 	#[test]
 	fn basic() {
-		let result = preprocess_shader(include_str!("../application/shaders/data_sphere.vert"));
-		println!("{}", result);
+		let result = preprocess_shader("application/shaders/data_sphere.vert", &[], &load_shader);
+		println!("{}", result.unwrap().source);
 	}
 
 	#[test]
@@ -150,4 +329,120 @@ mod tests {
 		let shader_map = include_strs!("application/shaders/pointmapping.glsl");
 		println!("{:?}", shader_map);
 	}
+
+	#[test]
+	fn fills_includes() {
+		let shader = preprocess(
+			&[
+				("main.frag", "#version 300 es\n#include <common.glsl>\nvoid main() {}"),
+				("common.glsl", "  #include <math.glsl>\nfloat a;"),
+				("math.glsl", "float pi;"),
+			],
+			&[],
+		)
+		.unwrap();
+		assert_eq!(shader.source, "#version 300 es\nfloat pi;\nfloat a;\nvoid main() {}\n");
+		assert_eq!(shader.source_map.location(2), Some(&location("math.glsl", 1)));
+		assert_eq!(shader.source_map.location(3), Some(&location("common.glsl", 2)));
+		assert_eq!(shader.source_map.location(4), Some(&location("main.frag", 3)));
+		assert_eq!(shader.source_map.location(5), None);
+		assert_eq!(shader.source_map.location(0), None);
+	}
+
+	#[test]
+	fn skips_commented_includes() {
+		let shader =
+			preprocess(&[("main.frag", "// #include <missing.glsl>\nvoid main() {}")], &[])
+				.unwrap();
+		assert_eq!(shader.source, "// #include <missing.glsl>\nvoid main() {}\n");
+		assert_eq!(includes_of("// #include <missing.glsl>").count(), 0);
+	}
+
+	#[test]
+	fn includes_each_file_once() {
+		// Both layers include the same helpers, and the helpers include the
+		// main shader back
+		let shader = preprocess(
+			&[
+				("main.frag", "#include <a.glsl>\n#include <b.glsl>\n#include <a.glsl>"),
+				("a.glsl", "#include <helpers.glsl>\nfloat a;"),
+				("b.glsl", "#include <helpers.glsl>\nfloat b;"),
+				("helpers.glsl", "#include <main.frag>\nfloat helper;"),
+			],
+			&[],
+		)
+		.unwrap();
+		assert_eq!(shader.source, "float helper;\nfloat a;\nfloat b;\n");
+		assert_eq!(shader.source_map.location(3), Some(&location("b.glsl", 2)));
+	}
+
+	#[test]
+	fn injects_defines_after_the_version() {
+		let defines = [("NUM_CHANNELS_IN_MAP", 4.to_string()), ("SCALE", "0.5".to_owned())];
+		let shader = preprocess(
+			&[(
+				"main.frag",
+				"#version 300 es\nprecision highp float;\nint n = NUM_CHANNELS_IN_MAP;",
+			)],
+			&defines,
+		)
+		.unwrap();
+		assert_eq!(
+			shader.source,
+			"#version 300 es\n#define NUM_CHANNELS_IN_MAP 4\n#define SCALE 0.5\nprecision highp \
+			 float;\nint n = NUM_CHANNELS_IN_MAP;\n"
+		);
+		assert_eq!(shader.source_map.location(3), Some(&location(DEFINES_PATH, 2)));
+		assert_eq!(shader.source_map.location(4), Some(&location("main.frag", 2)));
+
+		// At the very top without a version
+		let shader = preprocess(&[("main.frag", "float a;")], &defines).unwrap();
+		assert!(shader.source.starts_with("#define NUM_CHANNELS_IN_MAP 4\n"));
+		assert_eq!(shader.source_map.location(3), Some(&location("main.frag", 1)));
+	}
+
+	#[test]
+	fn reports_missing_includes() {
+		let error = preprocess(
+			&[("main.frag", "#version 300 es\n#include <a.glsl>"), ("a.glsl", "#include <b.glsl>")],
+			&[],
+		)
+		.unwrap_err();
+		assert_eq!(
+			error,
+			"No shader b.glsl\n\tincluded from a.glsl:1\n\tincluded from main.frag:2"
+		);
+	}
+
+	#[test]
+	fn maps_errors_to_included_files() {
+		let shader = preprocess(
+			&[
+				("main.frag", "#version 300 es\n#include <common.glsl>\nvoid main() {}"),
+				("common.glsl", "float a;\nfloat b;"),
+			],
+			&[("SCALE", "2".to_owned())],
+		)
+		.unwrap();
+		assert_eq!(
+			includes_of("#include <common.glsl>\nfloat a;").collect::<Vec<_>>(),
+			["common.glsl"]
+		);
+
+		let info_log = "ERROR: 0:4: 'b' : redefinition\nERROR: 0:5: '' : syntax error\n\
+		                WARNING: 0:2: 'SCALE' : unused\nERROR: 0:99: '' : past the end\n";
+		assert_eq!(
+			shader.map_info_log(info_log),
+			"ERROR: common.glsl:2: 'b' : redefinition\nERROR: main.frag:3: '' : syntax error\n\
+			 WARNING: <defines>:1: 'SCALE' : unused\nERROR: 0:99: '' : past the end\n"
+		);
+	}
+
+	#[test]
+	fn baked_shaders_preprocess() {
+		for path in PREPROCESSABLE_SHADERS.keys() {
+			let shader = preprocess_shader(path, &[], &load_shader).unwrap();
+			assert!(!shader.source.contains("#include"), "{path} has an include left");
+		}
+	}
 }
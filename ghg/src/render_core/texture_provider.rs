@@ -1,23 +1,136 @@
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use web_sys::WebGl2RenderingContext;
 
 use crate::utils::prelude::*;
 
-#[derive(Clone, Debug, Default)]
+/// Hands out texture units, lowest free first, and takes each back once its
+/// `TextureUnit` is dropped
+#[derive(Clone, Default)]
 pub struct TextureProvider {
-	next_index: Arc<AtomicU32>,
+	pool: Rc<RefCell<UnitPool>>,
+}
+
+#[derive(Default)]
+struct UnitPool {
+	in_use: BTreeSet<u32>,
+	/// Called with each unit as it's given back
+	on_release: Vec<Rc<dyn Fn(u32)>>,
 }
 
 impl TextureProvider {
+	// WebGL2 minimum number frag textures required on all platforms
 	const MAX_TEXTURES: u32 = 16;
 
-	// WebGL2 minimum number frag textures required on all platforms
+	pub fn take(&self) -> TextureUnit {
+		let mut pool = self.pool.borrow_mut();
+		let index = (0..).find(|index| !pool.in_use.contains(index)).expect("Ran out of u32s");
+		if index >= Self::MAX_TEXTURES {
+			ghg_error!("Cannot guarantee so many textures! Allocated {}", pool.in_use.len() + 1);
+		}
+		pool.in_use.insert(index);
+		TextureUnit { index, pool: self.pool.clone() }
+	}
+
+	/// Calls `on_release` with each unit's index as it's given back
+	pub fn on_release(&self, on_release: impl Fn(u32) + 'static) {
+		self.pool.borrow_mut().on_release.push(Rc::new(on_release));
+	}
+}
+
+impl Debug for TextureProvider {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("TextureProvider").field("in_use", &self.pool.borrow().in_use).finish()
+	}
+}
+
+/// A texture unit, given back to its `TextureProvider` when dropped
+pub struct TextureUnit {
+	index: u32,
+	pool: Rc<RefCell<UnitPool>>,
+}
+
+impl TextureUnit {
+	/// What sampler uniforms are set to
+	pub fn index(&self) -> u32 { self.index }
 
-	pub fn take(&mut self) -> u32 {
-		let result = self.next_index.fetch_add(1, Ordering::Relaxed);
-		if result >= Self::MAX_TEXTURES {
-			ghg_error!("Cannot guarantee so many textures! Allocated {}", result + 1);
+	/// What `active_texture` takes
+	pub fn gl_unit(&self) -> u32 { WebGl2RenderingContext::TEXTURE0 + self.index }
+}
+
+impl Debug for TextureUnit {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.debug_tuple("TextureUnit").field(&self.index).finish()
+	}
+}
+
+impl Drop for TextureUnit {
+	fn drop(&mut self) {
+		// Called once the pool is free again, so they can take or release units
+		let on_release = {
+			let mut pool = self.pool.borrow_mut();
+			pool.in_use.remove(&self.index);
+			pool.on_release.clone()
+		};
+		for on_release in on_release {
+			on_release(self.index);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reuses_dropped_units() {
+		let provider = TextureProvider::default();
+		let released = Rc::new(RefCell::new(Vec::new()));
+		provider.on_release({
+			let released = released.clone();
+			move |index| released.borrow_mut().push(index)
+		});
+
+		let first = provider.take();
+		let second = provider.take();
+		let third = provider.take();
+		assert_eq!((first.index(), second.index(), third.index()), (0, 1, 2));
+		assert_eq!(second.gl_unit(), WebGl2RenderingContext::TEXTURE1);
+
+		drop(second);
+		assert_eq!(*released.borrow(), [1]);
+		assert_eq!(provider.take().index(), 1);
+
+		// Taking and dropping over and over never runs out
+		for _ in 0..100 {
+			let unit = provider.take();
+			assert_eq!(unit.index(), 1);
 		}
-		result
+		drop((first, third));
+		assert_eq!(released.borrow().len(), 104);
+		assert_eq!(provider.take().index(), 0);
+	}
+
+	#[test]
+	fn takes_units_while_releasing() {
+		let provider = TextureProvider::default();
+		let replacement = Rc::new(RefCell::new(None));
+		provider.on_release({
+			let (provider, replacement) = (provider.clone(), replacement.clone());
+			move |_| {
+				if replacement.borrow().is_none() {
+					let unit = provider.take();
+					replacement.replace(Some(unit));
+				}
+			}
+		});
+
+		let first = provider.take();
+		drop(first);
+		assert_eq!(replacement.borrow().as_ref().map(TextureUnit::index), Some(0));
+		assert_eq!(provider.take().index(), 1);
 	}
 }
@@ -0,0 +1,51 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlTexture};
+
+use crate::render_core::texture_provider::{TextureProvider, TextureUnit};
+
+/// Hands out texture units, and keeps the texture loaded into each, so it's
+/// deleted from the GPU once it's replaced or its unit is given back
+#[derive(Clone)]
+pub struct TextureRegistry {
+	context: WebGl2RenderingContext,
+	provider: TextureProvider,
+	textures: Rc<RefCell<HashMap<u32, WebGlTexture>>>,
+}
+
+impl TextureRegistry {
+	pub fn new(context: WebGl2RenderingContext) -> Self {
+		let provider = TextureProvider::default();
+		let textures: Rc<RefCell<HashMap<u32, WebGlTexture>>> = Default::default();
+		provider.on_release({
+			let context = context.clone();
+			let textures = textures.clone();
+			move |index| {
+				if let Some(texture) = textures.borrow_mut().remove(&index) {
+					context.delete_texture(Some(&texture));
+				}
+			}
+		});
+		Self { context, provider, textures }
+	}
+
+	pub fn context(&self) -> &WebGl2RenderingContext { &self.context }
+
+	pub fn take(&self) -> TextureUnit { self.provider.take() }
+
+	/// A new texture, bound to `unit` as `target`, deleting whatever texture
+	/// was in it before
+	pub fn create(&self, unit: &TextureUnit, target: u32) -> Result<WebGlTexture, JsValue> {
+		let texture = self.context.create_texture().ok_or("no texture")?;
+		self.context.active_texture(unit.gl_unit());
+		self.context.bind_texture(target, Some(&texture));
+
+		if let Some(replaced) = self.textures.borrow_mut().insert(unit.index(), texture.clone()) {
+			self.context.delete_texture(Some(&replaced));
+		}
+		Ok(texture)
+	}
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use ghg_data_core::tiles::TileSet;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct TileKey {
+	pub level: usize,
+	pub x: u32,
+	pub y: u32,
+}
+
+impl TileKey {
+	pub fn new(level: usize, x: u32, y: u32) -> Self { Self { level, x, y } }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Resident {
+	layer: u32,
+	last_used: u64,
+}
+
+/// Bookkeeping for tiles of a `TileSet` held in a fixed number of texture
+/// array layers. Tiles not used in the current frame are evicted least recently
+/// used first, except the coarsest level, which is the fallback for everything
+/// else and is never evicted.
+///
+/// The page table maps each tile to its layer (or -1), one row per tile row,
+/// with levels stacked from 0 downwards. It's as wide as level 0 has columns.
+pub struct TileCache {
+	tile_set: TileSet,
+	resident: HashMap<TileKey, Resident>,
+	free_layers: Vec<u32>,
+	frame: u64,
+	page_table: Vec<i32>,
+	row_offsets: Vec<u32>,
+	page_table_changed: bool,
+}
+
+impl TileCache {
+	pub fn new(tile_set: TileSet, layers: u32) -> Self {
+		let row_offsets = tile_set
+			.levels
+			.iter()
+			.scan(0, |offset, level| {
+				let level_offset = *offset;
+				*offset += level.rows;
+				Some(level_offset)
+			})
+			.collect();
+		let page_table = vec![-1; Self::page_table_size(&tile_set)];
+
+		Self {
+			tile_set,
+			resident: HashMap::new(),
+			free_layers: (0..layers).rev().collect(),
+			frame: 0,
+			page_table,
+			row_offsets,
+			page_table_changed: true,
+		}
+	}
+
+	fn page_table_size(tile_set: &TileSet) -> usize {
+		let (width, height) = Self::page_table_dimensions(tile_set);
+		width as usize * height as usize
+	}
+
+	fn page_table_dimensions(tile_set: &TileSet) -> (u32, u32) {
+		let width = tile_set.levels.iter().map(|level| level.columns).max().unwrap_or(0);
+		let height = tile_set.levels.iter().map(|level| level.rows).sum();
+		(width, height)
+	}
+
+	pub fn tile_set(&self) -> &TileSet { &self.tile_set }
+
+	pub fn coarsest_level(&self) -> usize { self.tile_set.levels.len().saturating_sub(1) }
+
+	/// Starts a new frame; tiles `touch`ed from now on are protected from
+	/// eviction until the next one
+	pub fn next_frame(&mut self) { self.frame += 1; }
+
+	pub fn contains(&self, key: &TileKey) -> bool { self.resident.contains_key(key) }
+
+	/// Marks `key` as used this frame. Returns false if it isn't resident.
+	pub fn touch(&mut self, key: &TileKey) -> bool {
+		match self.resident.get_mut(key) {
+			Some(resident) => {
+				resident.last_used = self.frame;
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Assigns `key` a layer, evicting the least recently used tile if there
+	/// are none free. Returns `None` if every layer is in use this frame or
+	/// pinned.
+	pub fn insert(&mut self, key: TileKey) -> Option<u32> {
+		if let Some(resident) = self.resident.get_mut(&key) {
+			resident.last_used = self.frame;
+			return Some(resident.layer);
+		}
+
+		let layer = match self.free_layers.pop() {
+			Some(layer) => layer,
+			None => self.evict()?,
+		};
+
+		self.resident.insert(key, Resident { layer, last_used: self.frame });
+		self.set_page(&key, layer as i32);
+		Some(layer)
+	}
+
+	fn evict(&mut self) -> Option<u32> {
+		let coarsest_level = self.coarsest_level();
+		let (&key, resident) = self
+			.resident
+			.iter()
+			.filter(|(key, resident)| {
+				key.level != coarsest_level && resident.last_used < self.frame
+			})
+			.min_by_key(|(key, resident)| (resident.last_used, **key))?;
+		let layer = resident.layer;
+
+		self.resident.remove(&key);
+		self.set_page(&key, -1);
+		Some(layer)
+	}
+
+	fn set_page(&mut self, key: &TileKey, value: i32) {
+		let (width, _) = Self::page_table_dimensions(&self.tile_set);
+		let row = self.row_offsets[key.level] + key.y;
+		self.page_table[(row * width + key.x) as usize] = value;
+		self.page_table_changed = true;
+	}
+
+	/// The layer of each tile, or -1, as `(width, height, entries)`
+	pub fn page_table(&self) -> (u32, u32, &[i32]) {
+		let (width, height) = Self::page_table_dimensions(&self.tile_set);
+		(width, height, &self.page_table)
+	}
+
+	/// Whether the page table changed since this was last called
+	pub fn take_page_table_changed(&mut self) -> bool {
+		std::mem::take(&mut self.page_table_changed)
+	}
+
+	/// Whether every tile of the coarsest level is resident, so anything can
+	/// be sampled
+	pub fn has_fallback(&self) -> bool {
+		let coarsest_level = self.coarsest_level();
+		self.tile_set.level(coarsest_level).is_some_and(|level| {
+			(0..level.columns).all(|x| {
+				(0..level.rows).all(|y| self.contains(&TileKey::new(coarsest_level, x, y)))
+			})
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ghg_data_core::tiles::TileLevel;
+
+	use super::*;
+
+	fn tile_set() -> TileSet {
+		TileSet {
+			tile_size: 2,
+			channels: 3,
+			levels: vec![
+				TileLevel::new(0, 8, 4, 2),
+				TileLevel::new(1, 4, 2, 2),
+				TileLevel::new(2, 2, 1, 2),
+			],
+		}
+	}
+
+	#[test]
+	fn page_table_stacks_levels() {
+		let mut cache = TileCache::new(tile_set(), 4);
+		assert_eq!(cache.insert(TileKey::new(1, 1, 0)), Some(0));
+		assert_eq!(cache.insert(TileKey::new(2, 0, 0)), Some(1));
+
+		let (width, height, entries) = cache.page_table();
+		assert_eq!((width, height), (4, 4));
+		assert_eq!(entries[2 * 4 + 1], 0);
+		assert_eq!(entries[3 * 4], 1);
+		assert_eq!(entries.iter().filter(|&&entry| entry >= 0).count(), 2);
+	}
+
+	#[test]
+	fn evicts_least_recently_used() {
+		let mut cache = TileCache::new(tile_set(), 2);
+		cache.insert(TileKey::new(0, 0, 0));
+		cache.next_frame();
+		cache.insert(TileKey::new(0, 1, 0));
+		cache.next_frame();
+
+		assert_eq!(cache.insert(TileKey::new(0, 2, 0)), Some(0));
+		assert!(!cache.contains(&TileKey::new(0, 0, 0)));
+		assert!(cache.contains(&TileKey::new(0, 1, 0)));
+		assert_eq!(cache.page_table().2[0], -1);
+	}
+
+	#[test]
+	fn keeps_tiles_used_this_frame() {
+		let mut cache = TileCache::new(tile_set(), 1);
+		cache.insert(TileKey::new(0, 0, 0));
+		cache.next_frame();
+		assert!(cache.touch(&TileKey::new(0, 0, 0)));
+
+		assert_eq!(cache.insert(TileKey::new(0, 1, 0)), None);
+		assert!(cache.contains(&TileKey::new(0, 0, 0)));
+	}
+
+	#[test]
+	fn never_evicts_coarsest_level() {
+		let mut cache = TileCache::new(tile_set(), 2);
+		cache.insert(TileKey::new(2, 0, 0));
+		cache.next_frame();
+		cache.insert(TileKey::new(0, 0, 0));
+		cache.next_frame();
+
+		assert_eq!(cache.insert(TileKey::new(0, 1, 0)), Some(1));
+		assert!(cache.has_fallback());
+	}
+
+	#[test]
+	fn page_table_changes_are_reported_once() {
+		let mut cache = TileCache::new(tile_set(), 2);
+		assert!(cache.take_page_table_changed());
+		assert!(!cache.take_page_table_changed());
+		cache.insert(TileKey::new(0, 0, 0));
+		assert!(cache.take_page_table_changed());
+	}
+}
@@ -0,0 +1,132 @@
+use std::marker::PhantomData;
+
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlTexture};
+
+use crate::render_core::image::LoadableImageType;
+use crate::render_core::texture_provider::TextureUnit;
+use crate::render_core::texture_registry::TextureRegistry;
+
+/// The GPU side of a `TileCache`: a texture array with one tile per layer, and
+/// an integer texture holding the page table
+pub struct TileTexture<T: LoadableImageType> {
+	context: WebGl2RenderingContext,
+	tiles: WebGlTexture,
+	tiles_unit: u32,
+	pages: WebGlTexture,
+	pages_unit: u32,
+	phantom_value: PhantomData<T>,
+}
+
+impl<T: LoadableImageType> TileTexture<T> {
+	/// The units must outlive the texture, since their textures are deleted
+	/// once they're given back
+	pub fn new(
+		textures: &TextureRegistry,
+		tiles_texture_unit: &TextureUnit,
+		pages_texture_unit: &TextureUnit,
+		tile_size: u32,
+		layers: u32,
+	) -> Result<Self, JsValue> {
+		let context = textures.context().clone();
+		let tiles_unit = tiles_texture_unit.gl_unit();
+		let pages_unit = pages_texture_unit.gl_unit();
+
+		let tiles =
+			textures.create(tiles_texture_unit, WebGl2RenderingContext::TEXTURE_2D_ARRAY)?;
+		set_filters(
+			&context,
+			WebGl2RenderingContext::TEXTURE_2D_ARRAY,
+			WebGl2RenderingContext::LINEAR,
+		);
+		context.tex_image_3d_with_opt_u8_array(
+			WebGl2RenderingContext::TEXTURE_2D_ARRAY,
+			0,
+			T::texture_internal_format() as i32,
+			tile_size as i32,
+			tile_size as i32,
+			layers as i32,
+			0,
+			T::texture_format(),
+			T::texture_type(),
+			None,
+		)?;
+
+		let pages = textures.create(pages_texture_unit, WebGl2RenderingContext::TEXTURE_2D)?;
+		// Integer textures can't be filtered
+		set_filters(&context, WebGl2RenderingContext::TEXTURE_2D, WebGl2RenderingContext::NEAREST);
+
+		Ok(Self { context, tiles, tiles_unit, pages, pages_unit, phantom_value: PhantomData })
+	}
+
+	/// Copies a decoded tile into `layer`, starting at texel (0, 0). Partial
+	/// tiles leave the rest of the layer as it was.
+	pub fn upload_tile(
+		&self,
+		layer: u32,
+		pixels: &[u8],
+		width: u32,
+		height: u32,
+	) -> Result<(), JsValue> {
+		self.context.active_texture(self.tiles_unit);
+		self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D_ARRAY, Some(&self.tiles));
+		// Partial tiles can have rows that aren't a multiple of 4 bytes
+		self.context.pixel_storei(WebGl2RenderingContext::UNPACK_ALIGNMENT, 1);
+		let result = self.context.tex_sub_image_3d_with_opt_u8_array(
+			WebGl2RenderingContext::TEXTURE_2D_ARRAY,
+			0,
+			0,
+			0,
+			layer as i32,
+			width as i32,
+			height as i32,
+			1,
+			T::texture_format(),
+			T::texture_type(),
+			Some(pixels),
+		);
+		self.context.pixel_storei(WebGl2RenderingContext::UNPACK_ALIGNMENT, 4);
+		result
+	}
+
+	pub fn upload_page_table(
+		&self,
+		width: u32,
+		height: u32,
+		entries: &[i32],
+	) -> Result<(), JsValue> {
+		self.context.active_texture(self.pages_unit);
+		self.context.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.pages));
+
+		unsafe {
+			let entries_view = js_sys::Int32Array::view(entries);
+			self.context
+				.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+					WebGl2RenderingContext::TEXTURE_2D,
+					0,
+					WebGl2RenderingContext::R32I as i32,
+					width as i32,
+					height as i32,
+					0,
+					WebGl2RenderingContext::RED_INTEGER,
+					WebGl2RenderingContext::INT,
+					Some(&entries_view),
+				)
+		}
+	}
+}
+
+fn set_filters(context: &WebGl2RenderingContext, target: u32, filter: u32) {
+	context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MIN_FILTER, filter as i32);
+	context.tex_parameteri(target, WebGl2RenderingContext::TEXTURE_MAG_FILTER, filter as i32);
+	context.tex_parameteri(
+		target,
+		WebGl2RenderingContext::TEXTURE_WRAP_S,
+		WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
+	);
+	context.tex_parameteri(
+		target,
+		WebGl2RenderingContext::TEXTURE_WRAP_T,
+		WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
+	);
+}
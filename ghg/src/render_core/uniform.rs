@@ -1,44 +1,68 @@
+use std::any::Any;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::marker::PhantomData;
-use std::ops::DerefMut;
-use std::sync::Mutex;
 
-use lazy_static::lazy_static;
 use paste::paste;
-use web_sys::{WebGl2RenderingContext, WebGlUniformLocation};
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlUniformLocation};
 
 use crate::application::shaders::ShaderContext;
 #[allow(unused_imports)]
 use crate::utils::prelude::*;
 
-#[derive(Clone, Debug, Default)]
-struct UniformValueTracker<T: UniformValue> {
-	uniforms: HashMap<(usize, String), T>,
+/// The values last written to one program's uniforms, by name. Each
+/// `ShaderContext` has its own, so separate viewers never share them.
+#[derive(Default)]
+pub struct UniformValueTracker {
+	uniforms: HashMap<String, Box<dyn SavedValue>>,
 }
 
-impl<T: Clone + Debug + PartialEq + UniformValue> UniformValueTracker<T> {
-	fn update(&mut self, uniform: &Uniform<T>, value: T) -> bool {
-		let key = (uniform.shader_context.id(), uniform.name.clone());
+/// A uniform's last value, which can be written again if its program loses it
+trait SavedValue {
+	fn as_any_mut(&mut self) -> &mut dyn Any;
 
-		if let Some(saved) = self.uniforms.get_mut(&key) {
-			if *saved != value {
-				*saved = value;
-				return true;
-			}
-		} else {
-			self.uniforms.insert(key, value);
-			return true;
-		}
+	#[cfg(debug_assertions)]
+	fn rewrite(&self, name: &str, shader_context: &ShaderContext);
+}
 
-		false
+impl<T: Clone + Debug + PartialEq + UniformValue + 'static> SavedValue for T {
+	fn as_any_mut(&mut self) -> &mut dyn Any { self }
+
+	#[cfg(debug_assertions)]
+	fn rewrite(&self, name: &str, shader_context: &ShaderContext) {
+		Uniform::<T>::new(name, shader_context).write_to_location(self.clone());
 	}
 }
 
-trait GetUniformValueTracker {
-	fn get_uniform_tracker() -> &'static Mutex<UniformValueTracker<Self>>
-	where
-		Self: UniformValue + Sized;
+impl UniformValueTracker {
+	/// Saves `value` for the uniform `name`, returning whether it changed
+	fn update<T: Clone + Debug + PartialEq + UniformValue + 'static>(
+		&mut self,
+		name: &str,
+		value: &T,
+	) -> bool {
+		match self.uniforms.get_mut(name).and_then(|saved| saved.as_any_mut().downcast_mut::<T>()) {
+			Some(saved) if saved == value => false,
+			Some(saved) => {
+				*saved = value.clone();
+				true
+			}
+			None => {
+				self.uniforms.insert(name.to_owned(), Box::new(value.clone()));
+				true
+			}
+		}
+	}
+
+	/// Writes every uniform's last value to `shader_context`'s program again,
+	/// once relinking it has reset them
+	#[cfg(debug_assertions)]
+	pub fn rewrite_all(&self, shader_context: &ShaderContext) {
+		for (name, value) in &self.uniforms {
+			value.rewrite(name, shader_context);
+		}
+	}
 }
 
 /// This provides a simple wrapper type for writing to uniform values.
@@ -53,52 +77,56 @@ trait GetUniformValueTracker {
 pub struct Uniform<T: Debug> {
 	name: String,
 	shader_context: ShaderContext,
-	location: Option<WebGlUniformLocation>,
+	/// Looked up again once the program is relinked, with the generation it
+	/// was looked up in
+	location: RefCell<(u32, Option<WebGlUniformLocation>)>,
 	phantom_value: PhantomData<T>, // Strongly-typed Uniforms are important
 }
 
-impl<T: Clone + Debug + PartialEq + UniformValue> Uniform<T> {
+impl<T: Clone + Debug + PartialEq + UniformValue + 'static> Uniform<T> {
 	pub fn new(name: &str, shader_context: &ShaderContext) -> Self {
 		let location = shader_context.context.get_uniform_location(&shader_context.program, name);
 		Self {
 			name: name.to_owned(),
 			shader_context: shader_context.clone(),
-			location,
+			location: RefCell::new((shader_context.generation(), location)),
 			phantom_value: PhantomData,
 		}
 	}
 
 	pub fn write_unchecked(&self, t: T) {
-		t.write_to_program(&self.shader_context.context, &self.location);
+		self.shader_context.uniform_values().update(&self.name, &t);
+		self.write_to_location(t);
+	}
+
+	fn write_to_location(&self, t: T) {
+		let generation = self.shader_context.generation();
+		let mut location = self.location.borrow_mut();
+		if location.0 != generation {
+			let shader_context = &self.shader_context;
+			*location = (
+				generation,
+				shader_context.context.get_uniform_location(&shader_context.program, &self.name),
+			);
+		}
+		t.write_to_program(&self.shader_context.context, &location.1);
 	}
 }
 
 #[derive(Debug)]
 pub struct SmartUniform<T: Debug + UniformValue + 'static> {
 	uniform: Uniform<T>,
-	uniform_tracker: &'static Mutex<UniformValueTracker<T>>,
 }
 
-impl<T: Clone + Debug + PartialEq + UniformValue + GetUniformValueTracker + 'static>
-	SmartUniform<T>
-{
+impl<T: Clone + Debug + PartialEq + UniformValue + 'static> SmartUniform<T> {
 	pub fn new(name: &str, shader_context: &ShaderContext) -> Self {
-		Self {
-			uniform: Uniform::new(name, shader_context),
-			uniform_tracker: T::get_uniform_tracker(),
-		}
+		Self { uniform: Uniform::new(name, shader_context) }
 	}
 
 	pub fn smart_write(&mut self, t: T) {
-		match self.uniform_tracker.lock() {
-			Ok(mut tracker) => {
-				if tracker.deref_mut().update(&self.uniform, t.clone()) {
-					self.uniform.write_unchecked(t);
-				}
-			}
-			Err(_) => {
-				ghg_error!("Failed to lock uniform tracker: {:?}", self.uniform);
-			}
+		let changed = self.uniform.shader_context.uniform_values().update(&self.uniform.name, &t);
+		if changed {
+			self.uniform.write_to_location(t);
 		}
 	}
 }
@@ -138,7 +166,7 @@ macro_rules! impl_uniform_creator_fns {
 }
 
 macro_rules! impl_smart_uniform_creator_fns {
-    ($type_name:ty, $short_name:ident, $tracker_name:ident) => {
+    ($type_name:ty, $short_name:ident) => {
         paste! {
             #[allow(dead_code)]
             #[doc = "Creates a new `SmartUniform<" [< $short_name:upper _STR >] ">`."]
@@ -157,16 +185,6 @@ macro_rules! impl_smart_uniform_creator_fns {
             }
 
         }
-
-        lazy_static! {
-            static ref $tracker_name: Mutex<UniformValueTracker<$type_name>> = Default::default();
-        }
-
-        impl GetUniformValueTracker for $type_name {
-            fn get_uniform_tracker() -> &'static Mutex<UniformValueTracker<Self>> {
-                &$tracker_name
-            }
-        }
     };
 }
 
@@ -180,9 +198,7 @@ macro_rules! impl_uniform {
         }
 
         impl_uniform_creator_fns!($type_name, $short_name);
-        paste! {
-            impl_smart_uniform_creator_fns!($type_name, $short_name, [< UNIFORM_VALUE_ $short_name >]);
-        }
+        impl_smart_uniform_creator_fns!($type_name, $short_name);
     };
 
     // Self is a primitive type, and its own short name; pass self directly to the OpenGL function call
@@ -207,14 +223,14 @@ macro_rules! impl_uniform {
         }
 
         impl_uniform_creator_fns!($type_name, $short_name);
-        paste! {
-            impl_smart_uniform_creator_fns!($type_name, $short_name, [< UNIFORM_VALUE_ $short_name >]);
-        }
+        impl_smart_uniform_creator_fns!($type_name, $short_name);
     };
 }
 
 impl_uniform!(i32, uniform1i);
 impl_uniform!(f32, uniform1f);
+impl_uniform!(nglm::IVec2, ivec2, uniform2i, self.x, self.y);
+impl_uniform!(nglm::IVec4, ivec4, uniform4i, self.x, self.y, self.z, self.w);
 impl_uniform!(nglm::Vec3, vec3, uniform3f, self.x, self.y, self.z);
 impl_uniform!(nglm::Vec4, vec4, uniform4f, self.x, self.y, self.z, self.w);
 impl_uniform!(nglm::Mat4, mat4, uniform_matrix4fv_with_f32_array, just false, call self.as_slice());
@@ -222,4 +238,230 @@ impl_uniform!(nglm::Mat4, mat4, uniform_matrix4fv_with_f32_array, just false, ca
 // TODO: Uh... This switches column/row. Is that expected?
 impl_uniform!(nglm::Mat4x3, mat4x3, uniform_matrix3x4fv_with_f32_array, just false, call self.as_slice());
 
+/// A `vec4` array, e.g. `uniform vec4 u_values[3]`, written from its first
+/// element
+impl UniformValue for Vec<nglm::Vec4> {
+	fn write_to_program(
+		self,
+		context: &WebGl2RenderingContext,
+		location: &Option<WebGlUniformLocation>,
+	) {
+		let values: Vec<f32> = self.iter().flat_map(|value| value.iter().copied()).collect();
+		context.uniform4fv_with_f32_array(location.as_ref(), &values);
+	}
+}
+impl_uniform_creator_fns!(Vec<nglm::Vec4>, vec4_array);
+impl_smart_uniform_creator_fns!(Vec<nglm::Vec4>, vec4_array);
+
 // TODO: Way more implementations
+
+/// A value that can be laid out in a uniform block, by GLSL's std140 rules
+pub trait Std140 {
+	/// What the value's offset in the block is rounded up to
+	const ALIGN: usize;
+	const SIZE: usize;
+
+	/// Writes the value into `bytes`, which are `SIZE` long
+	fn write_std140(&self, bytes: &mut [u8]);
+}
+
+fn write_floats(floats: &[f32], bytes: &mut [u8]) {
+	for (float, chunk) in floats.iter().zip(bytes.chunks_exact_mut(4)) {
+		chunk.copy_from_slice(&float.to_le_bytes());
+	}
+}
+
+impl Std140 for f32 {
+	const ALIGN: usize = 4;
+	const SIZE: usize = 4;
+
+	fn write_std140(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+}
+
+impl Std140 for i32 {
+	const ALIGN: usize = 4;
+	const SIZE: usize = 4;
+
+	fn write_std140(&self, bytes: &mut [u8]) { bytes.copy_from_slice(&self.to_le_bytes()); }
+}
+
+// A vec3 is aligned like a vec4, but a scalar after it can fill its last slot
+impl Std140 for nglm::Vec3 {
+	const ALIGN: usize = 16;
+	const SIZE: usize = 12;
+
+	fn write_std140(&self, bytes: &mut [u8]) { write_floats(self.as_slice(), bytes); }
+}
+
+impl Std140 for nglm::Vec4 {
+	const ALIGN: usize = 16;
+	const SIZE: usize = 16;
+
+	fn write_std140(&self, bytes: &mut [u8]) { write_floats(self.as_slice(), bytes); }
+}
+
+// Column major, each column a vec4
+impl Std140 for nglm::Mat4 {
+	const ALIGN: usize = 16;
+	const SIZE: usize = 64;
+
+	fn write_std140(&self, bytes: &mut [u8]) { write_floats(self.as_slice(), bytes); }
+}
+
+/// Appends `value` to a std140 block being laid out in `bytes`
+pub fn push_std140<T: Std140>(bytes: &mut Vec<u8>, value: &T) {
+	let offset = bytes.len().next_multiple_of(T::ALIGN);
+	bytes.resize(offset + T::SIZE, 0);
+	value.write_std140(&mut bytes[offset..]);
+}
+
+/// A Rust struct with the layout of a GLSL uniform block. Made with
+/// `uniform_block!`, which lays the fields out in order.
+pub trait UniformBlock: Clone + Debug + PartialEq {
+	/// The block's name in GLSL
+	const BLOCK_NAME: &'static str;
+	/// The binding point the block's buffer is bound to, unique to each block
+	const BINDING: u32;
+
+	fn std140_bytes(&self) -> Vec<u8>;
+}
+
+/// Declares a struct that's written into a `UniformBuffer` as the GLSL uniform
+/// block it's named after. Fields must be declared in the same order as the
+/// block's members.
+///
+/// ```ignore
+/// uniform_block! {
+///     #[block("Camera", binding = 0)]
+///     pub struct CameraBlock {
+///         pub view: nglm::Mat4,
+///         pub position: nglm::Vec3,
+///     }
+/// }
+/// ```
+macro_rules! uniform_block {
+	(
+		#[block($block_name:literal, binding = $binding:literal)]
+		$(#[$meta:meta])*
+		$vis:vis struct $name:ident {
+			$( $(#[$field_meta:meta])* pub $field:ident: $field_type:ty, )+
+		}
+	) => {
+		$(#[$meta])*
+		#[derive(Clone, Debug, PartialEq)]
+		$vis struct $name {
+			$( $(#[$field_meta])* pub $field: $field_type, )+
+		}
+
+		impl $crate::render_core::uniform::UniformBlock for $name {
+			const BLOCK_NAME: &'static str = $block_name;
+			const BINDING: u32 = $binding;
+
+			fn std140_bytes(&self) -> Vec<u8> {
+				let mut bytes = Vec::new();
+				$( $crate::render_core::uniform::push_std140(&mut bytes, &self.$field); )+
+				// Blocks are padded out to a whole vec4
+				bytes.resize(bytes.len().next_multiple_of(16), 0);
+				bytes
+			}
+		}
+	};
+}
+
+pub(crate) use uniform_block;
+
+/// A buffer holding one uniform block, which any number of programs can read
+/// from. Like `SmartUniform`, it's only written when the block changes.
+#[derive(Debug)]
+pub struct UniformBuffer<T: UniformBlock> {
+	context: WebGl2RenderingContext,
+	buffer: WebGlBuffer,
+	written: Option<T>,
+}
+
+impl<T: UniformBlock> UniformBuffer<T> {
+	pub fn new(context: &WebGl2RenderingContext) -> Result<Self, String> {
+		let buffer = context
+			.create_buffer()
+			.ok_or_else(|| format!("Unable to create a buffer for {}", T::BLOCK_NAME))?;
+		Ok(Self { context: context.clone(), buffer, written: None })
+	}
+
+	/// Has `shader_context`'s program read its block from this buffer. Only
+	/// needed once per program.
+	pub fn bind_to(&self, shader_context: &ShaderContext) -> Result<(), String> {
+		shader_context.bind_uniform_block(T::BLOCK_NAME, T::BINDING)
+	}
+
+	/// Binds the buffer to its binding point, for the frame's draws to read
+	pub fn bind(&self) {
+		self.context.bind_buffer_base(
+			WebGl2RenderingContext::UNIFORM_BUFFER,
+			T::BINDING,
+			Some(&self.buffer),
+		);
+	}
+
+	pub fn smart_write(&mut self, block: T) {
+		if self.written.as_ref() == Some(&block) {
+			return;
+		}
+		self.context.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&self.buffer));
+		self.context.buffer_data_with_u8_array(
+			WebGl2RenderingContext::UNIFORM_BUFFER,
+			&block.std140_bytes(),
+			WebGl2RenderingContext::DYNAMIC_DRAW,
+		);
+		self.written = Some(block);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tracks_changes_by_name() {
+		let mut tracker = UniformValueTracker::default();
+
+		assert!(tracker.update("u_month", &1));
+		assert!(!tracker.update("u_month", &1));
+		assert!(tracker.update("u_month", &2));
+		assert!(tracker.update("u_scale", &2.0f32));
+		assert!(!tracker.update("u_scale", &2.0f32));
+	}
+
+	uniform_block! {
+		#[block("Test", binding = 0)]
+		struct TestBlock {
+			pub strength: f32,
+			pub color: nglm::Vec3,
+			pub shininess: f32,
+			pub transform: nglm::Mat4,
+			pub mode: i32,
+		}
+	}
+
+	#[test]
+	fn lays_out_blocks_by_std140() {
+		let block = TestBlock {
+			strength: 1.0,
+			color: nglm::vec3(2.0, 3.0, 4.0),
+			shininess: 5.0,
+			transform: nglm::Mat4::from_fn(|row, column| (column * 4 + row) as f32),
+			mode: 7,
+		};
+		let bytes = block.std140_bytes();
+		let float_at =
+			|offset: usize| f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+		assert_eq!(float_at(0), 1.0);
+		// The vec3 starts on a vec4, and the float after it fills its last slot
+		assert_eq!((float_at(16), float_at(20), float_at(24)), (2.0, 3.0, 4.0));
+		assert_eq!(float_at(28), 5.0);
+		// Column major
+		assert_eq!((float_at(32), float_at(36), float_at(48)), (0.0, 1.0, 4.0));
+		assert_eq!(i32::from_le_bytes(bytes[96..100].try_into().unwrap()), 7);
+		assert_eq!(bytes.len(), 112);
+	}
+}
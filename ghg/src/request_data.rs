@@ -20,6 +20,11 @@ pub async fn fetch_blob(url: &str) -> Result<Blob, JsValue> {
 		resp_value.dyn_into().unwrap()
 	};
 
+	// Otherwise a missing file "succeeds" with the server's error page
+	if !response.ok() {
+		return Err(format!("Fetching {url} failed with status {}", response.status()).into());
+	}
+
 	let blob = JsFuture::from(response.blob()?).await?;
 	assert!(blob.is_instance_of::<Blob>());
 
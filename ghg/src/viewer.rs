@@ -0,0 +1,165 @@
+use ghg_data_core::color_map::ColorScale;
+use js_sys::Function;
+use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
+
+use crate::application::animation_loop::{get_animation_loop, ViewerState};
+use crate::application::data::Dataset;
+use crate::application::events::ViewerEvent;
+use crate::application::layers::{BlendMode, Layer};
+use crate::application::probe::latitude_longitude_to_point;
+use crate::render_core::animation::run_animation_loop;
+use crate::render_core::canvas::get_webgl2_context;
+use crate::render_core::viewport::Viewport;
+use crate::utils::prelude::*;
+
+/// How close to the poles the camera can be turned, in degrees. Any closer and
+/// its up direction can't be worked out.
+const MAX_TARGET_LATITUDE: f32 = 89.0;
+
+/// A handle to a viewer, so the page hosting it can control it and follow what
+/// happens in it. Each viewer has its own canvas and state, so a page can have
+/// several.
+#[wasm_bindgen]
+pub struct GhgViewer {
+	state: ViewerState,
+}
+
+#[wasm_bindgen]
+impl GhgViewer {
+	/// Starts drawing the globe on `canvas`
+	#[wasm_bindgen(constructor)]
+	pub fn new(canvas: HtmlCanvasElement) -> Result<GhgViewer, JsValue> {
+		let context = get_webgl2_context(&canvas).ok_or("Failed to create WebGL2 context")?;
+
+		// Workaround: https://stackoverflow.com/a/18934718/1403459
+		canvas.set_attribute("tabindex", "0")?;
+		canvas.focus()?;
+
+		context.enable(WebGl2RenderingContext::DEPTH_TEST);
+		context.depth_func(WebGl2RenderingContext::LESS);
+
+		let viewport = Viewport::new(canvas.clone(), context.clone());
+		let (animation_body, state) = get_animation_loop(canvas, context)?;
+		run_animation_loop(viewport, animation_body);
+
+		Ok(Self { state })
+	}
+
+	/// Shows `year` of the data exported to `images/{name}`. The current data
+	/// stays up until it's loaded, and is chosen again if it fails to load.
+	pub fn set_dataset(&self, name: String, year: u32) {
+		self.state.dataset.replace(Dataset { name, year });
+	}
+
+	/// How many time steps the loaded data has, one per month. 0 until it's
+	/// loaded.
+	pub fn time_step_count(&self) -> usize { self.state.timeline.borrow().step_count() }
+
+	pub fn time_step(&self) -> usize { self.state.timeline.borrow().current_step() }
+
+	/// Shows `time_step`, counting months from January of the dataset's year
+	pub fn set_time_step(&self, time_step: usize) -> Result<(), JsValue> {
+		Ok(self.state.timeline.borrow_mut().seek(time_step)?)
+	}
+
+	/// Shows a year and month, from 0 for January
+	pub fn seek_to_date(&self, year: u32, month: usize) -> Result<(), JsValue> {
+		Ok(self.state.timeline.borrow_mut().seek_to_date(year, month)?)
+	}
+
+	/// Moves `steps` forwards or backwards, wrapping around
+	pub fn step(&self, steps: i32) { self.state.timeline.borrow_mut().step(steps); }
+
+	pub fn play(&self) { self.state.timeline.borrow_mut().play(); }
+
+	pub fn pause(&self) { self.state.timeline.borrow_mut().pause(); }
+
+	pub fn set_speed(&self, steps_per_second: f32) -> Result<(), JsValue> {
+		Ok(self.state.timeline.borrow_mut().set_speed(steps_per_second)?)
+	}
+
+	/// Keeps playback between two time steps, inclusive
+	pub fn set_loop_range(&self, first: usize, last: usize) -> Result<(), JsValue> {
+		Ok(self.state.timeline.borrow_mut().set_range(first, last)?)
+	}
+
+	/// Plays over every time step again
+	pub fn clear_loop_range(&self) { self.state.timeline.borrow_mut().clear_range(); }
+
+	/// Whether playback starts over at the end of the range, rather than
+	/// stopping. On by default.
+	pub fn set_looping(&self, looping: bool) {
+		self.state.timeline.borrow_mut().set_looping(looping);
+	}
+
+	/// Colors the data with a color scale, written as JSON like a dataset's
+	/// `color_scale.json`, rather than the dataset's own
+	pub fn set_color_scale(&self, color_scale: &str) -> Result<(), JsValue> {
+		let color_scale: ColorScale =
+			serde_json::from_str(color_scale).map_err(|e| e.to_string())?;
+		color_scale.color_map.validate()?;
+		self.state.color_scale.replace(Some(color_scale));
+		Ok(())
+	}
+
+	/// Goes back to the dataset's own color scale
+	pub fn reset_color_scale(&self) { self.state.color_scale.replace(None); }
+
+	/// Recompiles the shaders from sources fetched from under `base_url`, in
+	/// dev builds, e.g. `"../src/"` when the crate's directory is served. A
+	/// shader that fails keeps drawing as it was, and its errors are sent as a
+	/// `ShaderError` event.
+	#[cfg(debug_assertions)]
+	pub fn reload_shaders(&self, base_url: String) {
+		self.state.shader_reload.replace(Some(base_url));
+	}
+
+	/// Turns the camera to look down at a latitude and longitude, in degrees,
+	/// from the same distance
+	pub fn set_camera_target(&self, latitude: f32, longitude: f32) {
+		let latitude = latitude.clamp(-MAX_TARGET_LATITUDE, MAX_TARGET_LATITUDE);
+
+		let mut camera = self.state.camera.borrow_mut();
+		let distance = camera.position().magnitude();
+		camera.set_position(latitude_longitude_to_point(latitude, longitude) * distance);
+		camera.set_target(nglm::Vec3::zeros());
+	}
+
+	pub fn is_layer_visible(&self, layer: Layer) -> bool {
+		self.state.layers.borrow().is_visible(layer)
+	}
+
+	pub fn set_layer_visible(&self, layer: Layer, visible: bool) {
+		self.state.layers.borrow_mut().set_visible(layer, visible);
+	}
+
+	/// How strongly `layer` shows over the layers beneath it, from 0 to 1
+	pub fn set_layer_opacity(&self, layer: Layer, opacity: f32) -> Result<(), JsValue> {
+		Ok(self.state.layers.borrow_mut().set_opacity(layer, opacity)?)
+	}
+
+	pub fn set_layer_blend_mode(&self, layer: Layer, blend_mode: BlendMode) {
+		self.state.layers.borrow_mut().set_blend_mode(layer, blend_mode);
+	}
+
+	/// Where `layer` is drawn among the others, from 0 at the bottom
+	pub fn layer_position(&self, layer: Layer) -> usize {
+		self.state.layers.borrow().position(layer)
+	}
+
+	/// Moves `layer` to `position`, from 0 at the bottom
+	pub fn set_layer_position(&self, layer: Layer, position: usize) -> Result<(), JsValue> {
+		Ok(self.state.layers.borrow_mut().set_position(layer, position)?)
+	}
+
+	/// Calls `callback` with a `CustomEvent` each time `event` happens. See
+	/// `ViewerEvent` for what's in each event's `detail`.
+	pub fn on(&self, event: ViewerEvent, callback: &Function) -> Result<(), JsValue> {
+		self.state.events.canvas().add_event_listener_with_callback(event.event_type(), callback)
+	}
+
+	/// Stops calling a `callback` passed to `on`
+	pub fn off(&self, event: ViewerEvent, callback: &Function) -> Result<(), JsValue> {
+		self.state.events.canvas().remove_event_listener_with_callback(event.event_type(), callback)
+	}
+}
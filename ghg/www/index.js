@@ -1,8 +1,24 @@
 // NOTE TO DEVELOPER: If this import isn't found, chance are your build didn't output to the correct directory.
 // Either add the flag `--out-dir ghg/www/wasm` to wasm-pack, or manually copy the wasm and js files to /www/wasm/
-import init
+import init, {GhgViewer, ViewerEvent}
     from './wasm/ghg.js'
+import {hide_probe, remove_overlay, show_legend, show_probe, show_shader_error}
+    from './overlay.js'
 
 init().then(wasm => {
     window.WASM = wasm;
+
+    const canvas = document.getElementById('render_canvas');
+    const viewer = new GhgViewer(canvas);
+    viewer.on(ViewerEvent.Loaded, event => {
+        if (event.detail.part === 'planet') {
+            remove_overlay();
+        } else if (event.detail.error) {
+            console.error(`Failed to load ${event.detail.dataset} ${event.detail.year}:`, event.detail.error);
+        }
+    });
+    viewer.on(ViewerEvent.Probe, event => event.detail ? show_probe(event.detail, canvas) : hide_probe());
+    viewer.on(ViewerEvent.Legend, event => show_legend(event.detail));
+    viewer.on(ViewerEvent.ShaderError, event => show_shader_error(event.detail));
+    window.viewer = viewer;
 });
@@ -6,3 +6,59 @@ export function remove_overlay() {
     overlay.style.opacity = '0';
     setTimeout(() => overlay.remove(), fadeOutMs);
 }
+
+const PROBE_OFFSET_PX = 16;
+
+// noinspection JSUnusedGlobalSymbols
+// Takes the `detail` of a viewer's probe events, and the canvas it's drawn on
+export function show_probe({x, y, coordinates, value, date, country}, canvas) {
+    let probe = document.getElementById('probe_overlay');
+    let canvasRect = canvas.getBoundingClientRect();
+    probe.style.left = `${canvasRect.left + x + PROBE_OFFSET_PX}px`;
+    probe.style.top = `${canvasRect.top + y + PROBE_OFFSET_PX}px`;
+
+    let fields = {coordinates, value, date, country};
+    for (let [name, text] of Object.entries(fields)) {
+        let span = probe.querySelector(`.probe_${name}`);
+        span.textContent = text;
+        span.hidden = text === '';
+    }
+
+    probe.hidden = false;
+}
+
+// noinspection JSUnusedGlobalSymbols
+export function hide_probe() {
+    document.getElementById('probe_overlay').hidden = true;
+}
+
+// noinspection JSUnusedGlobalSymbols
+// Takes the `detail` of a viewer's legend events, or null to hide the legend
+export function show_legend(legend) {
+    let overlay = document.getElementById('legend_overlay');
+    if (!legend) {
+        overlay.hidden = true;
+        return;
+    }
+
+    overlay.querySelector('.legend_bar').style.background = legend.gradient;
+    overlay.querySelector('.legend_units').textContent = legend.units;
+
+    let ticks = overlay.querySelector('.legend_ticks');
+    ticks.replaceChildren(...legend.ticks.map(({label, position}) => {
+        let tick = document.createElement('span');
+        tick.textContent = label;
+        tick.style.left = `${position * 100}%`;
+        return tick;
+    }));
+
+    overlay.hidden = false;
+}
+
+// noinspection JSUnusedGlobalSymbols
+// Takes the `detail` of a viewer's shader error events, or null once the shaders reload
+export function show_shader_error(error) {
+    let overlay = document.getElementById('shader_error_overlay');
+    overlay.textContent = error ? error.log : '';
+    overlay.hidden = !error;
+}